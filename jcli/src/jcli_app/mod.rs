@@ -5,6 +5,7 @@ mod certificate;
 mod debug;
 mod key;
 mod rest;
+mod secret;
 mod transaction;
 mod utils;
 
@@ -17,6 +18,8 @@ use structopt::StructOpt;
 pub enum JCli {
     /// Key Generation
     Key(key::Key),
+    /// Encryption of the node secret files
+    Secret(secret::Secret),
     /// Address tooling and helper
     Address(address::Address),
     /// Block tooling and helper
@@ -39,6 +42,7 @@ impl JCli {
     pub fn exec(self) -> Result<(), Box<dyn Error>> {
         match self {
             JCli::Key(key) => key.exec()?,
+            JCli::Secret(secret) => secret.exec()?,
             JCli::Address(address) => address.exec()?,
             JCli::Genesis(genesis) => genesis.exec()?,
             JCli::Rest(rest) => rest.exec()?,
@@ -24,6 +24,8 @@ custom_error! {pub Error
     InputSerializationFailed { source: serde_json::Error, filler: CustomErrorFiller } = "failed to serialize input",
     InputHexMalformed { source: FromHexError } = "input hex encoding is not valid",
     HttpError { source: reqwest::Error } = "HTTP query error",
+    FragmentRejected { fragment_id: String, reason: String } = "fragment {fragment_id} rejected by the node: {reason}",
+    FragmentRefused { fragment_id: String, reason: String } = "fragment {fragment_id} refused by the node: {reason}",
 }
 
 impl From<ReadYamlError> for Error {
@@ -36,6 +36,35 @@ pub enum Message {
         #[structopt(flatten)]
         output_format: OutputFormat,
     },
+
+    /// get the messages pending in the node's message pool, in the order
+    /// they were received, along with the totals and the limits of the pool
+    Pending {
+        #[structopt(flatten)]
+        addr: HostAddr,
+        #[structopt(flatten)]
+        debug: DebugFlag,
+        #[structopt(flatten)]
+        output_format: OutputFormat,
+        /// number of messages to skip
+        #[structopt(long)]
+        offset: Option<usize>,
+        /// maximum number of messages to get, all the messages from the
+        /// offset if not provided
+        #[structopt(long)]
+        limit: Option<usize>,
+    },
+
+    /// get the peers and the clients currently banned from sending
+    /// messages to the node
+    Bans {
+        #[structopt(flatten)]
+        addr: HostAddr,
+        #[structopt(flatten)]
+        debug: DebugFlag,
+        #[structopt(flatten)]
+        output_format: OutputFormat,
+    },
 }
 
 impl Message {
@@ -47,6 +76,18 @@ impl Message {
                 debug,
                 output_format,
             } => get_logs(addr, debug, output_format),
+            Message::Pending {
+                addr,
+                debug,
+                output_format,
+                offset,
+                limit,
+            } => get_pending(addr, debug, output_format, offset, limit),
+            Message::Bans {
+                addr,
+                debug,
+                output_format,
+            } => get_bans(addr, debug, output_format),
         }
     }
 }
@@ -62,6 +103,38 @@ fn get_logs(addr: HostAddr, debug: DebugFlag, output_format: OutputFormat) -> Re
     Ok(())
 }
 
+fn get_pending(
+    addr: HostAddr,
+    debug: DebugFlag,
+    output_format: OutputFormat,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<(), Error> {
+    let url = addr
+        .with_segments(&["v0", "fragment", "pending"])?
+        .into_url();
+    let builder = reqwest::Client::new()
+        .get(url)
+        .query(&[("offset", offset), ("limit", limit)]);
+    let response = RestApiSender::new(builder, &debug).send()?;
+    response.ok_response()?;
+    let pending = response.body().json_value()?;
+    let formatted = output_format.format_json(pending)?;
+    println!("{}", formatted);
+    Ok(())
+}
+
+fn get_bans(addr: HostAddr, debug: DebugFlag, output_format: OutputFormat) -> Result<(), Error> {
+    let url = addr.with_segments(&["v0", "fragment", "bans"])?.into_url();
+    let builder = reqwest::Client::new().get(url);
+    let response = RestApiSender::new(builder, &debug).send()?;
+    response.ok_response()?;
+    let bans = response.body().json_value()?;
+    let formatted = output_format.format_json(bans)?;
+    println!("{}", formatted);
+    Ok(())
+}
+
 fn post_message(file: Option<PathBuf>, addr: HostAddr, debug: DebugFlag) -> Result<(), Error> {
     let msg_hex = io::read_line(&file)?;
     let msg_bin = hex::decode(&msg_hex)?;
@@ -76,6 +149,21 @@ fn post_message(file: Option<PathBuf>, addr: HostAddr, debug: DebugFlag) -> Resu
     let response = RestApiSender::new(builder, &debug)
         .with_binary_body(msg_bin)
         .send()?;
+    match response.response().status() {
+        reqwest::StatusCode::BAD_REQUEST => {
+            return Err(Error::FragmentRejected {
+                fragment_id: fragment.id().to_string(),
+                reason: response.body().text().as_ref().to_owned(),
+            })
+        }
+        reqwest::StatusCode::FORBIDDEN | reqwest::StatusCode::TOO_MANY_REQUESTS => {
+            return Err(Error::FragmentRefused {
+                fragment_id: fragment.id().to_string(),
+                reason: response.body().text().as_ref().to_owned(),
+            })
+        }
+        _ => (),
+    }
     response.ok_response()?;
     println!("{}", fragment.id());
     Ok(())
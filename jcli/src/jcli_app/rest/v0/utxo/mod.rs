@@ -1,5 +1,5 @@
 use jcli_app::rest::Error;
-use jcli_app::utils::{DebugFlag, HostAddr, OutputFormat, RestApiSender};
+use jcli_app::utils::{DebugFlag, HostAddr, OutputFormat, RestApiSender, StateQuery};
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
@@ -13,6 +13,8 @@ pub enum Utxo {
         debug: DebugFlag,
         #[structopt(flatten)]
         output_format: OutputFormat,
+        #[structopt(flatten)]
+        state_query: StateQuery,
     },
 }
 
@@ -22,9 +24,12 @@ impl Utxo {
             addr,
             debug,
             output_format,
+            state_query,
         } = self;
         let url = addr.with_segments(&["v0", "utxo"])?.into_url();
-        let builder = reqwest::Client::new().get(url);
+        let builder = reqwest::Client::new()
+            .get(url)
+            .query(&state_query.query_params());
         let response = RestApiSender::new(builder, &debug).send()?;
         response.ok_response()?;
         let status = response.body().json_value()?;
@@ -0,0 +1,220 @@
+use jcli_app::utils::io;
+use jormungandr_lib::crypto::encryption;
+use rand::{rngs::EntropyRng, SeedableRng};
+use rand_chacha::ChaChaRng;
+use std::{
+    env,
+    io::{Read, Write},
+    path::PathBuf,
+};
+use structopt::StructOpt;
+
+custom_error! { pub Error
+    Io { source: std::io::Error } = "I/O error",
+    Rand { source: rand::Error } = "error while using random source",
+    InvalidInput { source: std::io::Error, path: PathBuf }
+        = @{{ let _ = source; format_args!("invalid input file path '{}'", path.display()) }},
+    InvalidOutput { source: std::io::Error, path: PathBuf }
+        = @{{ let _ = source; format_args!("invalid output file path '{}'", path.display()) }},
+    Encryption { source: encryption::Error } = "cannot decrypt the secret file",
+    AlreadyEncrypted = "the secret file is already encrypted",
+    PassphraseEnv { name: String } = "the environment variable '{name}' holding the passphrase is not set",
+    EmptyPassphrase = "the passphrase cannot be empty",
+    PassphraseMismatch = "the passphrases do not match",
+    TooFewIterations { min: u32 } = "the number of iterations of the key derivation must be at least {min}",
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "secret", rename_all = "kebab-case")]
+pub enum Secret {
+    /// encrypt a node secret file with a passphrase
+    Encrypt(Encrypt),
+    /// decrypt an encrypted node secret file
+    Decrypt(Decrypt),
+    /// encrypt an encrypted node secret file with a new passphrase
+    Rekey(Rekey),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct Encrypt {
+    #[structopt(flatten)]
+    input_file: InputFile,
+
+    /// name of the environment variable holding the passphrase, the
+    /// passphrase is prompted for if not provided
+    #[structopt(long = "passphrase-env", name = "PASSPHRASE_VAR")]
+    passphrase_env: Option<String>,
+
+    /// number of iterations of the key derivation, at least 10000, 100000
+    /// if not provided
+    #[structopt(long = "iterations")]
+    iterations: Option<u32>,
+
+    #[structopt(flatten)]
+    output_file: OutputFile,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct Decrypt {
+    #[structopt(flatten)]
+    input_file: InputFile,
+
+    /// name of the environment variable holding the passphrase, the
+    /// passphrase is prompted for if not provided
+    #[structopt(long = "passphrase-env", name = "PASSPHRASE_VAR")]
+    passphrase_env: Option<String>,
+
+    #[structopt(flatten)]
+    output_file: OutputFile,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct Rekey {
+    #[structopt(flatten)]
+    input_file: InputFile,
+
+    /// name of the environment variable holding the current passphrase,
+    /// the passphrase is prompted for if not provided
+    #[structopt(long = "passphrase-env", name = "PASSPHRASE_VAR")]
+    passphrase_env: Option<String>,
+
+    /// name of the environment variable holding the new passphrase, the
+    /// passphrase is prompted for if not provided
+    #[structopt(long = "new-passphrase-env", name = "NEW_PASSPHRASE_VAR")]
+    new_passphrase_env: Option<String>,
+
+    /// number of iterations of the key derivation of the new passphrase,
+    /// at least 10000, 100000 if not provided
+    #[structopt(long = "iterations")]
+    iterations: Option<u32>,
+
+    #[structopt(flatten)]
+    output_file: OutputFile,
+}
+
+#[derive(StructOpt, Debug)]
+struct InputFile {
+    /// the secret file to read, or the standard input if not provided
+    #[structopt(long = "input")]
+    input: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+struct OutputFile {
+    /// output the secret file to the given file or to stdout if not provided
+    #[structopt(name = "OUTPUT_FILE")]
+    output: Option<PathBuf>,
+}
+
+impl InputFile {
+    fn read(&self) -> Result<Vec<u8>, Error> {
+        let mut content = Vec::new();
+        io::open_file_read(&self.input)
+            .and_then(|mut input| input.read_to_end(&mut content))
+            .map_err(|source| Error::InvalidInput {
+                source,
+                path: io::path_to_path_buf(&self.input),
+            })?;
+        Ok(content)
+    }
+}
+
+impl OutputFile {
+    fn write(&self, content: &[u8]) -> Result<(), Error> {
+        let mut output =
+            io::open_file_write(&self.output).map_err(|source| Error::InvalidOutput {
+                source,
+                path: io::path_to_path_buf(&self.output),
+            })?;
+        output.write_all(content)?;
+        Ok(())
+    }
+}
+
+impl Secret {
+    pub fn exec(self) -> Result<(), Error> {
+        match self {
+            Secret::Encrypt(args) => args.exec(),
+            Secret::Decrypt(args) => args.exec(),
+            Secret::Rekey(args) => args.exec(),
+        }
+    }
+}
+
+impl Encrypt {
+    fn exec(self) -> Result<(), Error> {
+        let iterations = iterations(self.iterations)?;
+        let content = self.input_file.read()?;
+        if encryption::is_encrypted(&content) {
+            return Err(Error::AlreadyEncrypted);
+        }
+        let passphrase = new_passphrase(&self.passphrase_env, "passphrase")?;
+        let encrypted = encrypt(&passphrase, iterations, &content)?;
+        self.output_file.write(&encrypted)
+    }
+}
+
+impl Decrypt {
+    fn exec(self) -> Result<(), Error> {
+        let content = self.input_file.read()?;
+        let passphrase = read_passphrase(&self.passphrase_env, "passphrase: ")?;
+        let decrypted = encryption::decrypt(&passphrase, &content)?;
+        self.output_file.write(&decrypted)
+    }
+}
+
+impl Rekey {
+    fn exec(self) -> Result<(), Error> {
+        let iterations = iterations(self.iterations)?;
+        let content = self.input_file.read()?;
+        let passphrase = read_passphrase(&self.passphrase_env, "current passphrase: ")?;
+        let decrypted = encryption::decrypt(&passphrase, &content)?;
+        let new_passphrase = new_passphrase(&self.new_passphrase_env, "new passphrase")?;
+        let encrypted = encrypt(&new_passphrase, iterations, &decrypted)?;
+        self.output_file.write(&encrypted)
+    }
+}
+
+/// the number of iterations of the key derivation, checked before the
+/// passphrase is asked for
+fn iterations(iterations: Option<u32>) -> Result<u32, Error> {
+    let iterations = iterations.unwrap_or(encryption::DEFAULT_ITERATIONS);
+    if iterations < encryption::MIN_ITERATIONS {
+        return Err(Error::TooFewIterations {
+            min: encryption::MIN_ITERATIONS,
+        });
+    }
+    Ok(iterations)
+}
+
+fn encrypt(passphrase: &[u8], iterations: u32, content: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut rng = ChaChaRng::from_rng(EntropyRng::new())?;
+    let encrypted = encryption::encrypt_with_iterations(&mut rng, passphrase, iterations, content)?;
+    Ok(encrypted)
+}
+
+/// read the passphrase from the given environment variable, or prompt
+/// for it on the terminal
+fn read_passphrase(env_var: &Option<String>, prompt: &str) -> Result<Vec<u8>, Error> {
+    match env_var {
+        Some(name) => env::var(name)
+            .map(String::into_bytes)
+            .map_err(|_| Error::PassphraseEnv { name: name.clone() }),
+        None => Ok(rpassword::read_password_from_tty(Some(prompt))?.into_bytes()),
+    }
+}
+
+/// read a new passphrase, it is asked twice when prompted for
+fn new_passphrase(env_var: &Option<String>, name: &str) -> Result<Vec<u8>, Error> {
+    let passphrase = read_passphrase(env_var, &format!("{}: ", name))?;
+    if passphrase.is_empty() {
+        return Err(Error::EmptyPassphrase);
+    }
+    if env_var.is_none() {
+        let confirmation = read_passphrase(env_var, &format!("confirm the {}: ", name))?;
+        if confirmation != passphrase {
+            return Err(Error::PassphraseMismatch);
+        }
+    }
+    Ok(passphrase)
+}
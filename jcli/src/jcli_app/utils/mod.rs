@@ -1,5 +1,6 @@
 mod account_id;
 mod debug_flag;
+mod state_query;
 
 pub mod error;
 pub mod host_addr;
@@ -16,6 +17,7 @@ pub use self::host_addr::HostAddr;
 pub use self::open_api_verifier::OpenApiVerifier;
 pub use self::output_format::OutputFormat;
 pub use self::rest_api::{RestApiResponse, RestApiResponseBody, RestApiSender};
+pub use self::state_query::StateQuery;
 use bech32::Bech32;
 use structopt::StructOpt;
 
@@ -0,0 +1,26 @@
+use structopt::StructOpt;
+
+/// select the block whose state is queried, the tip of the
+/// blockchain is queried by default
+#[derive(StructOpt)]
+pub struct StateQuery {
+    /// hex-encoded ID of the block whose state is queried
+    #[structopt(long)]
+    block: Option<String>,
+    /// chain length of the block of the main branch whose state is queried
+    #[structopt(long)]
+    chain_length: Option<u32>,
+}
+
+impl StateQuery {
+    pub fn query_params(&self) -> Vec<(&'static str, String)> {
+        let mut params = Vec::new();
+        if let Some(block) = &self.block {
+            params.push(("block", block.clone()));
+        }
+        if let Some(chain_length) = self.chain_length {
+            params.push(("chain_length", chain_length.to_string()));
+        }
+        params
+    }
+}
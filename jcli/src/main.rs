@@ -11,6 +11,7 @@ extern crate openapiv3;
 extern crate rand;
 extern crate rand_chacha;
 extern crate reqwest;
+extern crate rpassword;
 extern crate serde;
 extern crate serde_json;
 extern crate serde_yaml;
@@ -295,9 +295,19 @@ pub fn assert_transaction_in_block(transaction_message: &str, host: &str) -> Has
 }
 
 pub fn assert_transaction_rejected(transaction_message: &str, host: &str, expected_reason: &str) {
-    let fragment_id = assert_post_transaction(&transaction_message, &host);
-    wait_until_transaction_processed(fragment_id, &host);
-    assert_transaction_log_shows_rejected(fragment_id, &host, &expected_reason);
+    process_assert::assert_process_failed_and_contains_message(
+        jcli_commands::get_post_transaction_command(&transaction_message, &host),
+        expected_reason,
+    );
+    let fragments = assert_get_rest_message_log(&host);
+    assert!(
+        fragments.iter().any(|x| match x.status() {
+            FragmentStatus::Rejected { reason } => reason.contains(&expected_reason),
+            _ => false,
+        }),
+        "cannot find the rejected fragment in rest message log, output: {:?}",
+        &fragments
+    );
 }
 
 pub fn wait_until_transaction_processed(fragment_id: Hash, host: &str) {
@@ -0,0 +1,241 @@
+//! encryption of the secret files with a passphrase
+//!
+//! The key is derived from the passphrase with PBKDF2-HMAC-SHA512 and
+//! a random salt, the content is encrypted and authenticated with
+//! ChaCha20-Poly1305. The encrypted file is made of:
+//!
+//! | field      | size (bytes) |                                   |
+//! |:-----------|-------------:|:----------------------------------|
+//! | magic      |            8 | `JORMSECR`                        |
+//! | version    |            4 | big endian, currently `1`         |
+//! | iterations |            4 | big endian, iterations of PBKDF2  |
+//! | salt       |           16 |                                   |
+//! | nonce      |           12 |                                   |
+//! | ciphertext |     variable |                                   |
+//! | tag        |           16 | authenticates the header as well  |
+
+use cryptoxide::{chacha20poly1305::ChaCha20Poly1305, hmac::Hmac, pbkdf2::pbkdf2, sha2::Sha512};
+use rand_core::{CryptoRng, RngCore};
+
+const MAGIC_SIZE: usize = 8;
+const MAGIC: &[u8; MAGIC_SIZE] = b"JORMSECR";
+const VERSION: u32 = 1;
+
+/// default number of iterations of PBKDF2 used to derive the key
+pub const DEFAULT_ITERATIONS: u32 = 100_000;
+/// minimum number of iterations of PBKDF2 used to derive the key, fewer
+/// iterations make the passphrase cheap to brute force
+pub const MIN_ITERATIONS: u32 = 10_000;
+
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+const KEY_SIZE: usize = 32;
+const TAG_SIZE: usize = 16;
+const HEADER_SIZE: usize = MAGIC_SIZE + 4 + 4 + SALT_SIZE + NONCE_SIZE;
+
+custom_error! {pub Error
+    InvalidMagic = "not an encrypted secret file",
+    UnsupportedVersion { version: u32 } = "unsupported version {version} of the encrypted secret file",
+    Truncated = "the encrypted secret file is truncated",
+    InvalidPassphrase = "invalid passphrase, or the encrypted secret file has been tampered with",
+    TooFewIterations { iterations: u32, min: u32 } = "{iterations} iterations of the key derivation are too few, the minimum is {min}",
+}
+
+/// check whether the given content is an encrypted secret file
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// encrypt the given content with a key derived from the passphrase
+pub fn encrypt<R>(rng: &mut R, passphrase: &[u8], data: &[u8]) -> Vec<u8>
+where
+    R: RngCore + CryptoRng,
+{
+    seal(rng, passphrase, DEFAULT_ITERATIONS, data)
+}
+
+/// encrypt the given content with a key derived from the passphrase in
+/// the given number of iterations, at least `MIN_ITERATIONS`
+pub fn encrypt_with_iterations<R>(
+    rng: &mut R,
+    passphrase: &[u8],
+    iterations: u32,
+    data: &[u8],
+) -> Result<Vec<u8>, Error>
+where
+    R: RngCore + CryptoRng,
+{
+    check_iterations(iterations)?;
+    Ok(seal(rng, passphrase, iterations, data))
+}
+
+/// check the number of iterations of the key derivation is not below
+/// `MIN_ITERATIONS`
+pub fn check_iterations(iterations: u32) -> Result<(), Error> {
+    if iterations < MIN_ITERATIONS {
+        Err(Error::TooFewIterations {
+            iterations,
+            min: MIN_ITERATIONS,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+fn seal<R>(rng: &mut R, passphrase: &[u8], iterations: u32, data: &[u8]) -> Vec<u8>
+where
+    R: RngCore + CryptoRng,
+{
+    let mut salt = [0; SALT_SIZE];
+    let mut nonce = [0; NONCE_SIZE];
+    rng.fill_bytes(&mut salt);
+    rng.fill_bytes(&mut nonce);
+
+    let mut encrypted = Vec::with_capacity(HEADER_SIZE + data.len() + TAG_SIZE);
+    encrypted.extend_from_slice(MAGIC);
+    encrypted.extend_from_slice(&VERSION.to_be_bytes());
+    encrypted.extend_from_slice(&iterations.to_be_bytes());
+    encrypted.extend_from_slice(&salt);
+    encrypted.extend_from_slice(&nonce);
+
+    let key = derive_key(passphrase, &salt, iterations);
+    let mut ciphertext = vec![0; data.len()];
+    let mut tag = [0; TAG_SIZE];
+    ChaCha20Poly1305::new(&key, &nonce, &encrypted).encrypt(data, &mut ciphertext, &mut tag);
+
+    encrypted.extend_from_slice(&ciphertext);
+    encrypted.extend_from_slice(&tag);
+    encrypted
+}
+
+/// decrypt the given encrypted secret file with the passphrase
+pub fn decrypt(passphrase: &[u8], data: &[u8]) -> Result<Vec<u8>, Error> {
+    if !is_encrypted(data) {
+        return Err(Error::InvalidMagic);
+    }
+    if data.len() < HEADER_SIZE + TAG_SIZE {
+        return Err(Error::Truncated);
+    }
+    let (header, rest) = data.split_at(HEADER_SIZE);
+    let (ciphertext, tag) = rest.split_at(rest.len() - TAG_SIZE);
+
+    let version = read_u32(&header[MAGIC_SIZE..]);
+    if version != VERSION {
+        return Err(Error::UnsupportedVersion { version });
+    }
+    let iterations = read_u32(&header[MAGIC_SIZE + 4..]);
+    check_iterations(iterations)?;
+    let salt = &header[MAGIC_SIZE + 8..MAGIC_SIZE + 8 + SALT_SIZE];
+    let nonce = &header[HEADER_SIZE - NONCE_SIZE..];
+
+    let key = derive_key(passphrase, salt, iterations);
+    let mut plaintext = vec![0; ciphertext.len()];
+    if ChaCha20Poly1305::new(&key, nonce, header).decrypt(ciphertext, &mut plaintext, tag) {
+        Ok(plaintext)
+    } else {
+        Err(Error::InvalidPassphrase)
+    }
+}
+
+fn derive_key(passphrase: &[u8], salt: &[u8], iterations: u32) -> [u8; KEY_SIZE] {
+    let mut key = [0; KEY_SIZE];
+    let mut mac = Hmac::new(Sha512::new(), passphrase);
+    pbkdf2(&mut mac, salt, iterations, &mut key);
+    key
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    let mut buf = [0; 4];
+    buf.copy_from_slice(&bytes[..4]);
+    u32::from_be_bytes(buf)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand_chacha::ChaChaRng;
+    use rand_core::SeedableRng;
+
+    // the fewest iterations allowed so the tests run quickly
+    const ITERATIONS: u32 = MIN_ITERATIONS;
+    const SECRET: &[u8] = b"bft:\n  signing_key: ed25519_sk1...\n";
+
+    #[test]
+    fn decrypt_with_the_passphrase() {
+        let mut rng = ChaChaRng::from_seed([0; 32]);
+        let encrypted =
+            encrypt_with_iterations(&mut rng, b"passphrase", ITERATIONS, SECRET).unwrap();
+
+        assert!(is_encrypted(&encrypted));
+        assert_eq!(decrypt(b"passphrase", &encrypted).unwrap(), SECRET);
+    }
+
+    #[test]
+    fn decrypt_with_another_passphrase() {
+        let mut rng = ChaChaRng::from_seed([0; 32]);
+        let encrypted =
+            encrypt_with_iterations(&mut rng, b"passphrase", ITERATIONS, SECRET).unwrap();
+
+        match decrypt(b"other passphrase", &encrypted) {
+            Err(Error::InvalidPassphrase) => {}
+            _ => panic!("decrypted with another passphrase"),
+        }
+    }
+
+    #[test]
+    fn decrypt_tampered_content() {
+        let mut rng = ChaChaRng::from_seed([0; 32]);
+        let mut encrypted =
+            encrypt_with_iterations(&mut rng, b"passphrase", ITERATIONS, SECRET).unwrap();
+        encrypted[HEADER_SIZE] ^= 1;
+
+        match decrypt(b"passphrase", &encrypted) {
+            Err(Error::InvalidPassphrase) => {}
+            _ => panic!("decrypted a tampered file"),
+        }
+    }
+
+    #[test]
+    fn encrypt_with_too_few_iterations() {
+        let mut rng = ChaChaRng::from_seed([0; 32]);
+        for iterations in &[0, MIN_ITERATIONS - 1] {
+            match encrypt_with_iterations(&mut rng, b"passphrase", *iterations, SECRET) {
+                Err(Error::TooFewIterations { .. }) => {}
+                _ => panic!("encrypted with {} iterations", iterations),
+            }
+        }
+    }
+
+    #[test]
+    fn decrypt_with_too_few_iterations() {
+        let mut rng = ChaChaRng::from_seed([0; 32]);
+        let mut encrypted = seal(&mut rng, b"passphrase", MIN_ITERATIONS - 1, SECRET);
+        match decrypt(b"passphrase", &encrypted) {
+            Err(Error::TooFewIterations { .. }) => {}
+            _ => panic!("decrypted with too few iterations"),
+        }
+
+        encrypted[MAGIC_SIZE + 4..MAGIC_SIZE + 8].copy_from_slice(&0u32.to_be_bytes());
+        match decrypt(b"passphrase", &encrypted) {
+            Err(Error::TooFewIterations { .. }) => {}
+            _ => panic!("decrypted with no iterations"),
+        }
+
+        // the iterations are authenticated: raising them in the header
+        // does not make the file valid
+        encrypted[MAGIC_SIZE + 4..MAGIC_SIZE + 8].copy_from_slice(&MIN_ITERATIONS.to_be_bytes());
+        match decrypt(b"passphrase", &encrypted) {
+            Err(Error::InvalidPassphrase) => {}
+            _ => panic!("decrypted a file with altered iterations"),
+        }
+    }
+
+    #[test]
+    fn decrypt_plain_file() {
+        assert!(!is_encrypted(SECRET));
+        match decrypt(b"passphrase", SECRET) {
+            Err(Error::InvalidMagic) => {}
+            _ => panic!("decrypted a plain file"),
+        }
+    }
+}
@@ -24,8 +24,14 @@
 //! It provides the same interfaces as for the identifier in the
 //! `key` module but limited to Account only.
 //!
+//! # Encrypted secret files
+//!
+//! The secret files of the node can be encrypted with a passphrase,
+//! the `encryption` module provides the format of the encrypted files.
+//!
 
 pub mod account;
+pub mod encryption;
 pub mod hash;
 pub mod key;
 pub(crate) mod serde;
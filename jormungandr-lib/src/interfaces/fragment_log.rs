@@ -1,21 +1,31 @@
 use crate::{crypto::hash::Hash, interfaces::BlockDate, time::SystemTime};
 use chain_impl_mockchain::key;
 use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 
 /// identify the source of a fragment
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum FragmentOrigin {
     /// trace back the origin of a fragment to a given
     /// network node. This will allow tracking back the
     /// origins of the fragment and eventually blacklisting
     /// the senders from sending us more fragment (in case
     /// they are invalids or so)
-    ///
-    /// TODO: add the network identifier/IP Address
-    Network,
+    Network {
+        /// identifier of the node that delivered the fragment
+        node_id: String,
+        /// address of the node, if known
+        addr: Option<SocketAddr>,
+    },
     /// This marks the fragment is coming from the REST interface
     /// (a client wallet or another service).
-    Rest,
+    Rest {
+        /// address of the client, if known
+        addr: Option<SocketAddr>,
+    },
+    /// the fragment has been put back in the pool after its block
+    /// was rolled back, and its original log had already expired
+    Rollback,
 }
 
 /// status of the fragment within the blockchain or the pool
@@ -27,6 +37,9 @@ pub enum FragmentStatus {
     Rejected { reason: String },
     /// The fragment has been added in a block
     InABlock { date: BlockDate },
+    /// the fragment has been evicted from the full pool and won't be
+    /// added in a block
+    Evicted { reason: String },
 }
 
 /// the log associated to a given fragment
@@ -37,6 +50,10 @@ pub struct FragmentLog {
     received_at: SystemTime,
     last_updated_at: SystemTime,
     status: FragmentStatus,
+    /// the hash of the block the fragment was in before the block
+    /// was rolled back and the fragment put back in the pool
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reverted_from: Option<Hash>,
 }
 
 impl FragmentLog {
@@ -49,6 +66,7 @@ impl FragmentLog {
             received_at: SystemTime::now(),
             last_updated_at: SystemTime::now(),
             status: FragmentStatus::Pending,
+            reverted_from: None,
         }
     }
 
@@ -75,6 +93,15 @@ impl FragmentLog {
         }
     }
 
+    #[inline]
+    pub fn is_evicted(&self) -> bool {
+        if let FragmentStatus::Evicted { .. } = &self.status {
+            true
+        } else {
+            false
+        }
+    }
+
     /// set the new status
     #[inline]
     pub fn modify(&mut self, new_status: FragmentStatus) {
@@ -82,6 +109,14 @@ impl FragmentLog {
         self.last_updated_at = SystemTime::now();
     }
 
+    /// the fragment's block has been rolled back: the fragment is set
+    /// back to pending and the log records the block it was reverted from
+    #[inline]
+    pub fn revert(&mut self, block_hash: key::Hash) {
+        self.modify(FragmentStatus::Pending);
+        self.reverted_from = Some(block_hash.into());
+    }
+
     #[inline]
     pub fn fragment_id(&self) -> &Hash {
         &self.fragment_id
@@ -106,4 +141,9 @@ impl FragmentLog {
     pub fn status(&self) -> &FragmentStatus {
         &self.status
     }
+
+    #[inline]
+    pub fn reverted_from(&self) -> Option<&Hash> {
+        self.reverted_from.as_ref()
+    }
 }
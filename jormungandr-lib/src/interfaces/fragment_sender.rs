@@ -0,0 +1,80 @@
+use crate::{interfaces::FragmentOrigin, time::SystemTime};
+use serde::{Deserialize, Serialize};
+use std::{fmt, net::IpAddr, time};
+
+/// identify the sender of fragments, the fragment intake quotas and
+/// the bans apply per sender.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum FragmentSender {
+    /// a network node, identified by its node identifier
+    Node { node_id: String },
+    /// a client of the REST interface, identified by its IP address
+    Client { ip: IpAddr },
+}
+
+/// a sender temporarily banned from sending fragments to the node
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FragmentSenderBan {
+    sender: FragmentSender,
+    banned_at: SystemTime,
+    banned_until: SystemTime,
+    reason: String,
+}
+
+impl FragmentSender {
+    /// the sender of the fragments received from the given origin. The
+    /// fragments of unknown senders are not accounted for.
+    pub fn from_origin(origin: &FragmentOrigin) -> Option<Self> {
+        match origin {
+            FragmentOrigin::Network { node_id, .. } => Some(FragmentSender::Node {
+                node_id: node_id.clone(),
+            }),
+            FragmentOrigin::Rest { addr: Some(addr) } => {
+                Some(FragmentSender::Client { ip: addr.ip() })
+            }
+            FragmentOrigin::Rest { addr: None } | FragmentOrigin::Rollback => None,
+        }
+    }
+}
+
+impl fmt::Display for FragmentSender {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FragmentSender::Node { node_id } => write!(f, "node {}", node_id),
+            FragmentSender::Client { ip } => write!(f, "client {}", ip),
+        }
+    }
+}
+
+impl FragmentSenderBan {
+    /// ban the sender from now on, for the given duration
+    pub fn new(sender: FragmentSender, duration: time::Duration, reason: String) -> Self {
+        let now = time::SystemTime::now();
+        FragmentSenderBan {
+            sender,
+            banned_at: now.into(),
+            banned_until: (now + duration).into(),
+            reason,
+        }
+    }
+
+    #[inline]
+    pub fn sender(&self) -> &FragmentSender {
+        &self.sender
+    }
+
+    #[inline]
+    pub fn banned_at(&self) -> &SystemTime {
+        &self.banned_at
+    }
+
+    #[inline]
+    pub fn banned_until(&self) -> &SystemTime {
+        &self.banned_until
+    }
+
+    #[inline]
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
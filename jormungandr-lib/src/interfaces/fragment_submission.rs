@@ -0,0 +1,46 @@
+use crate::crypto::hash::Hash;
+use chain_impl_mockchain::key;
+use serde::{Deserialize, Serialize};
+
+/// outcome of the submission of a fragment to the node
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FragmentSubmission {
+    fragment_id: Hash,
+    status: FragmentSubmissionStatus,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FragmentSubmissionStatus {
+    /// the fragment is valid against the ledger of the tip and is in the
+    /// fragment pool
+    Accepted,
+    /// the fragment is not valid against the ledger of the tip
+    Rejected { reason: String },
+    /// the fragment has not been processed: its sender is banned or has
+    /// sent too many fragments
+    Refused { reason: String },
+}
+
+impl FragmentSubmission {
+    pub fn new(fragment_id: key::Hash, status: FragmentSubmissionStatus) -> Self {
+        FragmentSubmission {
+            fragment_id: fragment_id.into(),
+            status,
+        }
+    }
+
+    #[inline]
+    pub fn fragment_id(&self) -> &Hash {
+        &self.fragment_id
+    }
+
+    #[inline]
+    pub fn status(&self) -> &FragmentSubmissionStatus {
+        &self.status
+    }
+
+    #[inline]
+    pub fn is_accepted(&self) -> bool {
+        self.status == FragmentSubmissionStatus::Accepted
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{interfaces::BlockDate, time::SystemTime};
+use crate::{crypto::hash::Hash, interfaces::BlockDate, time::SystemTime};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
@@ -21,6 +21,26 @@ pub struct LeadershipLog {
     wake_at_time: Option<SystemTime>,
     finished_at_time: Option<SystemTime>,
     enclave_leader_id: EnclaveLeaderId,
+    #[serde(default)]
+    status: LeadershipLogStatus,
+}
+
+/// the outcome of a leadership event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LeadershipLogStatus {
+    /// the leadership event has not been processed yet
+    Pending,
+    /// the block has been created and sent to the blockchain task, it is
+    /// not on the main chain yet
+    Block { block: Hash },
+    /// the block is on the main chain
+    Adopted { block: Hash },
+    /// the main chain switched to a fork the block is not part of
+    Orphaned { block: Hash },
+    /// the block has not been created, it would not be accepted anyway
+    Skipped { reason: String },
+    /// creating the block failed
+    Failed { reason: String },
 }
 
 impl EnclaveLeaderId {
@@ -46,6 +66,7 @@ impl LeadershipLog {
             wake_at_time: None,
             finished_at_time: None,
             enclave_leader_id,
+            status: LeadershipLogStatus::Pending,
         }
     }
 
@@ -72,6 +93,9 @@ impl LeadershipLog {
     pub fn enclave_leader_id(&self) -> &EnclaveLeaderId {
         &self.enclave_leader_id
     }
+    pub fn status(&self) -> &LeadershipLogStatus {
+        &self.status
+    }
 
     /// make a leadership event as triggered.
     ///
@@ -101,6 +125,31 @@ impl LeadershipLog {
         debug_assert!(self.finished_at_time.is_none());
         self.finished_at_time = Some(SystemTime::now())
     }
+
+    /// set the outcome of the leadership event
+    pub fn set_status(&mut self, status: LeadershipLogStatus) {
+        self.status = status
+    }
+}
+
+impl LeadershipLogStatus {
+    /// the block created by the leadership event, if any
+    pub fn block(&self) -> Option<&Hash> {
+        match self {
+            LeadershipLogStatus::Block { block }
+            | LeadershipLogStatus::Adopted { block }
+            | LeadershipLogStatus::Orphaned { block } => Some(block),
+            LeadershipLogStatus::Pending
+            | LeadershipLogStatus::Skipped { .. }
+            | LeadershipLogStatus::Failed { .. } => None,
+        }
+    }
+}
+
+impl Default for LeadershipLogStatus {
+    fn default() -> Self {
+        LeadershipLogStatus::Pending
+    }
 }
 
 impl fmt::Display for EnclaveLeaderId {
@@ -4,9 +4,12 @@ mod block0_configuration;
 mod blockdate;
 mod certificate;
 mod fragment_log;
+mod fragment_sender;
+mod fragment_submission;
 mod leadership_log;
 mod linear_fee;
 mod old_address;
+mod pending_fragment;
 mod settings;
 mod transaction_output;
 mod transaction_witness;
@@ -21,9 +24,16 @@ pub use self::certificate::{
     Certificate, CertificateFromBech32Error, CertificateFromStrError, CertificateToBech32Error,
 };
 pub use self::fragment_log::{FragmentLog, FragmentOrigin, FragmentStatus};
-pub use self::leadership_log::{EnclaveLeaderId, LeadershipLog, LeadershipLogId};
+pub use self::fragment_sender::{FragmentSender, FragmentSenderBan};
+pub use self::fragment_submission::{FragmentSubmission, FragmentSubmissionStatus};
+pub use self::leadership_log::{
+    EnclaveLeaderId, LeadershipLog, LeadershipLogId, LeadershipLogStatus,
+};
 pub use self::linear_fee::LinearFeeDef;
 pub use self::old_address::OldAddress;
+pub use self::pending_fragment::{
+    DecodedPendingFragment, FragmentContent, PendingFragment, PendingFragments,
+};
 pub use self::settings::*;
 pub use self::transaction_output::TransactionOutput;
 pub use self::transaction_witness::TransactionWitness;
@@ -0,0 +1,191 @@
+use crate::{
+    crypto::hash::Hash,
+    interfaces::{Certificate, TransactionOutput, Value},
+    time::SystemTime,
+};
+use chain_impl_mockchain::{fragment::Fragment, transaction::Transaction, value};
+use serde::{Deserialize, Serialize};
+
+/// a fragment pending in the fragment pool of the node
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PendingFragment {
+    fragment_id: Hash,
+    size: usize,
+    fee: Value,
+    received_at: SystemTime,
+}
+
+/// the fragments pending in the fragment pool of the node, in the order
+/// they were received, along with the totals and the limits of the pool
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PendingFragments {
+    total_count: usize,
+    total_size: usize,
+    max_count: usize,
+    max_size: usize,
+    fragments: Vec<PendingFragment>,
+}
+
+/// a pending fragment along with its decoded content
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DecodedPendingFragment {
+    #[serde(flatten)]
+    pending: PendingFragment,
+    content: FragmentContent,
+}
+
+/// the decoded content of a fragment
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum FragmentContent {
+    Initial,
+    OldUtxoDeclaration,
+    Transaction {
+        input_value: Value,
+        outputs: Vec<TransactionOutput>,
+    },
+    Certificate {
+        input_value: Value,
+        outputs: Vec<TransactionOutput>,
+        certificate: Certificate,
+    },
+    /// the update proposals and votes
+    Other,
+}
+
+impl PendingFragment {
+    pub fn new(fragment_id: Hash, size: usize, fee: Value, received_at: SystemTime) -> Self {
+        PendingFragment {
+            fragment_id,
+            size,
+            fee,
+            received_at,
+        }
+    }
+
+    #[inline]
+    pub fn fragment_id(&self) -> &Hash {
+        &self.fragment_id
+    }
+
+    /// the size of the fragment, in bytes
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    #[inline]
+    pub fn fee(&self) -> &Value {
+        &self.fee
+    }
+
+    #[inline]
+    pub fn received_at(&self) -> &SystemTime {
+        &self.received_at
+    }
+}
+
+impl PendingFragments {
+    /// the `fragments` may be a range only of the fragments of the pool,
+    /// the totals account for all the fragments of the pool
+    pub fn new(
+        total_count: usize,
+        total_size: usize,
+        max_count: usize,
+        max_size: usize,
+        fragments: Vec<PendingFragment>,
+    ) -> Self {
+        PendingFragments {
+            total_count,
+            total_size,
+            max_count,
+            max_size,
+            fragments,
+        }
+    }
+
+    /// the number of fragments in the pool
+    #[inline]
+    pub fn total_count(&self) -> usize {
+        self.total_count
+    }
+
+    /// the cumulated size of the fragments of the pool, in bytes
+    #[inline]
+    pub fn total_size(&self) -> usize {
+        self.total_size
+    }
+
+    /// the maximum number of fragments in the pool
+    #[inline]
+    pub fn max_count(&self) -> usize {
+        self.max_count
+    }
+
+    /// the maximum cumulated size of the fragments of the pool, in bytes
+    #[inline]
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// the requested range of the fragments of the pool, in the order
+    /// they were received
+    #[inline]
+    pub fn fragments(&self) -> &[PendingFragment] {
+        &self.fragments
+    }
+}
+
+impl DecodedPendingFragment {
+    pub fn new(pending: PendingFragment, fragment: &Fragment) -> Self {
+        DecodedPendingFragment {
+            pending,
+            content: FragmentContent::from(fragment),
+        }
+    }
+
+    #[inline]
+    pub fn pending(&self) -> &PendingFragment {
+        &self.pending
+    }
+
+    #[inline]
+    pub fn content(&self) -> &FragmentContent {
+        &self.content
+    }
+}
+
+/* ---------------- Conversion --------------------------------------------- */
+
+impl<'a> From<&'a Fragment> for FragmentContent {
+    fn from(fragment: &'a Fragment) -> Self {
+        match fragment {
+            Fragment::Initial(_) => FragmentContent::Initial,
+            Fragment::OldUtxoDeclaration(_) => FragmentContent::OldUtxoDeclaration,
+            Fragment::Transaction(tx) => FragmentContent::Transaction {
+                input_value: input_value(&tx.transaction),
+                outputs: outputs(&tx.transaction),
+            },
+            Fragment::Certificate(tx) => FragmentContent::Certificate {
+                input_value: input_value(&tx.transaction),
+                outputs: outputs(&tx.transaction),
+                certificate: tx.transaction.extra.clone().into(),
+            },
+            _ => FragmentContent::Other,
+        }
+    }
+}
+
+fn input_value<Extra>(transaction: &Transaction<chain_addr::Address, Extra>) -> Value {
+    value::Value::sum(transaction.inputs.iter().map(|input| input.value))
+        .unwrap_or(value::Value::zero())
+        .into()
+}
+
+fn outputs<Extra>(transaction: &Transaction<chain_addr::Address, Extra>) -> Vec<TransactionOutput> {
+    transaction
+        .outputs
+        .iter()
+        .cloned()
+        .map(TransactionOutput::from)
+        .collect()
+}
@@ -10,7 +10,6 @@ pub use chain_impl_mockchain::{
     fragment::{ConfigParams, Fragment, FragmentId},
     leadership::{BftLeader, GenesisLeader, Leader, LeaderOutput, Leadership},
     ledger::{Ledger, LedgerParameters, LedgerStaticParameters},
-    multiverse::Multiverse,
     value::{Value, ValueError},
 };
 use std::time::{Duration, SystemTime};
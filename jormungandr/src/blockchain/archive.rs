@@ -0,0 +1,366 @@
+//! portable block archive
+//!
+//! A block archive is a plain file containing a contiguous range of blocks
+//! of the blockchain, from the oldest to the newest. It allows to seed a
+//! node with the blocks of another node without network access.
+//!
+//! The archive file is made of:
+//!
+//! * the magic `JORMBLKS` and the format version (u32, big endian);
+//! * the blocks, each prefixed by its length in bytes (u32, big endian),
+//!   at most `MAX_BLOCK_SIZE`.
+//!
+//! The same walk through the blocks is used to copy the blocks from a
+//! storage backend to another.
+//!
+//! Importing an archive goes through the same validation as the blocks
+//! received from the network: every block is pre-checked, post-checked
+//! and then applied and stored. The blocks already present in the
+//! storage are skipped.
+
+use crate::{
+    blockcfg::{Block, HeaderHash},
+    blockchain::{
+        chain::MAIN_BRANCH_TAG, process_new_ref, Blockchain, Branch, Error as BlockchainError,
+        ErrorKind as BlockchainErrorKind, PreCheckedHeader, Storage,
+    },
+    start_up::NodeStorage,
+};
+use chain_core::property::{Deserialize as _, Serialize as _};
+use chain_storage::error::Error as StorageError;
+use slog::Logger;
+use std::io::{self, Read, Write};
+use tokio::prelude::*;
+
+const ARCHIVE_MAGIC: &[u8; 8] = b"JORMBLKS";
+const ARCHIVE_VERSION: u32 = 1;
+/// bound of the size of a block in the archive, so a corrupted length
+/// does not make the node allocate an arbitrary amount of memory. It
+/// leaves room for a block0 holding many initial funds.
+const MAX_BLOCK_SIZE: usize = 64 * 1024 * 1024;
+
+custom_error! {pub Error
+    Io { source: io::Error } = "I/O error while accessing the block archive",
+    InvalidMagic = "the file is not a block archive",
+    UnsupportedVersion { version: u32 } = "unsupported block archive format version {version}",
+    Encoding { reason: String } = "cannot encode or decode a block of the archive: {reason}",
+    TooLarge { size: usize, max: usize } = "invalid block of {size} bytes in the archive, the maximum is {max} bytes",
+    Storage { source: StorageError } = "cannot access the blocks in the storage",
+    BlockNotFound { hash: HeaderHash } = "block {hash} is not in the storage",
+    NotAnAncestor { from: HeaderHash, to: HeaderHash } = "block {from} is not an ancestor of block {to}",
+    MissingParent { hash: HeaderHash } = "block {hash} is not connected to the blockchain",
+    Blockchain { source: BlockchainError } = "block of the archive is not valid",
+}
+
+/// write the blocks from `from` to `to` (both included) in the archive,
+/// `to` defaults to the `HEAD` of the storage.
+///
+/// returns the number of blocks written.
+pub fn export<W: Write>(
+    storage: &Storage,
+    from: HeaderHash,
+    to: Option<HeaderHash>,
+    writer: W,
+) -> Result<u64, Error> {
+    let mut writer = ArchiveWriter::new(writer)?;
+    for_each_block(storage, from, to, |block| writer.write(&block))?;
+    writer.finish()
+}
+
+/// copy the blocks from the block0 to the `HEAD` of the `source` storage
+/// in the `target` block store and set the `HEAD` of the `target`.
+///
+/// returns the number of blocks copied.
+pub fn copy_blocks(
+    source: &Storage,
+    block0_hash: HeaderHash,
+    target: &mut NodeStorage,
+) -> Result<u64, Error> {
+    let mut copied = 0;
+    let mut head = block0_hash;
+    for_each_block(source, block0_hash, None, |block| {
+        head = block.header.hash();
+        copied += 1;
+        target.put_block(&block).map_err(Error::from)
+    })?;
+    target.put_tag(MAIN_BRANCH_TAG, &head)?;
+    Ok(copied)
+}
+
+/// call `f` on every block from `from` to `to` (both included), `to`
+/// defaults to the `HEAD` of the storage.
+fn for_each_block<F>(
+    storage: &Storage,
+    from: HeaderHash,
+    to: Option<HeaderHash>,
+    mut f: F,
+) -> Result<(), Error>
+where
+    F: FnMut(Block) -> Result<(), Error>,
+{
+    let to = match to {
+        Some(to) => to,
+        None => storage
+            .get_tag(MAIN_BRANCH_TAG.to_owned())
+            .wait()?
+            .ok_or_else(|| {
+                BlockchainError::from(BlockchainErrorKind::NoTag(MAIN_BRANCH_TAG.to_owned()))
+            })?,
+    };
+
+    let first = storage
+        .get(from)
+        .wait()?
+        .ok_or(Error::BlockNotFound { hash: from })?;
+    f(first)?;
+
+    let blocks = storage
+        .stream_from_to(from, to)
+        .wait()?
+        .ok_or(Error::NotAnAncestor { from, to })?;
+    for block in blocks.wait() {
+        f(block?)?;
+    }
+    Ok(())
+}
+
+/// validate and apply the blocks of the archive on the blockchain, moving
+/// the `tip` as the blocks are applied.
+///
+/// returns the number of blocks applied, the blocks already in the
+/// storage are skipped.
+pub fn import<R: Read>(
+    mut blockchain: Blockchain,
+    tip: Branch,
+    reader: R,
+    logger: &Logger,
+) -> Result<u64, Error> {
+    let mut applied = 0;
+
+    for block in ArchiveReader::new(reader)? {
+        let block = block?;
+        let post_checked = match blockchain.pre_check_header(block.header.clone()).wait()? {
+            PreCheckedHeader::AlreadyPresent { .. } => continue,
+            PreCheckedHeader::MissingParent { header, .. } => {
+                return Err(Error::MissingParent {
+                    hash: header.hash(),
+                })
+            }
+            PreCheckedHeader::HeaderWithCache { header, parent_ref } => {
+                blockchain.post_check_header(header, parent_ref).wait()?
+            }
+        };
+        let new_ref = blockchain
+            .apply_and_store_block(post_checked, block)
+            .wait()?;
+        debug!(logger, "block imported"; "hash" => new_ref.hash().to_string());
+        process_new_ref(logger.clone(), blockchain.clone(), tip.clone(), new_ref).wait()?;
+        applied += 1;
+    }
+
+    Ok(applied)
+}
+
+struct ArchiveWriter<W> {
+    writer: W,
+    count: u64,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    fn new(mut writer: W) -> Result<Self, Error> {
+        writer.write_all(ARCHIVE_MAGIC)?;
+        writer.write_all(&ARCHIVE_VERSION.to_be_bytes())?;
+        Ok(ArchiveWriter { writer, count: 0 })
+    }
+
+    fn write(&mut self, block: &Block) -> Result<(), Error> {
+        let bytes = block.serialize_as_vec().map_err(|err| Error::Encoding {
+            reason: err.to_string(),
+        })?;
+        if bytes.len() > MAX_BLOCK_SIZE {
+            return Err(Error::TooLarge {
+                size: bytes.len(),
+                max: MAX_BLOCK_SIZE,
+            });
+        }
+        self.writer.write_all(&(bytes.len() as u32).to_be_bytes())?;
+        self.writer.write_all(&bytes)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<u64, Error> {
+        self.writer.flush()?;
+        Ok(self.count)
+    }
+}
+
+struct ArchiveReader<R> {
+    reader: R,
+}
+
+impl<R: Read> ArchiveReader<R> {
+    fn new(mut reader: R) -> Result<Self, Error> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != ARCHIVE_MAGIC {
+            return Err(Error::InvalidMagic);
+        }
+        let mut version = [0; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_be_bytes(version);
+        if version != ARCHIVE_VERSION {
+            return Err(Error::UnsupportedVersion { version });
+        }
+        Ok(ArchiveReader { reader })
+    }
+
+    /// read the length of the next block, `None` if the end of
+    /// the archive has been reached.
+    fn read_len(&mut self) -> Result<Option<usize>, Error> {
+        let mut len = [0; 4];
+        let mut read = 0;
+        while read < len.len() {
+            match self.reader.read(&mut len[read..])? {
+                0 if read == 0 => return Ok(None),
+                0 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                n => read += n,
+            }
+        }
+        Ok(Some(u32::from_be_bytes(len) as usize))
+    }
+
+    fn read_block(&mut self) -> Result<Option<Block>, Error> {
+        let len = match self.read_len()? {
+            None => return Ok(None),
+            Some(len) => len,
+        };
+        if len > MAX_BLOCK_SIZE {
+            return Err(Error::TooLarge {
+                size: len,
+                max: MAX_BLOCK_SIZE,
+            });
+        }
+        let mut bytes = vec![0; len];
+        self.reader.read_exact(&mut bytes)?;
+        Block::deserialize(bytes.as_slice())
+            .map(Some)
+            .map_err(|err| Error::Encoding {
+                reason: err.to_string(),
+            })
+    }
+}
+
+impl<R: Read> Iterator for ArchiveReader<R> {
+    type Item = Result<Block, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_block() {
+            Ok(None) => None,
+            Ok(Some(block)) => Some(Ok(block)),
+            Err(error) => Some(Err(error)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::test_utils::{block0, branch, storage};
+    use chain_storage::memory::MemoryBlockStore;
+
+    fn hashes<'a, I: IntoIterator<Item = &'a Block>>(blocks: I) -> Vec<HeaderHash> {
+        blocks
+            .into_iter()
+            .map(|block| block.header.hash())
+            .collect()
+    }
+
+    #[test]
+    fn archive_round_trip() {
+        let block0 = block0();
+        let blocks = branch(&block0, 3, 1);
+        let storage = storage(Some(&block0).into_iter().chain(&blocks));
+
+        let mut archive = Vec::new();
+        let written = export(
+            &storage,
+            block0.header.hash(),
+            Some(blocks[2].header.hash()),
+            &mut archive,
+        )
+        .unwrap();
+        assert_eq!(written, 4);
+
+        let read = ArchiveReader::new(archive.as_slice())
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            hashes(&read),
+            hashes(Some(&block0).into_iter().chain(&blocks))
+        );
+    }
+
+    #[test]
+    fn copy_blocks_up_to_the_head() {
+        let block0 = block0();
+        let blocks = branch(&block0, 3, 1);
+        let mut source = storage(Some(&block0).into_iter().chain(&blocks));
+        let head = blocks[2].header.hash();
+        source
+            .put_tag(MAIN_BRANCH_TAG.to_owned(), head)
+            .wait()
+            .unwrap();
+
+        let mut target: NodeStorage = Box::new(MemoryBlockStore::new());
+        let copied = copy_blocks(&source, block0.header.hash(), &mut target).unwrap();
+
+        assert_eq!(copied, 4);
+        assert_eq!(target.get_tag(MAIN_BRANCH_TAG).unwrap(), Some(head));
+        for hash in hashes(&blocks) {
+            assert!(target.block_exists(&hash).unwrap());
+        }
+    }
+
+    #[test]
+    fn archive_invalid_magic() {
+        match ArchiveReader::new(&b"JORMSNAP\0\0\0\x01"[..]) {
+            Err(Error::InvalidMagic) => {}
+            _ => panic!("read an archive with an invalid magic"),
+        }
+    }
+
+    #[test]
+    fn archive_block_too_large() {
+        let mut archive = ARCHIVE_MAGIC.to_vec();
+        archive.extend_from_slice(&ARCHIVE_VERSION.to_be_bytes());
+        archive.extend_from_slice(&u32::max_value().to_be_bytes());
+
+        match ArchiveReader::new(archive.as_slice()).unwrap().next() {
+            Some(Err(Error::TooLarge { .. })) => {}
+            _ => panic!("read a block larger than the maximum"),
+        }
+    }
+
+    #[test]
+    fn archive_truncated() {
+        let block0 = block0();
+        let storage = storage(Some(&block0));
+        let mut archive = Vec::new();
+        export(
+            &storage,
+            block0.header.hash(),
+            Some(block0.header.hash()),
+            &mut archive,
+        )
+        .unwrap();
+        archive.pop();
+
+        let mut reader = ArchiveReader::new(archive.as_slice()).unwrap();
+        match reader.next() {
+            Some(Err(Error::Io { .. })) => {}
+            _ => panic!("read a block of a truncated archive"),
+        }
+    }
+}
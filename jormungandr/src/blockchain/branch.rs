@@ -1,4 +1,7 @@
-use crate::blockchain::Ref;
+use crate::{
+    blockcfg::{ChainLength, HeaderHash},
+    blockchain::{Ref, MAX_ROLLBACK_DEPTH},
+};
 use tokio::{prelude::*, sync::lock::Lock};
 
 use std::convert::Infallible;
@@ -39,6 +42,71 @@ impl Branches {
         future::poll_fn(move || Ok(branches.inner.poll_lock()))
             .map(move |mut guard| guard.add(branch))
     }
+
+    /// find the branch whose tip is the parent of the `candidate` and move
+    /// it forward to the `candidate`. If there is no such branch, a new one
+    /// is created and added to the `Branches`.
+    ///
+    /// The future returns the branch now pointing to the `candidate`.
+    pub fn apply_or_create(
+        &mut self,
+        candidate: Ref,
+    ) -> impl Future<Item = Branch, Error = Infallible> {
+        let mut branches = self.clone();
+        let parent_hash = candidate.block_parent_hash().clone();
+
+        self.branch_with_tip(parent_hash)
+            .and_then(move |maybe_branch| match maybe_branch {
+                Some(mut branch) => {
+                    future::Either::A(branch.update_ref(candidate).map(move |_| branch))
+                }
+                None => {
+                    let branch = Branch::new(candidate);
+                    future::Either::B(branches.add(branch.clone()).map(move |()| branch))
+                }
+            })
+    }
+
+    /// remove the branches whose tip is more than `MAX_ROLLBACK_DEPTH`
+    /// blocks below the tip of the main branch. The node can no longer
+    /// switch to them, their states would be kept in memory forever.
+    pub fn prune(&mut self, main_tip: ChainLength) -> impl Future<Item = (), Error = Infallible> {
+        let mut inner = self.inner.clone();
+        let main_tip = u64::from(u32::from(main_tip));
+
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |mut guard| {
+            let branches = std::mem::replace(&mut guard.branches, Vec::new());
+            stream::iter_ok(branches)
+                .and_then(|branch| branch.get_ref().map(move |reference| (branch, reference)))
+                .filter(move |(_, reference)| {
+                    let length = u64::from(u32::from(reference.chain_length()));
+                    length + MAX_ROLLBACK_DEPTH >= main_tip
+                })
+                .map(|(branch, _)| branch)
+                .collect()
+                .map(move |branches| guard.branches = branches)
+        })
+    }
+
+    /// find the branch that currently points to the block of the given hash
+    fn branch_with_tip(
+        &self,
+        hash: HeaderHash,
+    ) -> impl Future<Item = Option<Branch>, Error = Infallible> {
+        let mut inner = self.inner.clone();
+
+        future::poll_fn(move || Ok(inner.poll_lock()))
+            .map(|guard| guard.branches.clone())
+            .and_then(move |branches| {
+                stream::iter_ok(branches)
+                    .and_then(|branch| branch.get_ref().map(move |reference| (branch, reference)))
+                    .filter(move |(_, reference)| reference.hash() == hash)
+                    .map(|(branch, _)| branch)
+                    .into_future()
+                    .map(|(branch, _)| branch)
+                    .map_err(|(error, _)| error)
+            })
+    }
 }
 
 impl BranchesData {
@@ -86,3 +154,56 @@ impl BranchData {
         &self.reference
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::test_utils::{block0, branch, child, reference};
+
+    fn tips(branches: &Branches) -> Vec<HeaderHash> {
+        let mut inner = branches.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock()))
+            .map(|guard| guard.branches.clone())
+            .and_then(|branches| {
+                stream::iter_ok(branches)
+                    .and_then(|branch| branch.get_ref())
+                    .collect()
+            })
+            .wait()
+            .unwrap()
+            .iter()
+            .map(Ref::hash)
+            .collect()
+    }
+
+    #[test]
+    fn prune_the_branches_too_far_behind_the_main_tip() {
+        let block0 = block0();
+        let stale = child(&block0, 1);
+        let recent = branch(&block0, 3, 2);
+        let mut branches = Branches::new();
+        branches.add(Branch::new(reference(&stale))).wait().unwrap();
+        branches
+            .add(Branch::new(reference(recent.last().unwrap())))
+            .wait()
+            .unwrap();
+
+        let main_tip = (MAX_ROLLBACK_DEPTH as u32 + 2).into();
+        branches.prune(main_tip).wait().unwrap();
+
+        assert_eq!(tips(&branches), vec![recent.last().unwrap().header.hash()]);
+    }
+
+    #[test]
+    fn keep_the_branches_within_the_rollback_depth() {
+        let block0 = block0();
+        let tip = child(&block0, 1);
+        let mut branches = Branches::new();
+        branches.add(Branch::new(reference(&tip))).wait().unwrap();
+
+        let main_tip = (MAX_ROLLBACK_DEPTH as u32 + 1).into();
+        branches.prune(main_tip).wait().unwrap();
+
+        assert_eq!(tips(&branches), vec![tip.header.hash()]);
+    }
+}
@@ -0,0 +1,385 @@
+use crate::{
+    blockcfg::{Block, Header, HeaderHash},
+    blockchain::{Error, ErrorKind},
+};
+use chain_core::property::ChainLength as _;
+use std::{collections::HashMap, convert::Infallible, time::Duration};
+use tokio::{
+    prelude::*,
+    sync::lock::Lock,
+    timer::{self, delay_queue, DelayQueue},
+};
+
+/// default time a candidate is kept in the [`CandidateForest`] waiting
+/// for its block to be applied.
+///
+/// [`CandidateForest`]: ./struct.CandidateForest.html
+pub const DEFAULT_CANDIDATE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// default maximum number of candidates held in the [`CandidateForest`]
+///
+/// [`CandidateForest`]: ./struct.CandidateForest.html
+pub const DEFAULT_MAX_CANDIDATES: usize = 4096;
+
+/// object that stores chains of headers received from the network
+/// that are not yet applied on the blockchain.
+///
+/// This allows the node to pre-validate a whole chain of headers
+/// (as received with a header pull or a header push) and then
+/// to fetch the associated blocks in any order. A block that is
+/// received before its parent has been applied is held in the
+/// `CandidateForest` until its parent is applied.
+///
+/// The forest is bounded: once the maximum number of candidates is
+/// reached the new headers are refused. The candidates that have not
+/// been applied before the TTL expires may be removed from the forest.
+/// Like the [`RefCache`], the expired candidates are removed only once
+/// [`purge`] has been called and has completed (or to make room for a
+/// new candidate).
+///
+/// [`RefCache`]: ./struct.RefCache.html
+/// [`purge`]: ./struct.CandidateForest.html#method.purge
+#[derive(Clone)]
+pub struct CandidateForest {
+    inner: Lock<CandidateForestData>,
+}
+
+struct CandidateForestData {
+    candidates: HashMap<HeaderHash, Candidate>,
+    /// index of the candidates by their parent's hash. The parent
+    /// may be a candidate itself or a block already applied on the
+    /// blockchain.
+    children: HashMap<HeaderHash, Vec<HeaderHash>>,
+    expirations: DelayQueue<HeaderHash>,
+
+    ttl: Duration,
+    max_entries: usize,
+}
+
+struct Candidate {
+    header: Header,
+    block: Option<Block>,
+    expiration_key: delay_queue::Key,
+}
+
+impl CandidateForest {
+    /// create a new `CandidateForest` holding at most `max_entries`
+    /// candidates for the given expiration `Duration`.
+    ///
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        CandidateForest {
+            inner: Lock::new(CandidateForestData::new(ttl, max_entries)),
+        }
+    }
+
+    /// return a future that will insert the given header, whose parent
+    /// has already been applied on the blockchain, as a new candidate.
+    ///
+    /// The header is expected to have been verified against its
+    /// parent already.
+    ///
+    /// The future returns `true` if the block of this candidate still
+    /// needs to be retrieved.
+    ///
+    /// # Errors
+    ///
+    /// The future fails if the forest is full.
+    ///
+    pub fn insert_root(&self, header: Header) -> impl Future<Item = bool, Error = Error> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock()))
+            .map_err(|_: Infallible| unreachable!())
+            .and_then(move |mut guard| guard.insert(header))
+    }
+
+    /// return a future that will insert the given header as a new
+    /// candidate descending from a candidate already in the forest.
+    ///
+    /// The header's date and chain length are checked against
+    /// the parent candidate.
+    ///
+    /// The future returns `true` if the block of this candidate still
+    /// needs to be retrieved.
+    ///
+    /// # Errors
+    ///
+    /// * the parent is not in the forest: `ErrorKind::MissingParentBlockFromStorage`;
+    /// * the header does not follow its parent's date or chain length;
+    /// * the forest is full.
+    ///
+    pub fn insert_child(&self, header: Header) -> impl Future<Item = bool, Error = Error> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock()))
+            .map_err(|_: Infallible| unreachable!())
+            .and_then(move |mut guard| guard.insert_child(header))
+    }
+
+    /// return a future that will hold the given block in the forest
+    /// until its parent is applied.
+    ///
+    /// If the block's header is not a known candidate the block is
+    /// not held and the future returns it back.
+    ///
+    pub fn hold_block(
+        &self,
+        block: Block,
+    ) -> impl Future<Item = Option<Block>, Error = Infallible> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock())).map(move |mut guard| guard.hold_block(block))
+    }
+
+    /// return a future that will notify the forest that the block
+    /// `applied` has been applied on the blockchain.
+    ///
+    /// The matching candidate, if any, is removed from the forest
+    /// and the blocks of its children that are already held are
+    /// returned so they can be applied in turn.
+    ///
+    pub fn take_ready_children(
+        &self,
+        applied: HeaderHash,
+    ) -> impl Future<Item = Vec<Block>, Error = Infallible> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock()))
+            .map(move |mut guard| guard.take_ready_children(&applied))
+    }
+
+    /// return a future that will remove the candidate and all
+    /// its descendants from the forest. To use when the candidate
+    /// has proved to be invalid.
+    ///
+    pub fn remove_descendants(
+        &self,
+        hash: HeaderHash,
+    ) -> impl Future<Item = (), Error = Infallible> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock()))
+            .map(move |mut guard| guard.remove_descendants(hash))
+    }
+
+    /// return a future that will remove every expired candidate from the forest
+    ///
+    pub fn purge(&self) -> impl Future<Item = (), Error = timer::Error> {
+        let mut inner = self.inner.clone();
+
+        future::poll_fn(move || Ok(inner.poll_lock()))
+            .and_then(|mut guard| future::poll_fn(move || guard.poll_purge()))
+    }
+}
+
+impl CandidateForestData {
+    fn new(ttl: Duration, max_entries: usize) -> Self {
+        CandidateForestData {
+            candidates: HashMap::new(),
+            children: HashMap::new(),
+            expirations: DelayQueue::new(),
+            ttl,
+            max_entries,
+        }
+    }
+
+    fn insert(&mut self, header: Header) -> Result<bool, Error> {
+        let hash = header.hash();
+        if let Some(candidate) = self.candidates.get(&hash) {
+            return Ok(candidate.block.is_none());
+        }
+
+        if self.candidates.len() >= self.max_entries {
+            // make room by removing the candidates that have already
+            // expired, without waiting for the ones still pending
+            let _ = self.poll_purge();
+            if self.candidates.len() >= self.max_entries {
+                return Err("too many candidate headers, the header is refused".into());
+            }
+        }
+
+        let parent_hash = header.block_parent_hash().clone();
+        let expiration_key = self.expirations.insert(hash.clone(), self.ttl);
+        self.candidates.insert(
+            hash.clone(),
+            Candidate {
+                header,
+                block: None,
+                expiration_key,
+            },
+        );
+        self.children
+            .entry(parent_hash)
+            .or_insert_with(Vec::new)
+            .push(hash);
+        Ok(true)
+    }
+
+    fn insert_child(&mut self, header: Header) -> Result<bool, Error> {
+        match self.candidates.get(header.block_parent_hash()) {
+            None => return Err(ErrorKind::MissingParentBlockFromStorage(header).into()),
+            Some(parent) => {
+                if header.block_date() <= parent.header.block_date() {
+                    return Err("block is not valid, date is set before parent's".into());
+                }
+                if header.chain_length() != parent.header.chain_length().next() {
+                    return Err(
+                        "block is not valid, chain length is not monotonically increasing".into(),
+                    );
+                }
+            }
+        }
+
+        self.insert(header)
+    }
+
+    fn hold_block(&mut self, block: Block) -> Option<Block> {
+        if let Some(candidate) = self.candidates.get_mut(&block.header.hash()) {
+            candidate.block = Some(block);
+            None
+        } else {
+            Some(block)
+        }
+    }
+
+    fn remove_candidate(&mut self, hash: &HeaderHash) -> Option<Candidate> {
+        let candidate = self.candidates.remove(hash)?;
+        self.expirations.remove(&candidate.expiration_key);
+        self.unlink_from_parent(hash, &candidate);
+        Some(candidate)
+    }
+
+    fn unlink_from_parent(&mut self, hash: &HeaderHash, candidate: &Candidate) {
+        let parent_hash = candidate.header.block_parent_hash();
+        if let Some(siblings) = self.children.get_mut(parent_hash) {
+            siblings.retain(|sibling| sibling != hash);
+            if siblings.is_empty() {
+                self.children.remove(parent_hash);
+            }
+        }
+    }
+
+    fn take_ready_children(&mut self, applied: &HeaderHash) -> Vec<Block> {
+        self.remove_candidate(applied);
+
+        let ready: Vec<HeaderHash> = match self.children.get(applied) {
+            None => return Vec::new(),
+            Some(children) => children
+                .iter()
+                .filter(|child| {
+                    self.candidates
+                        .get(child)
+                        .map_or(false, |candidate| candidate.block.is_some())
+                })
+                .cloned()
+                .collect(),
+        };
+
+        ready
+            .iter()
+            .filter_map(|child| self.remove_candidate(child))
+            .filter_map(|candidate| candidate.block)
+            .collect()
+    }
+
+    fn remove_descendants(&mut self, hash: HeaderHash) {
+        let mut to_remove = vec![hash];
+        while let Some(hash) = to_remove.pop() {
+            self.remove_candidate(&hash);
+            if let Some(children) = self.children.remove(&hash) {
+                to_remove.extend(children);
+            }
+        }
+    }
+
+    fn poll_purge(&mut self) -> Poll<(), timer::Error> {
+        loop {
+            match self.expirations.poll()? {
+                Async::NotReady => return Ok(Async::Ready(())),
+                Async::Ready(None) => return Ok(Async::Ready(())),
+                Async::Ready(Some(entry)) => {
+                    // the expiration key of the candidate is no longer
+                    // valid: it is removed here, then its descendants
+                    // which can no longer be applied
+                    let hash = entry.into_inner();
+                    if let Some(candidate) = self.candidates.remove(&hash) {
+                        self.unlink_from_parent(&hash, &candidate);
+                    }
+                    if let Some(children) = self.children.remove(&hash) {
+                        for child in children {
+                            self.remove_descendants(child);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::test_utils::{block0, child};
+    use std::time::Instant;
+    use tokio::{runtime::current_thread::Runtime, timer::Delay};
+
+    const TTL: Duration = Duration::from_millis(100);
+
+    fn wait(runtime: &mut Runtime, duration: Duration) {
+        runtime
+            .block_on(Delay::new(Instant::now() + duration))
+            .unwrap();
+    }
+
+    #[test]
+    fn expire_the_descendants_with_their_parent() {
+        let mut runtime = Runtime::new().unwrap();
+        let forest = CandidateForest::new(TTL, DEFAULT_MAX_CANDIDATES);
+        let block0 = block0();
+        let parent = child(&block0, 1);
+        let first_child = child(&parent, 2);
+        let grandchild = child(&first_child, 3);
+
+        assert!(runtime
+            .block_on(forest.insert_root(parent.header.clone()))
+            .unwrap());
+        wait(&mut runtime, TTL / 2);
+        for header in &[&first_child.header, &grandchild.header] {
+            assert!(runtime
+                .block_on(forest.insert_child((*header).clone()))
+                .unwrap());
+        }
+
+        // only the parent has expired
+        wait(&mut runtime, TTL * 3 / 4);
+        runtime.block_on(forest.purge()).unwrap();
+
+        let mut inner = forest.inner.clone();
+        let (candidates, children) = runtime
+            .block_on(
+                future::poll_fn(move || Ok::<_, ()>(inner.poll_lock()))
+                    .map(|guard| (guard.candidates.len(), guard.children.len())),
+            )
+            .unwrap();
+        assert_eq!(candidates, 0);
+        assert_eq!(children, 0);
+    }
+
+    #[test]
+    fn keep_the_candidates_not_expired() {
+        let mut runtime = Runtime::new().unwrap();
+        let forest = CandidateForest::new(TTL, DEFAULT_MAX_CANDIDATES);
+        let block0 = block0();
+        let parent = child(&block0, 1);
+        let first_child = child(&parent, 2);
+
+        runtime
+            .block_on(forest.insert_root(parent.header.clone()))
+            .unwrap();
+        runtime
+            .block_on(forest.insert_child(first_child.header.clone()))
+            .unwrap();
+        runtime.block_on(forest.purge()).unwrap();
+
+        // the block of the child is held: the child is still a candidate
+        assert!(runtime
+            .block_on(forest.hold_block(first_child.clone()))
+            .unwrap()
+            .is_none());
+    }
+}
@@ -53,14 +53,27 @@ use crate::{
     blockcfg::{
         Block, Block0Error, Epoch, Header, HeaderHash, Leadership, Ledger, LedgerParameters,
     },
-    blockchain::{Branch, Branches, Multiverse, Ref, RefCache, Storage},
+    blockchain::{
+        candidate::{DEFAULT_CANDIDATE_TTL, DEFAULT_MAX_CANDIDATES},
+        orphans::{DEFAULT_MAX_ORPHAN_BLOCKS, DEFAULT_ORPHAN_BLOCK_TTL},
+        Branch, Branches, CandidateForest, ChainEvents, Multiverse, OrphanPool, Ref, RefCache,
+        Snapshot, Snapshots, Storage,
+    },
     start_up::NodeStorage,
 };
 use chain_impl_mockchain::{leadership::Verification, ledger};
 use chain_storage::error::Error as StorageError;
 use chain_time::TimeFrame;
-use std::{convert::Infallible, sync::Arc, time::Duration};
-use tokio::prelude::*;
+use slog::Logger;
+use std::{
+    convert::{identity, Infallible},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    prelude::{future::Loop, *},
+    timer,
+};
 
 error_chain! {
     foreign_links {
@@ -100,10 +113,29 @@ error_chain! {
         CannotApplyBlock {
             description("Block cannot be applied on top of the previous block's ledger state"),
         }
+
+        StateTooFar (hash: HeaderHash) {
+            description("the state of the block is too far from any known state to be rebuilt"),
+            display("The state of block '{}' is too far from any cached or snapshot state to be rebuilt", hash),
+        }
     }
 }
 
-const MAIN_BRANCH_TAG: &str = "HEAD";
+pub(super) const MAIN_BRANCH_TAG: &str = "HEAD";
+
+/// maximum number of blocks replayed to rebuild the `Ref` of a block that
+/// is no longer in the cache, from the closest cached or snapshot state.
+pub const MAX_REF_REPLAY_LENGTH: usize = 10_000;
+
+/// the state a `Ref` is rebuilt from
+enum ReplayStart {
+    /// a `Ref` still in the cache
+    Cached(Ref),
+    /// a ledger snapshot
+    Snapshot(Snapshot),
+    /// the block0 of the given hash
+    Block0(HeaderHash),
+}
 
 /// blockchain object, can be safely shared across multiple threads. However it is better not
 /// to as some operations may require a mutex.
@@ -114,6 +146,12 @@ const MAIN_BRANCH_TAG: &str = "HEAD";
 /// * `RefCache`: a cache of blocks headers and associated states;
 /// * `Multiverse`: of ledger. It is a cache of different ledger states.
 ///
+/// It also holds the `CandidateForest`: the chains of headers received
+/// from the network whose blocks are not yet applied, and the `OrphanPool`:
+/// the blocks received from the network whose parent is not known yet.
+///
+/// The changes of the blockchain are published on its `ChainEvents`.
+///
 #[derive(Clone)]
 pub struct Blockchain {
     branches: Branches,
@@ -122,7 +160,15 @@ pub struct Blockchain {
 
     ledgers: Multiverse<Arc<Ledger>>,
 
+    candidates: CandidateForest,
+
+    orphans: OrphanPool,
+
     storage: Storage,
+
+    snapshots: Option<Snapshots>,
+
+    events: ChainEvents,
 }
 
 pub enum PreCheckedHeader {
@@ -171,12 +217,20 @@ pub struct PostCheckedHeader {
 }
 
 impl Blockchain {
-    pub fn new(storage: NodeStorage, ref_cache_ttl: Duration) -> Self {
+    pub fn new(
+        storage: NodeStorage,
+        ref_cache_ttl: Duration,
+        snapshots: Option<Snapshots>,
+    ) -> Self {
         Blockchain {
             branches: Branches::new(),
             ref_cache: RefCache::new(ref_cache_ttl),
             ledgers: Multiverse::new(),
+            candidates: CandidateForest::new(DEFAULT_CANDIDATE_TTL, DEFAULT_MAX_CANDIDATES),
+            orphans: OrphanPool::new(DEFAULT_ORPHAN_BLOCK_TTL, DEFAULT_MAX_ORPHAN_BLOCKS),
             storage: Storage::new(storage),
+            snapshots,
+            events: ChainEvents::new(),
         }
     }
 
@@ -184,6 +238,49 @@ impl Blockchain {
         &self.storage
     }
 
+    pub fn snapshots(&self) -> Option<&Snapshots> {
+        self.snapshots.as_ref()
+    }
+
+    pub fn candidates(&self) -> &CandidateForest {
+        &self.candidates
+    }
+
+    pub fn orphans(&self) -> &OrphanPool {
+        &self.orphans
+    }
+
+    pub fn events(&self) -> &ChainEvents {
+        &self.events
+    }
+
+    pub fn branches(&self) -> &Branches {
+        &self.branches
+    }
+
+    pub fn branches_mut(&mut self) -> &mut Branches {
+        &mut self.branches
+    }
+
+    /// return a future that will run the garbage collection of the
+    /// blockchain's caches: the expired `Ref`s, candidates and orphan
+    /// blocks are removed first, then the ledger states that are no
+    /// longer referenced are collected from the `Multiverse`.
+    ///
+    /// The future returns the number of ledger states collected.
+    ///
+    pub fn purge(&self) -> impl Future<Item = usize, Error = timer::Error> {
+        let candidates = self.candidates.clone();
+        let orphans = self.orphans.clone();
+        let ledgers = self.ledgers.clone();
+
+        self.ref_cache
+            .purge()
+            .and_then(move |()| candidates.purge())
+            .and_then(move |()| orphans.purge())
+            .and_then(move |()| ledgers.purge().map_err(|_: Infallible| unreachable!()))
+    }
+
     /// create and store a reference of this leader to the new
     fn create_and_store_reference(
         &mut self,
@@ -195,13 +292,11 @@ impl Blockchain {
         ledger_parameters: Arc<LedgerParameters>,
         previous_epoch_state: Option<Arc<Ref>>,
     ) -> impl Future<Item = Ref, Error = Infallible> {
-        let chain_length = header.chain_length();
-
         let multiverse = self.ledgers.clone();
         let ref_cache = self.ref_cache.clone();
 
         multiverse
-            .insert(chain_length, header_hash, ledger.clone())
+            .insert(header_hash, ledger.clone())
             .and_then(move |ledger_gcroot| {
                 let reference = Ref::new(
                     ledger_gcroot,
@@ -227,31 +322,33 @@ impl Blockchain {
     /// and it might be necessary to contacts the network to retrieve a missing
     /// branch
     ///
-    /// TODO: the case where the block is in storage but not yet in the cache
-    ///       is not implemented
+    /// If the block is in the storage but no longer in the cache, its `Ref` is
+    /// rebuilt by replaying the blocks from the closest ancestor still in the
+    /// cache (or from the block0).
     pub fn get_ref(
         &mut self,
         header_hash: HeaderHash,
     ) -> impl Future<Item = Option<Ref>, Error = Error> {
         let get_ref_cache_future = self.ref_cache.get(header_hash.clone());
         let block_exists_future = self.storage.block_exists(header_hash);
+        let self1 = self.clone();
 
         get_ref_cache_future
             .map_err(|_: Infallible| unreachable!())
-            .and_then(|maybe_ref| {
+            .and_then(move |maybe_ref| {
                 if maybe_ref.is_none() {
                     future::Either::A(
                         block_exists_future
                             .map_err(|e| {
                                 Error::with_chain(e, "cannot check if the block is in the storage")
                             })
-                            .and_then(|block_exists| {
+                            .and_then(move |block_exists| {
                                 if block_exists {
-                                    unimplemented!(
-                                        "method to load a Ref from the storage is not yet there"
+                                    future::Either::A(
+                                        self1.load_ref_from_storage(header_hash).map(Some),
                                     )
                                 } else {
-                                    future::ok(None)
+                                    future::Either::B(future::ok(None))
                                 }
                             }),
                     )
@@ -261,6 +358,163 @@ impl Blockchain {
             })
     }
 
+    /// rebuild the `Ref` of a block of the storage that is no longer in the
+    /// cache: the blocks are replayed from the closest ancestor still in the
+    /// cache or saved in a snapshot. Only the `Ref` of the requested block
+    /// is added to the cache, the intermediate blocks are replayed without
+    /// being cached.
+    fn load_ref_from_storage(
+        &self,
+        header_hash: HeaderHash,
+    ) -> impl Future<Item = Ref, Error = Error> {
+        let self1 = self.clone();
+        let self2 = self.clone();
+
+        self.find_cached_ancestor(header_hash)
+            .and_then(move |(ancestor, mut to_replay)| {
+                let last = to_replay.pop();
+                stream::iter_ok::<_, Error>(to_replay)
+                    .fold(ancestor, move |parent, hash| {
+                        let mut self3 = self1.clone();
+                        self1
+                            .get_block(hash)
+                            .and_then(move |block| self3.replay_block(parent, block))
+                    })
+                    .map(move |parent| (parent, last))
+            })
+            .and_then(move |(parent, last)| match last {
+                None => future::Either::A(future::ok(parent)),
+                Some(hash) => {
+                    let mut self3 = self2.clone();
+                    future::Either::B(self2.get_block(hash).and_then(move |block| {
+                        let mut self4 = self3.clone();
+                        self3
+                            .post_check_header(block.header.clone(), parent)
+                            .and_then(move |post_checked| self4.apply_block(post_checked, &block))
+                    }))
+                }
+            })
+    }
+
+    /// walk the storage back from the given block to the closest ancestor
+    /// whose `Ref` is in the cache or whose state is saved in a snapshot. If
+    /// none is, the `Ref` of the block0 is created again. The walk fails
+    /// with `ErrorKind::StateTooFar` after `MAX_REF_REPLAY_LENGTH` blocks.
+    ///
+    /// The future returns the ancestor's `Ref` and the hashes of the blocks
+    /// to replay on top of it, from the oldest to the given block.
+    fn find_cached_ancestor(
+        &self,
+        header_hash: HeaderHash,
+    ) -> impl Future<Item = (Ref, Vec<HeaderHash>), Error = Error> {
+        let ref_cache = self.ref_cache.clone();
+        let storage = self.storage.clone();
+        let snapshots = self.snapshots.clone();
+        let snapshot_entries = Arc::new(
+            self.snapshots
+                .as_ref()
+                .and_then(|snapshots| snapshots.list().ok())
+                .unwrap_or_default(),
+        );
+        let mut self1 = self.clone();
+
+        future::loop_fn(
+            (header_hash, Vec::new()),
+            move |(current, mut to_replay): (HeaderHash, Vec<HeaderHash>)| {
+                let storage = storage.clone();
+                let snapshots = snapshots.clone();
+                let snapshot_entries = snapshot_entries.clone();
+                ref_cache
+                    .get(current)
+                    .map_err(|_: Infallible| unreachable!())
+                    .and_then(move |cached| {
+                        if let Some(ancestor) = cached {
+                            return future::Either::A(future::ok(Loop::Break((
+                                ReplayStart::Cached(ancestor),
+                                to_replay,
+                            ))));
+                        }
+                        let snapshot = snapshots.as_ref().and_then(|snapshots| {
+                            snapshot_entries
+                                .iter()
+                                .find(|entry| entry.hash == current)
+                                .and_then(|entry| snapshots.read(entry).ok())
+                        });
+                        if let Some(snapshot) = snapshot {
+                            return future::Either::A(future::ok(Loop::Break((
+                                ReplayStart::Snapshot(snapshot),
+                                to_replay,
+                            ))));
+                        }
+                        if to_replay.len() >= MAX_REF_REPLAY_LENGTH {
+                            return future::Either::A(future::err(
+                                ErrorKind::StateTooFar(header_hash).into(),
+                            ));
+                        }
+                        future::Either::B(
+                            storage
+                                .get_block_info(current)
+                                .map_err(|e| {
+                                    Error::with_chain(
+                                        e,
+                                        "cannot get the block info from the storage",
+                                    )
+                                })
+                                .and_then(move |block_info| match block_info {
+                                    None => Err("block not found in the storage".into()),
+                                    // the block0 is the only block of depth 1
+                                    Some(ref block_info) if block_info.depth == 1 => {
+                                        Ok(Loop::Break((ReplayStart::Block0(current), to_replay)))
+                                    }
+                                    Some(block_info) => {
+                                        to_replay.push(current);
+                                        Ok(Loop::Continue((block_info.parent_id(), to_replay)))
+                                    }
+                                }),
+                        )
+                    })
+            },
+        )
+        .and_then(move |(start, mut to_replay)| {
+            to_replay.reverse();
+            self1
+                .replay_start_ref(start)
+                .map(move |ancestor| (ancestor, to_replay))
+        })
+    }
+
+    /// get the `Ref` the blocks are replayed from, the `Ref`s rebuilt from
+    /// a snapshot or from the block0 are added to the cache.
+    fn replay_start_ref(&mut self, start: ReplayStart) -> impl Future<Item = Ref, Error = Error> {
+        match start {
+            ReplayStart::Cached(ancestor) => future::Either::A(future::ok(ancestor)),
+            ReplayStart::Block0(block0_hash) => {
+                let mut self1 = self.clone();
+                future::Either::B(future::Either::A(
+                    self.get_block(block0_hash)
+                        .and_then(move |block0| self1.create_block0_ref(block0)),
+                ))
+            }
+            ReplayStart::Snapshot(snapshot) => {
+                let self1 = self.clone();
+                let mut self2 = self.clone();
+                future::Either::B(future::Either::B(
+                    self.storage
+                        .get_ancestor_at_chain_length(snapshot.hash, 0)
+                        .map_err(|e| Error::with_chain(e, "cannot find the block0 in the storage"))
+                        .and_then(|block0_hash| {
+                            block0_hash.ok_or_else(|| "block0 not found in the storage".into())
+                        })
+                        .and_then(move |block0_hash| self1.get_block(block0_hash))
+                        .and_then(|block0| block0_time_frame(&block0))
+                        .and_then(move |time_frame| {
+                            self2.restore_snapshot(snapshot, Arc::new(time_frame))
+                        }),
+                ))
+            }
+        }
+    }
+
     /// load the header's parent `Ref`.
     fn load_header_parent(
         &mut self,
@@ -311,30 +565,16 @@ impl Blockchain {
         //       actually sign the header signing data against
         //       the public key).
 
-        self.load_header_parent(header)
-            .and_then(|pre_check| match &pre_check {
-                PreCheckedHeader::HeaderWithCache {
-                    ref header,
-                    ref parent_ref,
-                } => {
-                    use chain_core::property::ChainLength as _;
-
-                    if header.block_date() <= parent_ref.block_date() {
-                        return future::err(
-                            "block is not valid, date is set before parent's".into(),
-                        );
-                    }
-                    if header.chain_length() != parent_ref.chain_length().next() {
-                        return future::err(
-                            "block is not valid, chain length is not monotonically increasing"
-                                .into(),
-                        );
-                    }
-
-                    future::ok(pre_check)
-                }
-                _ => future::ok(pre_check),
-            })
+        self.load_header_parent(header).and_then(|pre_check| {
+            if let PreCheckedHeader::HeaderWithCache {
+                ref header,
+                ref parent_ref,
+            } = pre_check
+            {
+                check_header_against_parent(header, parent_ref)?;
+            }
+            Ok(pre_check)
+        })
     }
 
     /// check the header cryptographic properties and leadership's schedule
@@ -381,7 +621,6 @@ impl Blockchain {
         Arc<TimeFrame>,
         Option<Arc<Ref>>,
     ) {
-        let parent_ledger_state = parent.ledger().clone();
         let parent_epoch_leadership_schedule = parent.epoch_leadership_schedule().clone();
         let parent_epoch_ledger_parameters = parent.epoch_ledger_parameters().clone();
         let parent_time_frame = parent.time_frame().clone();
@@ -392,19 +631,7 @@ impl Blockchain {
             // TODO: the time frame may change in the future, we will need to handle this
             //       special case but it is not actually clear how to modify the time frame
             //       for the blockchain
-            use chain_impl_mockchain::block::ConsensusVersion;
-
-            let epoch_state =
-                if parent_ledger_state.consensus_version() == ConsensusVersion::GenesisPraos {
-                    // if there is no parent state available this might be because it is not
-                    // available in memory or it is the epoch0 or epoch1
-                    parent
-                        .last_ref_previous_epoch()
-                        .map(|r| r.ledger().clone())
-                        .unwrap_or(parent_ledger_state.clone())
-                } else {
-                    parent_ledger_state.clone()
-                };
+            let epoch_state = epoch_leadership_state(&parent);
 
             let leadership = Arc::new(Leadership::new(epoch, &epoch_state));
             let ledger_parameters = Arc::new(leadership.ledger_parameters().clone());
@@ -425,7 +652,7 @@ impl Blockchain {
         }
     }
 
-    fn apply_block(
+    pub(super) fn apply_block(
         &mut self,
         post_checked_header: PostCheckedHeader,
         block: &Block,
@@ -464,6 +691,36 @@ impl Blockchain {
         })
     }
 
+    /// apply the block on top of its `parent` without keeping the new
+    /// `Ref` in the cache: the ledger states no longer referenced are
+    /// collected right away, so replaying a long chain of blocks only
+    /// keeps the `Ref` of the last block in memory.
+    ///
+    /// The block's parent hash must be the hash of `parent`.
+    pub(super) fn replay_block(
+        &mut self,
+        parent: Ref,
+        block: Block,
+    ) -> impl Future<Item = Ref, Error = Error> {
+        debug_assert!(block.header.block_parent_hash() == &parent.hash());
+
+        let mut self1 = self.clone();
+        let mut self2 = self.clone();
+        let ref_cache = self.ref_cache.clone();
+        let ledgers = self.ledgers.clone();
+
+        future::result(check_header_against_parent(&block.header, &parent))
+            .and_then(move |()| self1.post_check_header(block.header.clone(), parent))
+            .and_then(move |post_checked| self2.apply_block(post_checked, &block))
+            .and_then(move |new_ref| {
+                ref_cache
+                    .remove(new_ref.hash())
+                    .and_then(move |()| ledgers.purge())
+                    .map(move |_collected| new_ref)
+                    .map_err(|_: Infallible| unreachable!())
+            })
+    }
+
     /// Apply the block on the blockchain from a post checked header
     /// and add it to the storage.
     pub fn apply_and_store_block(
@@ -494,33 +751,28 @@ impl Blockchain {
     /// * the block0 does build an invalid `Ledger`: `ErrorKind::Block0InitialLedgerError`;
     ///
     fn apply_block0(&mut self, block0: Block) -> impl Future<Item = Branch, Error = Error> {
+        let mut branches = self.branches.clone();
+
+        self.create_block0_ref(block0)
+            .map(Branch::new)
+            .and_then(move |branch| {
+                branches
+                    .add(branch.clone())
+                    .map(|()| branch)
+                    .map_err(|_: Infallible| unreachable!())
+            })
+    }
+
+    /// create the `Ref` of the block0 and add it to the cache
+    fn create_block0_ref(&mut self, block0: Block) -> impl Future<Item = Ref, Error = Error> {
         let block0_header = block0.header.clone();
         let block0_id = block0_header.hash();
         let block0_id_1 = block0_header.hash();
         let block0_date = block0_header.block_date().clone();
 
         let mut self1 = self.clone();
-        let mut branches = self.branches.clone();
 
-        let time_frame = {
-            use crate::blockcfg::Block0DataSource as _;
-
-            let start_time = block0
-                .start_time()
-                .map_err(|err| Error::with_chain(err, ErrorKind::Block0InitialLedgerError));
-            let slot_duration = block0
-                .slot_duration()
-                .map_err(|err| Error::with_chain(err, ErrorKind::Block0InitialLedgerError));
-
-            future::result(start_time.and_then(|start_time| {
-                slot_duration.map(|slot_duration| {
-                    TimeFrame::new(
-                        chain_time::Timeline::new(start_time),
-                        chain_time::SlotDuration::from_secs(slot_duration.as_secs() as u32),
-                    )
-                })
-            }))
-        };
+        let time_frame = future::result(block0_time_frame(&block0));
 
         // we lift the creation of the ledger in the future type
         // this allow chaining of the operation and lifting the error handling
@@ -551,13 +803,6 @@ impl Blockchain {
                     )
                     .map_err(|_: Infallible| unreachable!())
             })
-            .map(Branch::new)
-            .and_then(move |branch| {
-                branches
-                    .add(branch.clone())
-                    .map(|()| branch)
-                    .map_err(|_: Infallible| unreachable!())
-            })
     }
 
     /// function to do the initial application of the block0 in the `Blockchain` and its
@@ -613,6 +858,9 @@ impl Blockchain {
     /// from the block0 to the `Head` of the storage (the last known block which
     /// made consensus).
     ///
+    /// If a valid snapshot of the ledger state is available for one of the
+    /// ancestors of the `Head`, only the blocks after the snapshot are applied.
+    ///
     /// The Future will returns a branch pointing to the `Head`.
     ///
     /// # Errors
@@ -626,14 +874,15 @@ impl Blockchain {
     pub fn load_from_storage(
         &mut self,
         block0: Block,
+        logger: Logger,
     ) -> impl Future<Item = Branch, Error = Error> {
         let block0_header = block0.header.clone();
         let block0_id = block0_header.hash();
-        let block0_id_2 = block0_id.clone();
         let self1 = self.clone();
         let mut self2 = self.clone();
-        let self3 = self.clone();
+        let mut self3 = self.clone();
         let self4 = self.clone();
+        let self5 = self.clone();
 
         self.storage
             .block_exists(block0_id.clone())
@@ -665,8 +914,13 @@ impl Blockchain {
             })
             .and_then(move |(branch, head_hash)| {
                 self3
+                    .resume_from_snapshot(branch.clone(), head_hash.clone(), logger)
+                    .map(move |from| (branch, from, head_hash))
+            })
+            .and_then(move |(branch, from, head_hash)| {
+                self4
                     .storage
-                    .stream_from_to(block0_id_2, head_hash)
+                    .stream_from_to(from, head_hash)
                     .map_err(|e| Error::with_chain(e, "Cannot iterate blocks from block0 to HEAD"))
                     .and_then(|block_stream| {
                         if let Some(block_stream) = block_stream {
@@ -680,21 +934,21 @@ impl Blockchain {
                             .map_err(|e| {
                                 Error::with_chain(e, "Error while iterating between bloc0 and HEAD")
                             })
-                            .fold((branch, self4), move |(branch, mut self4), block: Block| {
+                            .fold((branch, self5), move |(branch, mut self5), block: Block| {
                                 let header = block.header.clone();
 
-                                let mut self5 = self4.clone();
-                                let mut self6 = self4.clone();
-                                let returned = self4.clone();
+                                let mut self6 = self5.clone();
+                                let mut self7 = self5.clone();
+                                let returned = self5.clone();
 
-                                self4
+                                self5
                                     .pre_check_header(header)
                                     .and_then(move |pre_checked_header: PreCheckedHeader| {
                                         match pre_checked_header {
                                             PreCheckedHeader::HeaderWithCache {
                                                 header,
                                                 parent_ref,
-                                            } => future::Either::A(self5.post_check_header(header, parent_ref)),
+                                            } => future::Either::A(self6.post_check_header(header, parent_ref)),
                                             PreCheckedHeader::AlreadyPresent { header, cached_reference: _cached_reference } => {
                                                 unreachable!("block already present, this should not happen. {:#?}", header)
                                             },
@@ -703,7 +957,7 @@ impl Blockchain {
                                         }
                                     })
                                     .and_then(move |post_checked_header: PostCheckedHeader| {
-                                        self6.apply_block(post_checked_header, &block)
+                                        self7.apply_block(post_checked_header, &block)
                                     })
                                     .and_then(move |new_ref| {
                                         branch
@@ -718,6 +972,139 @@ impl Blockchain {
             })
     }
 
+    /// look for the most recent snapshot of the ledger state that is an
+    /// ancestor of `head_hash` and restore it in the given branch.
+    ///
+    /// The future returns the hash of the block the branch is pointing to:
+    /// the block of the restored snapshot, or the block0 if there is no
+    /// valid snapshot to resume from.
+    fn resume_from_snapshot(
+        &mut self,
+        mut branch: Branch,
+        head_hash: HeaderHash,
+        logger: Logger,
+    ) -> impl Future<Item = HeaderHash, Error = Error> {
+        let entries = match &self.snapshots {
+            None => Vec::new(),
+            Some(snapshots) => snapshots.list().unwrap_or_else(|error| {
+                warn!(logger, "cannot list the ledger snapshots"; "reason" => error.to_string());
+                Vec::new()
+            }),
+        };
+        let snapshots = self.snapshots.clone();
+        let storage = self.storage.clone();
+        let mut self1 = self.clone();
+
+        branch
+            .get_ref()
+            .map_err(|_: Infallible| unreachable!())
+            .and_then(move |block0_ref| {
+                let load_logger = logger.clone();
+                stream::iter_ok(entries)
+                    .and_then(move |entry| {
+                        storage
+                            .is_ancestor(entry.hash.clone(), head_hash.clone())
+                            .then(|is_ancestor| match is_ancestor {
+                                Ok(Some(_distance)) => Ok(Some(entry)),
+                                _ => Ok(None),
+                            })
+                    })
+                    .filter_map(identity)
+                    .filter_map(move |entry| {
+                        snapshots
+                            .as_ref()
+                            .and_then(|snapshots| snapshots.load(&entry, &load_logger))
+                    })
+                    .into_future()
+                    .map_err(|(error, _): (Error, _)| error)
+                    .and_then(move |(snapshot, _)| match snapshot {
+                        None => future::Either::A(future::ok(block0_ref.hash())),
+                        Some(snapshot) => {
+                            info!(logger, "resuming from ledger snapshot";
+                                "hash" => snapshot.hash.to_string());
+                            let time_frame = block0_ref.time_frame().clone();
+                            future::Either::B(
+                                self1.restore_snapshot(snapshot, time_frame).and_then(
+                                    move |reference| {
+                                        let hash = reference.hash();
+                                        branch
+                                            .update_ref(reference)
+                                            .map(move |_block0_ref| hash)
+                                            .map_err(|_: Infallible| unreachable!())
+                                    },
+                                ),
+                            )
+                        }
+                    })
+            })
+    }
+
+    /// re-create the `Ref` of the snapshot's block and the `Ref` of the
+    /// previous epoch's last block from the snapshot.
+    fn restore_snapshot(
+        &mut self,
+        snapshot: Snapshot,
+        time_frame: Arc<TimeFrame>,
+    ) -> impl Future<Item = Ref, Error = Error> {
+        let Snapshot {
+            hash,
+            ledger,
+            previous_epoch_hash,
+            previous_epoch_ledger,
+            epoch_leadership_ledger,
+        } = snapshot;
+        let mut self1 = self.clone();
+        let mut self2 = self.clone();
+
+        self.get_header(previous_epoch_hash.clone())
+            .join(self.get_header(hash.clone()))
+            .and_then(move |(previous_epoch_header, header)| {
+                let epoch = header.block_date().epoch;
+                let leadership = Arc::new(Leadership::new(epoch, &epoch_leadership_ledger));
+                let ledger_parameters = Arc::new(leadership.ledger_parameters().clone());
+
+                // only the ledger of the previous epoch state is used (to compute
+                // the leadership of the next epoch), we can share the leadership
+                // and ledger parameters of the current epoch with it.
+                self1
+                    .create_and_store_reference(
+                        previous_epoch_hash,
+                        previous_epoch_header,
+                        previous_epoch_ledger,
+                        time_frame.clone(),
+                        leadership.clone(),
+                        ledger_parameters.clone(),
+                        None,
+                    )
+                    .and_then(move |previous_epoch_ref| {
+                        self2.create_and_store_reference(
+                            hash,
+                            header,
+                            ledger,
+                            time_frame,
+                            leadership,
+                            ledger_parameters,
+                            Some(Arc::new(previous_epoch_ref)),
+                        )
+                    })
+                    .map_err(|_: Infallible| unreachable!())
+            })
+    }
+
+    fn get_header(&self, header_hash: HeaderHash) -> impl Future<Item = Header, Error = Error> {
+        self.get_block(header_hash).map(|block| block.header)
+    }
+
+    fn get_block(&self, header_hash: HeaderHash) -> impl Future<Item = Block, Error = Error> {
+        self.storage
+            .get(header_hash)
+            .map_err(|e| Error::with_chain(e, "Cannot get the block from the storage"))
+            .and_then(|block| match block {
+                Some(block) => future::ok(block),
+                None => future::err("block not found in the storage".into()),
+            })
+    }
+
     pub fn get_checkpoints(
         &self,
         branch: Branch,
@@ -729,3 +1116,64 @@ impl Blockchain {
             .and_then(move |tip| storage.get_checkpoints(tip.hash()).map_err(|e| e.into()))
     }
 }
+
+/// the time frame of the blockchain, as set in the block0
+fn block0_time_frame(block0: &Block) -> Result<TimeFrame, Error> {
+    use crate::blockcfg::Block0DataSource as _;
+
+    let start_time = block0
+        .start_time()
+        .map_err(|err| Error::with_chain(err, ErrorKind::Block0InitialLedgerError))?;
+    let slot_duration = block0
+        .slot_duration()
+        .map_err(|err| Error::with_chain(err, ErrorKind::Block0InitialLedgerError))?;
+    Ok(TimeFrame::new(
+        chain_time::Timeline::new(start_time),
+        chain_time::SlotDuration::from_secs(slot_duration.as_secs() as u32),
+    ))
+}
+
+/// check the block date and the chain length of the header against its
+/// parent's.
+fn check_header_against_parent(header: &Header, parent_ref: &Ref) -> Result<(), Error> {
+    use chain_core::property::ChainLength as _;
+
+    if header.block_date() <= parent_ref.block_date() {
+        return Err("block is not valid, date is set before parent's".into());
+    }
+    if header.chain_length() != parent_ref.chain_length().next() {
+        return Err("block is not valid, chain length is not monotonically increasing".into());
+    }
+    Ok(())
+}
+
+/// get the ledger state used to compute the leadership schedule of the
+/// epochs following the `parent`'s epoch.
+pub(super) fn epoch_leadership_state(parent: &Ref) -> Arc<Ledger> {
+    use chain_impl_mockchain::block::ConsensusVersion;
+
+    let parent_ledger_state = parent.ledger();
+    if parent_ledger_state.consensus_version() == ConsensusVersion::GenesisPraos {
+        // if there is no parent state available this might be because it is not
+        // available in memory or it is the epoch0 or epoch1
+        parent
+            .last_ref_previous_epoch()
+            .map(|r| r.ledger().clone())
+            .unwrap_or(parent_ledger_state.clone())
+    } else {
+        parent_ledger_state.clone()
+    }
+}
+
+/// get the ledger state the leadership schedule of the given `epoch` is
+/// computed from, the `parent` being in this epoch or in a previous one.
+pub(super) fn epoch_leadership_ledger(parent: &Ref, epoch: Epoch) -> Arc<Ledger> {
+    if parent.block_date().epoch < epoch {
+        epoch_leadership_state(parent)
+    } else {
+        parent
+            .last_ref_previous_epoch()
+            .map(|previous_epoch| epoch_leadership_state(previous_epoch))
+            .unwrap_or(parent.ledger().clone())
+    }
+}
@@ -0,0 +1,177 @@
+//! chain selection policy
+//!
+//! When a new block is applied on the blockchain it creates a new candidate
+//! [`Ref`]. Either the candidate is a direct descendant of the current tip
+//! (the most common case) or it is on a competing fork. In the latter case
+//! we need to decide which of the 2 branches the node should follow.
+//!
+//! The policy is the following:
+//!
+//! 1. the longest chain (in chain length) is preferred;
+//! 2. on equal chain length we keep the current tip, we do not flip-flop
+//!    between forks of the same length;
+//! 3. we never roll back more than [`MAX_ROLLBACK_DEPTH`] blocks from the
+//!    current tip, even if the candidate is longer.
+//!
+//! [`Ref`]: ../struct.Ref.html
+//! [`MAX_ROLLBACK_DEPTH`]: ./constant.MAX_ROLLBACK_DEPTH.html
+
+use crate::{
+    blockcfg::{Header, HeaderHash},
+    blockchain::{Ref, Storage},
+};
+use chain_storage::error::Error as StorageError;
+use tokio::prelude::*;
+
+/// maximum number of blocks the node accepts to roll back from its
+/// current tip in order to switch to a competing branch.
+pub const MAX_ROLLBACK_DEPTH: u64 = 2600;
+
+/// the result of the comparison of the current tip against a candidate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComparisonResult {
+    /// keep the current tip
+    PreferCurrent,
+    /// switch the tip to the candidate. `rollback` is the number of blocks
+    /// of the current branch that will be abandoned (0 if the candidate
+    /// is a descendant of the current tip) and `ancestor` is the common
+    /// ancestor of the current tip and the candidate.
+    PreferCandidate { rollback: u64, ancestor: HeaderHash },
+}
+
+/// compare the current tip against the candidate.
+///
+/// # Errors
+///
+/// The future may fail if the storage could not be queried. The
+/// blocks of both `Ref` are expected to be in the storage already.
+///
+pub fn compare_against(
+    storage: &Storage,
+    current: &Ref,
+    candidate: &Ref,
+) -> impl Future<Item = ComparisonResult, Error = StorageError> {
+    compare_headers(storage, current.header(), candidate.header())
+}
+
+fn compare_headers(
+    storage: &Storage,
+    current: &Header,
+    candidate: &Header,
+) -> impl Future<Item = ComparisonResult, Error = StorageError> {
+    if candidate.chain_length() <= current.chain_length() {
+        return future::Either::A(future::ok(ComparisonResult::PreferCurrent));
+    }
+
+    if candidate.block_parent_hash() == &current.hash() {
+        return future::Either::A(future::ok(ComparisonResult::PreferCandidate {
+            rollback: 0,
+            ancestor: current.hash(),
+        }));
+    }
+
+    let current_hash = current.hash();
+    let candidate_hash = candidate.hash();
+
+    future::Either::B(
+        storage
+            .find_common_ancestor(current_hash, candidate_hash, MAX_ROLLBACK_DEPTH)
+            .map(|rollback| match rollback {
+                None => ComparisonResult::PreferCurrent,
+                Some((ancestor, rollback)) => {
+                    ComparisonResult::PreferCandidate { rollback, ancestor }
+                }
+            }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::test_utils::{block0, branch, child, storage};
+
+    #[test]
+    fn longer_chain_wins() {
+        let block0 = block0();
+        let block1 = child(&block0, 1);
+        let storage = storage(vec![&block0, &block1]);
+
+        let result = compare_headers(&storage, &block0.header, &block1.header)
+            .wait()
+            .unwrap();
+
+        assert_eq!(
+            result,
+            ComparisonResult::PreferCandidate {
+                rollback: 0,
+                ancestor: block0.header.hash(),
+            }
+        );
+    }
+
+    #[test]
+    fn longer_fork_wins() {
+        let block0 = block0();
+        let current = branch(&block0, 2, 1);
+        let candidate = branch(&block0, 3, 2);
+        let storage = storage(
+            Some(&block0)
+                .into_iter()
+                .chain(current.iter())
+                .chain(candidate.iter()),
+        );
+
+        let result = compare_headers(
+            &storage,
+            &current.last().unwrap().header,
+            &candidate.last().unwrap().header,
+        )
+        .wait()
+        .unwrap();
+
+        assert_eq!(
+            result,
+            ComparisonResult::PreferCandidate {
+                rollback: 2,
+                ancestor: block0.header.hash(),
+            }
+        );
+    }
+
+    #[test]
+    fn tie_keeps_the_tip() {
+        let block0 = block0();
+        let current = child(&block0, 1);
+        let candidate = child(&block0, 2);
+        let storage = storage(vec![&block0, &current, &candidate]);
+
+        let result = compare_headers(&storage, &current.header, &candidate.header)
+            .wait()
+            .unwrap();
+
+        assert_eq!(result, ComparisonResult::PreferCurrent);
+    }
+
+    #[test]
+    fn rollback_deeper_than_max_depth_keeps_the_tip() {
+        let block0 = block0();
+        let current = branch(&block0, MAX_ROLLBACK_DEPTH as usize + 1, 1);
+        let candidate = branch(&block0, MAX_ROLLBACK_DEPTH as usize + 2, 2);
+        let storage = storage(
+            Some(&block0)
+                .into_iter()
+                .chain(current.iter())
+                .chain(candidate.iter()),
+        );
+
+        let result = compare_headers(
+            &storage,
+            &current.last().unwrap().header,
+            &candidate.last().unwrap().header,
+        )
+        .wait()
+        .unwrap();
+
+        assert_eq!(result, ComparisonResult::PreferCurrent);
+    }
+}
@@ -0,0 +1,87 @@
+//! broadcast of the changes of the blockchain
+//!
+//! The block task publishes a [`ChainEvent`] every time a block is applied
+//! or the tip of the blockchain moves. Any module can [`subscribe`] to
+//! the events: every subscriber receives its own copy of every event
+//! published after its subscription.
+//!
+//! The publication never blocks the block task: a subscriber that does not
+//! keep up with the events misses the events published while its queue is
+//! full, and a subscriber whose receiving end has been dropped is removed.
+//!
+//! [`ChainEvent`]: ./enum.ChainEvent.html
+//! [`subscribe`]: ./struct.ChainEvents.html#method.subscribe
+
+use crate::{
+    blockcfg::{BlockDate, ChainLength, Epoch, HeaderHash},
+    utils::async_msg::{self, MessageBox, MessageQueue},
+};
+use std::sync::{Arc, Mutex};
+
+/// number of events a subscriber can be late of before missing events
+pub const SUBSCRIBER_QUEUE_LEN: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainEvent {
+    /// a block has been applied on the blockchain, on the main branch
+    /// or on a side branch
+    BlockApplied {
+        hash: HeaderHash,
+        chain_length: ChainLength,
+        date: BlockDate,
+    },
+    /// the tip of the blockchain moved to the given block
+    TipChanged {
+        hash: HeaderHash,
+        chain_length: ChainLength,
+        date: BlockDate,
+    },
+    /// the tip switched to a competing branch: the `depth` blocks after
+    /// `ancestor` up to `old_tip` are no longer on the main branch.
+    /// Published before the matching `TipChanged`.
+    Rollback {
+        depth: u64,
+        ancestor: HeaderHash,
+        old_tip: HeaderHash,
+    },
+    /// the tip of the blockchain entered a new epoch. Published after
+    /// the matching `TipChanged`.
+    EpochStarted { epoch: Epoch },
+}
+
+/// the publishing end of the chain events, cloning it gives access to
+/// the same subscribers.
+#[derive(Clone)]
+pub struct ChainEvents {
+    subscribers: Arc<Mutex<Vec<MessageBox<ChainEvent>>>>,
+}
+
+impl ChainEvents {
+    pub fn new() -> Self {
+        ChainEvents {
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// subscribe to the events published from now on
+    pub fn subscribe(&self) -> MessageQueue<ChainEvent> {
+        let (msgbox, queue) = async_msg::channel(SUBSCRIBER_QUEUE_LEN);
+        self.subscribers.lock().unwrap().push(msgbox);
+        queue
+    }
+
+    /// send the event to every subscriber, without waiting for
+    /// the subscribers to have room in their queue.
+    pub fn publish(&self, event: ChainEvent) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let mut i = 0;
+        while i < subscribers.len() {
+            match subscribers[i].try_send(event.clone()) {
+                Err(ref error) if error.is_disconnected() => {
+                    subscribers.swap_remove(i);
+                }
+                _ => i += 1,
+            }
+        }
+    }
+}
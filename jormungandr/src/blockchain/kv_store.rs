@@ -0,0 +1,185 @@
+//! block storage on top of an embedded log-structured key-value store
+//!
+//! The blocks, the blocks' info (depth and back links) and the tags are
+//! kept in 3 different trees of the same database. Writes are appended
+//! to the database's log and flushed periodically, which suits the
+//! append-heavy workload of the node (especially during the bootstrap)
+//! better than the SQLite storage.
+//!
+//! After a crash the database recovers a prefix of the writes, as the
+//! blocks and their info are written before the tags, a tag never points
+//! to a block that is not in the storage.
+
+use crate::blockcfg::{Block, HeaderHash};
+use chain_core::property::{Deserialize as _, Serialize as _};
+use chain_storage::{
+    error::Error as StorageError,
+    store::{BackLink, BlockInfo, BlockStore},
+};
+use std::{
+    io::{self, Read as _},
+    path::Path,
+    sync::Arc,
+};
+
+/// interval between 2 flushes of the database's log on disk
+const FLUSH_INTERVAL_MS: u64 = 1_000;
+
+const BLOCKS_TREE: &[u8] = b"blocks";
+const BLOCK_INFOS_TREE: &[u8] = b"block_infos";
+const TAGS_TREE: &[u8] = b"tags";
+
+pub type Error = sled::Error;
+
+pub struct KvBlockStore {
+    db: sled::Db,
+    blocks: Arc<sled::Tree>,
+    block_infos: Arc<sled::Tree>,
+    tags: Arc<sled::Tree>,
+}
+
+impl KvBlockStore {
+    /// open the key-value store in the given directory, the directory
+    /// is created if it does not exist yet.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let config = sled::ConfigBuilder::new()
+            .path(path)
+            .flush_every_ms(Some(FLUSH_INTERVAL_MS))
+            .build();
+        let db = sled::Db::start(config)?;
+        let blocks = db.open_tree(BLOCKS_TREE.to_vec())?;
+        let block_infos = db.open_tree(BLOCK_INFOS_TREE.to_vec())?;
+        let tags = db.open_tree(TAGS_TREE.to_vec())?;
+
+        Ok(KvBlockStore {
+            db,
+            blocks,
+            block_infos,
+            tags,
+        })
+    }
+}
+
+impl Drop for KvBlockStore {
+    fn drop(&mut self) {
+        // do not wait for the next periodic flush to persist the last writes
+        let _ = self.db.flush();
+    }
+}
+
+impl BlockStore for KvBlockStore {
+    type Block = Block;
+
+    fn put_block_internal(
+        &mut self,
+        block: &Block,
+        block_info: BlockInfo<HeaderHash>,
+    ) -> Result<(), StorageError> {
+        let key = encode_hash(&block_info.block_hash)?;
+        let block = block.serialize_as_vec().map_err(backend_error)?;
+        let block_info = encode_block_info(&block_info)?;
+
+        self.blocks
+            .insert(key.clone(), block)
+            .map_err(backend_error)?;
+        self.block_infos
+            .insert(key, block_info)
+            .map_err(backend_error)?;
+        Ok(())
+    }
+
+    fn get_block(
+        &self,
+        block_hash: &HeaderHash,
+    ) -> Result<(Block, BlockInfo<HeaderHash>), StorageError> {
+        let key = encode_hash(block_hash)?;
+        let block = match self.blocks.get(&key).map_err(backend_error)? {
+            None => return Err(StorageError::BlockNotFound),
+            Some(bytes) => Block::deserialize(bytes.as_ref()).map_err(backend_error)?,
+        };
+        Ok((block, self.get_block_info(block_hash)?))
+    }
+
+    fn get_block_info(
+        &self,
+        block_hash: &HeaderHash,
+    ) -> Result<BlockInfo<HeaderHash>, StorageError> {
+        let key = encode_hash(block_hash)?;
+        match self.block_infos.get(&key).map_err(backend_error)? {
+            None => Err(StorageError::BlockNotFound),
+            Some(bytes) => decode_block_info(block_hash.clone(), bytes.as_ref()),
+        }
+    }
+
+    fn put_tag(&mut self, tag_name: &str, block_hash: &HeaderHash) -> Result<(), StorageError> {
+        self.tags
+            .insert(tag_name.as_bytes(), encode_hash(block_hash)?)
+            .map_err(backend_error)?;
+        Ok(())
+    }
+
+    fn get_tag(&self, tag_name: &str) -> Result<Option<HeaderHash>, StorageError> {
+        match self.tags.get(tag_name.as_bytes()).map_err(backend_error)? {
+            None => Ok(None),
+            Some(bytes) => HeaderHash::deserialize(bytes.as_ref())
+                .map(Some)
+                .map_err(backend_error),
+        }
+    }
+}
+
+fn backend_error<E>(error: E) -> StorageError
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    StorageError::BackendError(Box::new(error))
+}
+
+fn encode_hash(hash: &HeaderHash) -> Result<Vec<u8>, StorageError> {
+    hash.serialize_as_vec().map_err(backend_error)
+}
+
+/// the block info is encoded as: the depth (u64, big endian), the number
+/// of back links (u32, big endian) and each back link's distance (u64,
+/// big endian) followed by the back link's hash.
+fn encode_block_info(block_info: &BlockInfo<HeaderHash>) -> Result<Vec<u8>, StorageError> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&block_info.depth.to_be_bytes());
+    bytes.extend_from_slice(&(block_info.back_links.len() as u32).to_be_bytes());
+    for back_link in block_info.back_links.iter() {
+        bytes.extend_from_slice(&back_link.distance.to_be_bytes());
+        bytes.extend(encode_hash(&back_link.block_hash)?);
+    }
+    Ok(bytes)
+}
+
+fn decode_block_info(
+    block_hash: HeaderHash,
+    mut bytes: &[u8],
+) -> Result<BlockInfo<HeaderHash>, StorageError> {
+    let depth = read_u64(&mut bytes).map_err(backend_error)?;
+    let mut len = [0; 4];
+    bytes.read_exact(&mut len).map_err(backend_error)?;
+    let len = u32::from_be_bytes(len);
+    let mut back_links = Vec::with_capacity(len as usize);
+    for _ in 0..len {
+        let distance = read_u64(&mut bytes).map_err(backend_error)?;
+        let block_hash = HeaderHash::deserialize(&mut bytes).map_err(backend_error)?;
+        back_links.push(BackLink {
+            distance,
+            block_hash,
+        });
+    }
+
+    Ok(BlockInfo {
+        block_hash,
+        depth,
+        back_links,
+    })
+}
+
+fn read_u64(bytes: &mut &[u8]) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    bytes.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
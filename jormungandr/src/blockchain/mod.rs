@@ -1,17 +1,40 @@
+mod archive;
 mod branch;
+mod candidate;
 mod chain;
+mod chain_selection;
+mod events;
+mod kv_store;
 mod multiverse;
+mod orphans;
 mod process;
 mod reference;
 mod reference_cache;
+mod snapshot;
 mod storage;
+#[cfg(test)]
+pub mod test_utils;
+mod verify;
 
 pub use self::{
+    archive::{
+        copy_blocks, export as export_blocks, import as import_blocks, Error as ArchiveError,
+    },
     branch::{Branch, Branches},
+    candidate::CandidateForest,
     chain::{Blockchain, Error, ErrorKind, PreCheckedHeader},
-    multiverse::Multiverse,
-    process::handle_input,
+    chain_selection::{ComparisonResult, MAX_ROLLBACK_DEPTH},
+    events::{ChainEvent, ChainEvents},
+    kv_store::{Error as KvStoreError, KvBlockStore},
+    multiverse::{GCRoot, Multiverse},
+    orphans::OrphanPool,
+    process::{handle_input, process_new_ref, start_garbage_collector, TipUpdate},
     reference::Ref,
     reference_cache::RefCache,
+    snapshot::{Error as SnapshotError, Snapshot, Snapshots},
     storage::Storage,
+    verify::{
+        rewind_head, verify, Error as VerifyError, Inconsistency as StorageInconsistency,
+        Verification,
+    },
 };
@@ -1,12 +1,44 @@
-use crate::blockcfg::{ChainLength, HeaderHash, Ledger, Multiverse as MultiverseData};
-use chain_impl_mockchain::multiverse::GCRoot;
-use std::convert::Infallible;
+//! in memory states of the blockchain, one for each block
+//!
+//! Every state inserted in the [`Multiverse`] is associated with a
+//! [`GCRoot`]. A state is kept as long as at least one of its `GCRoot`s
+//! is alive (typically held by a [`Ref`]), the states whose `GCRoot`s
+//! have all been dropped are removed when the garbage collection runs.
+//!
+//! [`Multiverse`]: ./struct.Multiverse.html
+//! [`GCRoot`]: ./struct.GCRoot.html
+//! [`Ref`]: ./struct.Ref.html
+
+use crate::blockcfg::HeaderHash;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    ops::Deref,
+    sync::{Arc, RwLock},
+};
 use tokio::{prelude::*, sync::lock::Lock};
 
 pub struct Multiverse<T> {
     inner: Lock<MultiverseData<T>>,
 }
 
+struct MultiverseData<T> {
+    states: HashMap<HeaderHash, T>,
+    roots: Arc<RwLock<Roots>>,
+}
+
+/// number of alive `GCRoot`s for each state of the multiverse
+type Roots = HashMap<HeaderHash, usize>;
+
+/// keep the state associated to the given block hash alive in the
+/// [`Multiverse`] until dropped.
+///
+/// [`Multiverse`]: ./struct.Multiverse.html
+pub struct GCRoot {
+    hash: HeaderHash,
+    roots: Arc<RwLock<Roots>>,
+}
+
 impl<T> Multiverse<T> {
     pub fn new() -> Self {
         Multiverse {
@@ -14,37 +46,40 @@ impl<T> Multiverse<T> {
         }
     }
 
+    /// return a future that will insert the given state and return
+    /// a new `GCRoot` for it.
+    ///
+    /// If a state is already known for this block hash it is kept
+    /// and the given `value` is dropped.
+    ///
     pub fn insert(
         &self,
-        chain_length: ChainLength,
         hash: HeaderHash,
         value: T,
     ) -> impl Future<Item = GCRoot, Error = Infallible> {
         let mut inner = self.inner.clone();
 
         future::poll_fn(move || Ok(inner.poll_lock()))
-            .map(move |mut guard| guard.insert(chain_length, hash, value))
+            .map(move |mut guard| guard.insert(hash, value))
     }
-}
 
-impl<T: Clone> Multiverse<T> {
-    pub fn get(&self, hash: HeaderHash) -> impl Future<Item = Option<T>, Error = Infallible> {
+    /// run the garbage collection of the multiverse: every state
+    /// without an alive `GCRoot` is removed.
+    ///
+    /// The future returns the number of states collected.
+    ///
+    pub fn purge(&self) -> impl Future<Item = usize, Error = Infallible> {
         let mut inner = self.inner.clone();
 
-        future::poll_fn(move || Ok(inner.poll_lock())).map(move |guard| guard.get(&hash).cloned())
+        future::poll_fn(move || Ok(inner.poll_lock())).map(|mut guard| guard.gc())
     }
 }
 
-impl Multiverse<Ledger> {
-    /// run the garbage collection of the multiverse
-    ///
-    /// TODO: this function is only working for the `Ledger` at the moment
-    ///       we need to generalize the `chain_impl_mockchain` to handle
-    ///       the garbage collection for any `T`
-    pub fn purge(&self) -> impl Future<Item = (), Error = Infallible> {
+impl<T: Clone> Multiverse<T> {
+    pub fn get(&self, hash: HeaderHash) -> impl Future<Item = Option<T>, Error = Infallible> {
         let mut inner = self.inner.clone();
 
-        future::poll_fn(move || Ok(inner.poll_lock())).map(|mut guard| guard.gc())
+        future::poll_fn(move || Ok(inner.poll_lock())).map(move |guard| guard.get(&hash).cloned())
     }
 }
 
@@ -55,3 +90,59 @@ impl<T> Clone for Multiverse<T> {
         }
     }
 }
+
+impl<T> MultiverseData<T> {
+    fn new() -> Self {
+        MultiverseData {
+            states: HashMap::new(),
+            roots: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    fn insert(&mut self, hash: HeaderHash, value: T) -> GCRoot {
+        self.states.entry(hash).or_insert(value);
+        GCRoot::new(hash, self.roots.clone())
+    }
+
+    fn get(&self, hash: &HeaderHash) -> Option<&T> {
+        self.states.get(hash)
+    }
+
+    fn gc(&mut self) -> usize {
+        let roots = self.roots.read().unwrap();
+        let before = self.states.len();
+        self.states.retain(|hash, _| roots.contains_key(hash));
+        before - self.states.len()
+    }
+}
+
+impl GCRoot {
+    fn new(hash: HeaderHash, roots: Arc<RwLock<Roots>>) -> Self {
+        *roots.write().unwrap().entry(hash).or_insert(0) += 1;
+        GCRoot { hash, roots }
+    }
+}
+
+impl Deref for GCRoot {
+    type Target = HeaderHash;
+
+    fn deref(&self) -> &Self::Target {
+        &self.hash
+    }
+}
+
+impl Drop for GCRoot {
+    fn drop(&mut self) {
+        let mut roots = self.roots.write().unwrap();
+        let remove = match roots.get_mut(&self.hash) {
+            Some(count) => {
+                *count -= 1;
+                *count == 0
+            }
+            None => false,
+        };
+        if remove {
+            roots.remove(&self.hash);
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use crate::blockcfg::{Block, HeaderHash};
+use std::{collections::HashMap, convert::Infallible, time::Duration};
+use tokio::{
+    prelude::*,
+    sync::lock::Lock,
+    timer::{self, delay_queue, DelayQueue},
+};
+
+/// default time an orphan block is kept in the [`OrphanPool`] waiting
+/// for its parent.
+///
+/// [`OrphanPool`]: ./struct.OrphanPool.html
+pub const DEFAULT_ORPHAN_BLOCK_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// default maximum number of blocks held in the [`OrphanPool`]
+///
+/// [`OrphanPool`]: ./struct.OrphanPool.html
+pub const DEFAULT_MAX_ORPHAN_BLOCKS: usize = 256;
+
+/// object that holds the blocks received from the network whose parent
+/// is not known locally yet: neither in the storage nor as a candidate
+/// in the [`CandidateForest`].
+///
+/// The pool is bounded: once the maximum number of blocks is reached the
+/// new orphan blocks are not held. The blocks are held for a limited
+/// time, the expired blocks are removed once [`purge`] has been called
+/// and has completed (or to make room for a new orphan block).
+///
+/// [`CandidateForest`]: ./struct.CandidateForest.html
+/// [`purge`]: ./struct.OrphanPool.html#method.purge
+#[derive(Clone)]
+pub struct OrphanPool {
+    inner: Lock<OrphanPoolData>,
+}
+
+struct OrphanPoolData {
+    blocks: HashMap<HeaderHash, (Block, delay_queue::Key)>,
+    /// index of the orphan blocks by the hash of their missing parent
+    by_parent: HashMap<HeaderHash, Vec<HeaderHash>>,
+    expirations: DelayQueue<HeaderHash>,
+
+    ttl: Duration,
+    max_entries: usize,
+}
+
+impl OrphanPool {
+    /// create a new `OrphanPool` holding at most `max_entries` blocks
+    /// for the given expiration `Duration`.
+    ///
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        OrphanPool {
+            inner: Lock::new(OrphanPoolData::new(ttl, max_entries)),
+        }
+    }
+
+    /// return a future that will attempt to hold the given block
+    /// in the pool.
+    ///
+    /// The future returns `false` if the pool is full and the block
+    /// could not be held.
+    ///
+    pub fn insert(&self, block: Block) -> impl Future<Item = bool, Error = Infallible> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock())).map(move |mut guard| guard.insert(block))
+    }
+
+    /// return a future that will remove from the pool and return the
+    /// blocks whose parent is the block `parent`.
+    ///
+    pub fn take_children(
+        &self,
+        parent: HeaderHash,
+    ) -> impl Future<Item = Vec<Block>, Error = Infallible> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock()))
+            .map(move |mut guard| guard.take_children(&parent))
+    }
+
+    /// return a future that will filter out the hashes of the blocks
+    /// already held in the pool.
+    ///
+    pub fn filter_not_held(
+        &self,
+        hashes: Vec<HeaderHash>,
+    ) -> impl Future<Item = Vec<HeaderHash>, Error = Infallible> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock())).map(move |guard| {
+            hashes
+                .into_iter()
+                .filter(|hash| !guard.blocks.contains_key(hash))
+                .collect()
+        })
+    }
+
+    /// return a future that will remove every expired block from the pool
+    ///
+    pub fn purge(&self) -> impl Future<Item = (), Error = timer::Error> {
+        let mut inner = self.inner.clone();
+
+        future::poll_fn(move || Ok(inner.poll_lock()))
+            .and_then(|mut guard| future::poll_fn(move || guard.poll_purge()))
+    }
+}
+
+impl OrphanPoolData {
+    fn new(ttl: Duration, max_entries: usize) -> Self {
+        OrphanPoolData {
+            blocks: HashMap::new(),
+            by_parent: HashMap::new(),
+            expirations: DelayQueue::new(),
+            ttl,
+            max_entries,
+        }
+    }
+
+    fn insert(&mut self, block: Block) -> bool {
+        let hash = block.header.hash();
+        if self.blocks.contains_key(&hash) {
+            return true;
+        }
+
+        if self.blocks.len() >= self.max_entries {
+            // make room by removing the blocks that have already expired,
+            // without waiting for the ones still pending
+            let _ = self.poll_purge();
+            if self.blocks.len() >= self.max_entries {
+                return false;
+            }
+        }
+
+        let parent_hash = block.header.block_parent_hash().clone();
+        let expiration_key = self.expirations.insert(hash.clone(), self.ttl);
+        self.blocks.insert(hash.clone(), (block, expiration_key));
+        self.by_parent
+            .entry(parent_hash)
+            .or_insert_with(Vec::new)
+            .push(hash);
+        true
+    }
+
+    fn take_children(&mut self, parent: &HeaderHash) -> Vec<Block> {
+        let children = match self.by_parent.remove(parent) {
+            None => return Vec::new(),
+            Some(children) => children,
+        };
+
+        let mut blocks = Vec::with_capacity(children.len());
+        for child in children {
+            if let Some((block, expiration_key)) = self.blocks.remove(&child) {
+                self.expirations.remove(&expiration_key);
+                blocks.push(block);
+            }
+        }
+        blocks
+    }
+
+    fn remove_expired(&mut self, hash: &HeaderHash) {
+        if let Some((block, _)) = self.blocks.remove(hash) {
+            let parent_hash = block.header.block_parent_hash();
+            if let Some(siblings) = self.by_parent.get_mut(parent_hash) {
+                siblings.retain(|sibling| sibling != hash);
+                if siblings.is_empty() {
+                    self.by_parent.remove(parent_hash);
+                }
+            }
+        }
+    }
+
+    fn poll_purge(&mut self) -> Poll<(), timer::Error> {
+        loop {
+            match self.expirations.poll()? {
+                Async::NotReady => return Ok(Async::Ready(())),
+                Async::Ready(None) => return Ok(Async::Ready(())),
+                Async::Ready(Some(entry)) => {
+                    self.remove_expired(entry.get_ref());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::test_utils::{block0, child};
+    use std::time::Instant;
+    use tokio::{runtime::current_thread::Runtime, timer::Delay};
+
+    #[test]
+    fn full_pool_refuses_new_blocks() {
+        let mut runtime = Runtime::new().unwrap();
+        let pool = OrphanPool::new(DEFAULT_ORPHAN_BLOCK_TTL, 2);
+        let block0 = block0();
+        let orphans: Vec<Block> = (1..=3).map(|slot_id| child(&block0, slot_id)).collect();
+
+        assert!(runtime.block_on(pool.insert(orphans[0].clone())).unwrap());
+        assert!(runtime.block_on(pool.insert(orphans[1].clone())).unwrap());
+        assert!(!runtime.block_on(pool.insert(orphans[2].clone())).unwrap());
+        // a block already held is not refused
+        assert!(runtime.block_on(pool.insert(orphans[0].clone())).unwrap());
+
+        let children = runtime
+            .block_on(pool.take_children(block0.header.hash()))
+            .unwrap();
+        assert_eq!(children.len(), 2);
+        assert!(runtime.block_on(pool.insert(orphans[2].clone())).unwrap());
+    }
+
+    #[test]
+    fn expired_blocks_are_purged() {
+        let mut runtime = Runtime::new().unwrap();
+        let pool = OrphanPool::new(Duration::from_millis(10), 1);
+        let block0 = block0();
+        let orphan = child(&block0, 1);
+        let other_orphan = child(&block0, 2);
+
+        assert!(runtime.block_on(pool.insert(orphan.clone())).unwrap());
+        runtime
+            .block_on(Delay::new(Instant::now() + Duration::from_millis(50)))
+            .unwrap();
+
+        // the expired block makes room for a new orphan block
+        assert!(runtime.block_on(pool.insert(other_orphan.clone())).unwrap());
+        let held = runtime
+            .block_on(pool.filter_not_held(vec![orphan.header.hash(), other_orphan.header.hash()]))
+            .unwrap();
+        assert_eq!(held, vec![orphan.header.hash()]);
+
+        runtime
+            .block_on(Delay::new(Instant::now() + Duration::from_millis(50)))
+            .unwrap();
+        runtime.block_on(pool.purge()).unwrap();
+        let children = runtime
+            .block_on(pool.take_children(block0.header.hash()))
+            .unwrap();
+        assert!(children.is_empty());
+    }
+}
@@ -1,6 +1,11 @@
-use super::{Blockchain, Branch, Error, ErrorKind, PreCheckedHeader, Ref};
+use super::{
+    chain::{epoch_leadership_ledger, MAIN_BRANCH_TAG},
+    chain_selection, Blockchain, Branch, ChainEvent, ComparisonResult, Error, ErrorKind,
+    PreCheckedHeader, Ref, Snapshots, Storage,
+};
 use crate::{
-    blockcfg::{Block, Epoch, Header, HeaderHash},
+    blockcfg::{Block, Epoch, Fragment, FragmentId, Header, HeaderHash},
+    fragment,
     intercom::{self, BlockMsg, NetworkMsg, PropagateMsg},
     leadership::NewEpochToSchedule,
     network::p2p::topology::NodeId,
@@ -14,9 +19,13 @@ use chain_core::property::{Block as _, HasHeader as _};
 
 use futures::future::Either;
 use slog::Logger;
-use tokio::{prelude::*, sync::mpsc::Sender};
+use tokio::{prelude::*, sync::mpsc::Sender, timer::Interval};
 
-use std::convert::identity;
+use std::{
+    collections::HashSet,
+    convert::{identity, Infallible},
+    time::Duration,
+};
 
 pub fn handle_input(
     info: &TokioServiceInfo,
@@ -25,6 +34,7 @@ pub fn handle_input(
     _stats_counter: &StatsCounter,
     new_epoch_announcements: &mut Sender<NewEpochToSchedule>,
     network_msg_box: &mut MessageBox<NetworkMsg>,
+    fragment_pool: &mut fragment::Pool,
     input: Input<BlockMsg>,
 ) -> Result<(), ()> {
     let bquery = match input {
@@ -57,13 +67,14 @@ pub fn handle_input(
         BlockMsg::LeadershipBlock(block) => {
             let future = process_leadership_block(info.logger(), blockchain.clone(), block);
             let new_block_ref = future.wait().unwrap();
-            let header = new_block_ref.header().clone();
-            blockchain_tip.update_ref(new_block_ref).wait().unwrap();
-            network_msg_box
-                .try_send(NetworkMsg::Propagate(PropagateMsg::Block(header)))
-                .unwrap_or_else(|err| {
-                    error!(info.logger(), "cannot propagate block to network: {}", err)
-                });
+            process_and_propagate_new_ref(
+                info.logger(),
+                blockchain.clone(),
+                blockchain_tip.clone(),
+                new_block_ref,
+                network_msg_box,
+                fragment_pool,
+            );
         }
         BlockMsg::AnnouncedBlock(header, node_id) => {
             let future = process_block_announcement(
@@ -77,31 +88,416 @@ pub fn handle_input(
             future.wait().unwrap();
         }
         BlockMsg::NetworkBlock(block, reply) => {
-            let future = process_network_block(blockchain.clone(), block, info.logger().clone());
+            let future = process_network_block(
+                blockchain.clone(),
+                blockchain_tip.clone(),
+                block,
+                network_msg_box.clone(),
+                info.logger().clone(),
+            );
             match future.wait() {
                 Err(e) => {
                     reply.reply_error(network_block_error_into_reply(e));
                 }
                 Ok(maybe_updated) => {
                     if let Some(new_block_ref) = maybe_updated {
-                        let header = new_block_ref.header().clone();
-                        blockchain_tip.update_ref(new_block_ref).wait().unwrap();
+                        let applied = new_block_ref.hash();
+                        process_and_propagate_new_ref(
+                            info.logger(),
+                            blockchain.clone(),
+                            blockchain_tip.clone(),
+                            new_block_ref,
+                            network_msg_box,
+                            fragment_pool,
+                        );
+                        process_held_descendants(
+                            info.logger(),
+                            blockchain.clone(),
+                            blockchain_tip.clone(),
+                            applied,
+                            network_msg_box,
+                            fragment_pool,
+                        );
+                    }
+                    reply.reply_ok(());
+                }
+            }
+        }
+        BlockMsg::ChainHeaders(headers, reply) => {
+            let future = process_chain_headers_into_block_request(
+                blockchain.clone(),
+                stream::iter_ok(headers),
+                info.logger().clone(),
+            );
+            match future.wait() {
+                Err(e) => {
+                    warn!(
+                        info.logger(),
+                        "rejecting chain headers";
+                        "reason" => e.to_string()
+                    );
+                    reply.reply_error(network_block_error_into_reply(e));
+                }
+                Ok(block_ids) => {
+                    if !block_ids.is_empty() {
                         network_msg_box
-                            .try_send(NetworkMsg::Propagate(PropagateMsg::Block(header)))
+                            .try_send(NetworkMsg::GetBlocks(block_ids))
                             .unwrap_or_else(|err| {
-                                error!(info.logger(), "cannot propagate block to network: {}", err)
+                                error!(
+                                    info.logger(),
+                                    "cannot send GetBlocks request to network: {}", err
+                                )
                             });
                     }
                     reply.reply_ok(());
                 }
             }
         }
-        BlockMsg::ChainHeaders(headers, reply) => unimplemented!(),
     };
 
     Ok(())
 }
 
+/// process the new `Ref` and propagate its header to the network
+/// if it became the new tip of the blockchain.
+///
+/// If the tip switched to a competing branch, the fragments of the
+/// abandoned blocks are put back in the fragment pool.
+///
+/// The changes of the blockchain are published on its `ChainEvents`.
+fn process_and_propagate_new_ref(
+    logger: &Logger,
+    blockchain: Blockchain,
+    blockchain_tip: Branch,
+    new_block_ref: Ref,
+    network_msg_box: &mut MessageBox<NetworkMsg>,
+    fragment_pool: &mut fragment::Pool,
+) {
+    let header = new_block_ref.header().clone();
+    let logger = logger.new(o!("hash" => header.hash().to_string()));
+    let snapshots = blockchain.snapshots().cloned();
+    let storage = blockchain.storage().clone();
+    let events = blockchain.events().clone();
+    let old_epoch = blockchain_tip
+        .get_ref()
+        .wait()
+        .unwrap_or_else(|_: Infallible| unreachable!())
+        .block_date()
+        .epoch;
+    let future = process_new_ref(
+        logger.clone(),
+        blockchain,
+        blockchain_tip,
+        new_block_ref.clone(),
+    );
+    let tip_update = match future.wait() {
+        Err(err) => {
+            error!(logger, "cannot process new block reference: {}", err);
+            return;
+        }
+        Ok(tip_update) => tip_update,
+    };
+
+    events.publish(ChainEvent::BlockApplied {
+        hash: new_block_ref.hash(),
+        chain_length: new_block_ref.chain_length(),
+        date: *new_block_ref.block_date(),
+    });
+    match tip_update {
+        TipUpdate::Unchanged => {}
+        tip_update => {
+            if let TipUpdate::Switched {
+                ancestor,
+                old_tip,
+                rollback,
+            } = tip_update
+            {
+                if rollback > 0 {
+                    events.publish(ChainEvent::Rollback {
+                        depth: rollback,
+                        ancestor,
+                        old_tip,
+                    });
+                    revert_abandoned_fragments(
+                        &logger,
+                        &storage,
+                        fragment_pool,
+                        ancestor,
+                        old_tip,
+                        new_block_ref.hash(),
+                    );
+                }
+            }
+            events.publish(ChainEvent::TipChanged {
+                hash: new_block_ref.hash(),
+                chain_length: new_block_ref.chain_length(),
+                date: *new_block_ref.block_date(),
+            });
+            let epoch = new_block_ref.block_date().epoch;
+            if epoch > old_epoch {
+                events.publish(ChainEvent::EpochStarted { epoch });
+            }
+            network_msg_box
+                .try_send(NetworkMsg::Propagate(PropagateMsg::Block(header)))
+                .unwrap_or_else(|err| error!(logger, "cannot propagate block to network: {}", err));
+            if let Some(snapshots) = snapshots {
+                take_snapshot(&logger, &snapshots, &new_block_ref);
+            }
+        }
+    }
+}
+
+/// put back in the fragment pool the fragments of the blocks between
+/// `ancestor` and `old_tip` that are not also in the blocks between
+/// `ancestor` and `new_tip`.
+fn revert_abandoned_fragments(
+    logger: &Logger,
+    storage: &Storage,
+    fragment_pool: &mut fragment::Pool,
+    ancestor: HeaderHash,
+    old_tip: HeaderHash,
+    new_tip: HeaderHash,
+) {
+    use chain_core::property::Fragment as _;
+
+    let blocks_to = |to| {
+        storage
+            .stream_from_to(ancestor, to)
+            .and_then(|stream| match stream {
+                None => Either::A(future::ok(Vec::new())),
+                Some(stream) => Either::B(stream.collect()),
+            })
+    };
+    let (abandoned, adopted) = match blocks_to(old_tip).join(blocks_to(new_tip)).wait() {
+        Err(err) => {
+            warn!(
+                logger,
+                "cannot retrieve the rolled back blocks, their fragments are not put back in the pool";
+                "reason" => err.to_string()
+            );
+            return;
+        }
+        Ok(blocks) => blocks,
+    };
+
+    let adopted: HashSet<FragmentId> = adopted
+        .iter()
+        .flat_map(|block| block.contents.iter().map(|fragment| fragment.id()))
+        .collect();
+
+    for block in abandoned {
+        let hash = block.header.hash();
+        let fragments: Vec<Fragment> = block
+            .contents
+            .iter()
+            .filter(|fragment| !adopted.contains(&fragment.id()))
+            .cloned()
+            .collect();
+        if fragments.is_empty() {
+            continue;
+        }
+        debug!(
+            logger,
+            "putting the fragments of a rolled back block back in the pool";
+            "block" => hash.to_string(),
+            "fragments" => fragments.len(),
+        );
+        fragment_pool
+            .revert(hash, fragments)
+            .wait()
+            .unwrap_or_else(|()| {
+                warn!(
+                    logger,
+                    "cannot put the rolled back fragments back in the pool"
+                )
+            });
+    }
+}
+
+fn take_snapshot(logger: &Logger, snapshots: &Snapshots, new_tip: &Ref) {
+    if !snapshots.should_take(new_tip) {
+        return;
+    }
+    match snapshots.save(new_tip) {
+        Ok(()) => info!(logger, "ledger snapshot saved"),
+        Err(err) => warn!(logger, "cannot save the ledger snapshot"; "reason" => err.to_string()),
+    }
+}
+
+/// apply the blocks held in the `CandidateForest` or in the `OrphanPool`
+/// that were waiting for the block `applied` to be applied, then for their
+/// own descendants.
+fn process_held_descendants(
+    logger: &Logger,
+    blockchain: Blockchain,
+    blockchain_tip: Branch,
+    applied: HeaderHash,
+    network_msg_box: &mut MessageBox<NetworkMsg>,
+    fragment_pool: &mut fragment::Pool,
+) {
+    let candidates = blockchain.candidates().clone();
+    let orphans = blockchain.orphans().clone();
+    let mut applied_blocks = vec![applied];
+
+    while let Some(parent_hash) = applied_blocks.pop() {
+        let blocks = candidates
+            .take_ready_children(parent_hash.clone())
+            .join(orphans.take_children(parent_hash))
+            .map(|(mut blocks, orphan_blocks)| {
+                blocks.extend(orphan_blocks);
+                blocks
+            })
+            .wait()
+            .unwrap_or_else(|_: Infallible| unreachable!());
+        for block in blocks {
+            let hash = block.header.hash();
+            let future = process_network_block(
+                blockchain.clone(),
+                blockchain_tip.clone(),
+                block,
+                network_msg_box.clone(),
+                logger.clone(),
+            );
+            match future.wait() {
+                Err(err) => {
+                    warn!(
+                        logger,
+                        "held block is not valid, discarding its descendants";
+                        "hash" => hash.to_string(),
+                        "reason" => err.to_string()
+                    );
+                    candidates
+                        .remove_descendants(hash)
+                        .wait()
+                        .unwrap_or_else(|_: Infallible| unreachable!());
+                }
+                Ok(None) => applied_blocks.push(hash),
+                Ok(Some(new_block_ref)) => {
+                    process_and_propagate_new_ref(
+                        logger,
+                        blockchain.clone(),
+                        blockchain_tip.clone(),
+                        new_block_ref,
+                        network_msg_box,
+                        fragment_pool,
+                    );
+                    applied_blocks.push(hash);
+                }
+            }
+        }
+    }
+}
+
+/// how the tip of the blockchain moved after [`process_new_ref`]
+///
+/// [`process_new_ref`]: ./fn.process_new_ref.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TipUpdate {
+    /// the tip did not move, the new `Ref` is on a side branch
+    Unchanged,
+    /// the new `Ref` is a child of the tip
+    Extended,
+    /// the tip switched to another branch: the `rollback` blocks after
+    /// `ancestor` up to `old_tip` are no longer on the main branch
+    Switched {
+        ancestor: HeaderHash,
+        old_tip: HeaderHash,
+        rollback: u64,
+    },
+}
+
+/// process a newly applied block `Ref` against the current tip of the
+/// blockchain.
+///
+/// The candidate is compared against the current tip with the rules
+/// described in the `chain_selection` module. If the candidate is
+/// preferred, the tip is moved to the candidate and the previous tip
+/// is kept in the branches so the node can switch back to it later.
+/// Otherwise the candidate is kept as a (new or updated) side branch.
+/// Once the tip moved, the side branches too far behind it to ever be
+/// switched to are pruned.
+///
+/// The future returns how the tip has been updated.
+pub fn process_new_ref(
+    logger: Logger,
+    mut blockchain: Blockchain,
+    mut tip: Branch,
+    candidate: Ref,
+) -> impl Future<Item = TipUpdate, Error = Error> {
+    let mut storage = blockchain.storage().clone();
+    let mut branches = blockchain.branches().clone();
+    let candidate_hash = candidate.hash();
+    let candidate_length = candidate.chain_length();
+
+    tip.get_ref()
+        .map_err(|_: Infallible| unreachable!())
+        .and_then(move |tip_ref| {
+            let storage = blockchain.storage().clone();
+            chain_selection::compare_against(&storage, &tip_ref, &candidate)
+                .map_err(|e| e.into())
+                .and_then(move |comparison| {
+                    let is_child_of_tip = candidate.block_parent_hash() == &tip_ref.hash();
+                    match comparison {
+                        ComparisonResult::PreferCurrent => {
+                            debug!(
+                                logger,
+                                "current tip is preferred, keeping the block on a side branch"
+                            );
+                            let future = blockchain
+                                .branches_mut()
+                                .apply_or_create(candidate)
+                                .map(|_| TipUpdate::Unchanged);
+                            Either::A(future)
+                        }
+                        ComparisonResult::PreferCandidate { .. } if is_child_of_tip => {
+                            debug!(logger, "extending the current tip");
+                            Either::B(Either::A(
+                                tip.update_ref(candidate).map(|_| TipUpdate::Extended),
+                            ))
+                        }
+                        ComparisonResult::PreferCandidate { rollback, ancestor } => {
+                            info!(
+                                logger,
+                                "switching the tip to a competing branch";
+                                "rollback" => rollback,
+                            );
+                            let future = blockchain
+                                .branches_mut()
+                                .apply_or_create(candidate.clone())
+                                .and_then(move |mut side_branch| {
+                                    tip.update_ref(candidate).and_then(move |old_tip| {
+                                        let old_tip_hash = old_tip.hash();
+                                        side_branch.update_ref(old_tip).map(move |_| {
+                                            TipUpdate::Switched {
+                                                ancestor,
+                                                old_tip: old_tip_hash,
+                                                rollback,
+                                            }
+                                        })
+                                    })
+                                });
+                            Either::B(Either::B(future))
+                        }
+                    }
+                    .map_err(|_: Infallible| unreachable!())
+                })
+        })
+        .and_then(move |tip_update| match tip_update {
+            TipUpdate::Unchanged => Either::B(future::ok(tip_update)),
+            _ => Either::A(
+                storage
+                    .put_tag(MAIN_BRANCH_TAG.to_owned(), candidate_hash)
+                    .map_err(|e| e.into())
+                    .and_then(move |()| {
+                        branches
+                            .prune(candidate_length)
+                            .map_err(|_: Infallible| unreachable!())
+                    })
+                    .map(move |()| tip_update),
+            ),
+        })
+}
+
 pub fn handle_end_of_epoch(
     logger: Logger,
     new_epoch_announcements: Sender<NewEpochToSchedule>,
@@ -114,6 +510,7 @@ pub fn handle_end_of_epoch(
         .get_ref()
         .map_err(|_: std::convert::Infallible| unreachable!())
         .and_then(move |ref_tip| {
+            let epoch_ledger = epoch_leadership_ledger(&ref_tip, epoch);
             let (new_schedule, new_parameters, time_frame, _) =
                 blockchain.new_epoch_leadership_from(epoch, ref_tip);
 
@@ -121,6 +518,7 @@ pub fn handle_end_of_epoch(
                 .send(NewEpochToSchedule {
                     new_schedule,
                     new_parameters,
+                    epoch_ledger,
                     time_frame: (*time_frame).clone(),
                 })
                 .map_err(move |_err| {
@@ -193,7 +591,11 @@ pub fn process_block_announcement(
                 let to = header.hash();
                 Either::B(blockchain.get_checkpoints(branch).map(move |from| {
                     network_msg_box
-                        .try_send(NetworkMsg::PullHeaders { node_id, from, to })
+                        .try_send(NetworkMsg::PullHeaders {
+                            node_id: Some(node_id),
+                            from,
+                            to,
+                        })
                         .unwrap_or_else(move |err| {
                             error!(
                                 logger,
@@ -220,12 +622,24 @@ pub fn process_block_announcement(
         })
 }
 
+/// process a block received from the network.
+///
+/// If the block's parent is not applied yet, the block is either held
+/// with its pre-validated header in the `CandidateForest` or held as an
+/// orphan block in the `OrphanPool`. In the latter case the headers of
+/// the missing ancestors are requested to the network. In both cases the
+/// future returns `None` and the block will be applied once its parent
+/// has been applied.
 pub fn process_network_block(
     mut blockchain: Blockchain,
+    branch: Branch,
     block: Block,
+    network_msg_box: MessageBox<NetworkMsg>,
     logger: Logger,
 ) -> impl Future<Item = Option<Ref>, Error = Error> {
     let mut end_blockchain = blockchain.clone();
+    let orphan_blockchain = blockchain.clone();
+    let candidates = blockchain.candidates().clone();
     let header = block.header();
     blockchain
         .pre_check_header(header)
@@ -235,10 +649,27 @@ pub fn process_network_block(
                 Either::A(future::ok(None))
             }
             PreCheckedHeader::MissingParent { header, .. } => {
-                debug!(logger, "block is missing a locally stored parent");
-                Either::A(future::err(
-                    ErrorKind::MissingParentBlockFromStorage(header).into(),
-                ))
+                let hold_block = candidates
+                    .hold_block(block)
+                    .map_err(|_: Infallible| unreachable!())
+                    .and_then(move |not_held| match not_held {
+                        None => {
+                            debug!(logger, "block is held until its parent is applied");
+                            Either::A(future::ok(None))
+                        }
+                        Some(block) => {
+                            debug!(logger, "block is missing a locally stored parent");
+                            Either::B(hold_orphan_block(
+                                orphan_blockchain,
+                                branch,
+                                header,
+                                block,
+                                network_msg_box,
+                                logger,
+                            ))
+                        }
+                    });
+                Either::B(Either::A(hold_block))
             }
             PreCheckedHeader::HeaderWithCache { header, parent_ref } => {
                 let post_check_and_apply = blockchain
@@ -250,11 +681,51 @@ pub fn process_network_block(
                         debug!(logger, "block successfully applied");
                         Some(block_ref)
                     });
-                Either::B(post_check_and_apply)
+                Either::B(Either::B(post_check_and_apply))
             }
         })
 }
 
+/// hold the block whose parent is unknown in the `OrphanPool` and request
+/// the headers of its missing ancestors to the network.
+fn hold_orphan_block(
+    blockchain: Blockchain,
+    branch: Branch,
+    header: Header,
+    block: Block,
+    mut network_msg_box: MessageBox<NetworkMsg>,
+    logger: Logger,
+) -> impl Future<Item = Option<Ref>, Error = Error> {
+    blockchain
+        .orphans()
+        .insert(block)
+        .map_err(|_: Infallible| unreachable!())
+        .and_then(move |held| {
+            if !held {
+                warn!(logger, "orphan block pool is full, dropping the block");
+                return Either::A(future::err(
+                    ErrorKind::MissingParentBlockFromStorage(header).into(),
+                ));
+            }
+            let to = header.hash();
+            Either::B(blockchain.get_checkpoints(branch).map(move |from| {
+                network_msg_box
+                    .try_send(NetworkMsg::PullHeaders {
+                        node_id: None,
+                        from,
+                        to,
+                    })
+                    .unwrap_or_else(move |err| {
+                        error!(
+                            logger,
+                            "cannot send PullHeaders request to network: {}", err
+                        )
+                    });
+                None
+            }))
+        })
+}
+
 fn network_block_error_into_reply(err: Error) -> intercom::Error {
     use super::ErrorKind::*;
 
@@ -268,20 +739,30 @@ fn network_block_error_into_reply(err: Error) -> intercom::Error {
     }
 }
 
+/// pre-validate a chain of headers and store them in the `CandidateForest`.
+///
+/// The headers are expected in ascending order: each header either has
+/// its parent already applied on the blockchain or is a descendant of
+/// a previously accepted header. Only the headers whose parent `Ref`
+/// is available are verified against the leadership schedule, the
+/// others are verified when their block is applied.
+///
+/// The future returns the hashes of the blocks that need to be fetched.
 pub fn process_chain_headers_into_block_request<S>(
     mut blockchain: Blockchain,
     headers: S,
     logger: Logger,
 ) -> impl Future<Item = Vec<HeaderHash>, Error = Error>
 where
-    S: Stream<Item = Header>,
+    S: Stream<Item = Header, Error = Error>,
 {
+    let candidates = blockchain.candidates().clone();
+    let orphans = blockchain.orphans().clone();
+    let end_blockchain = blockchain.clone();
     headers
-        .map_err(|e| {
-            // TODO: map the incoming stream error to the result error
-            unimplemented!()
-        })
         .and_then(move |header| {
+            let candidates = candidates.clone();
+            let mut end_blockchain = end_blockchain.clone();
             blockchain
                 .pre_check_header(header)
                 .and_then(move |pre_checked| match pre_checked {
@@ -289,23 +770,70 @@ where
                         // The block is already present. This may happen
                         // if the peer has started from an earlier checkpoint
                         // than our tip, so ignore this and proceed.
-                        Ok(None)
+                        Either::A(future::ok(None))
                     }
                     PreCheckedHeader::MissingParent { header, .. } => {
-                        // TODO: this fails on the first header after the
-                        // immediate descendant of the local tip. Need branch storage
-                        // that would store the whole header chain without blocks,
-                        // so that the chain can be pre-validated first and blocks
-                        // fetched afterwards in arbitrary order.
-                        Err(ErrorKind::MissingParentBlockFromStorage(header).into())
+                        // The parent may be one of the headers previously
+                        // accepted in the candidate forest.
+                        let hash = header.hash();
+                        let future = candidates.insert_child(header).map(move |needs_block| {
+                            if needs_block {
+                                Some(hash)
+                            } else {
+                                None
+                            }
+                        });
+                        Either::B(Either::A(future))
                     }
                     PreCheckedHeader::HeaderWithCache { header, parent_ref } => {
-                        // TODO: limit the headers to the single epoch
-                        // before pausing to retrieve blocks.
-                        Ok(Some(header.hash()))
+                        let hash = header.hash();
+                        let future = end_blockchain
+                            .post_check_header(header.clone(), parent_ref)
+                            .and_then(move |_post_checked| candidates.insert_root(header))
+                            .map(move |needs_block| if needs_block { Some(hash) } else { None });
+                        Either::B(Either::B(future))
                     }
                 })
         })
         .filter_map(identity)
         .collect()
+        .and_then(move |block_ids| {
+            // the blocks held as orphans do not need to be fetched again
+            orphans
+                .filter_not_held(block_ids)
+                .map_err(|_: Infallible| unreachable!())
+        })
+        .map(move |block_ids| {
+            debug!(
+                logger,
+                "chain headers accepted";
+                "blocks_to_fetch" => block_ids.len()
+            );
+            block_ids
+        })
+}
+
+/// run the garbage collection of the blockchain's caches and of the
+/// `Multiverse` every `interval`, the number of ledger states collected
+/// is added to the node's stats.
+pub fn start_garbage_collector(
+    info: TokioServiceInfo,
+    blockchain: Blockchain,
+    interval: Duration,
+    stats_counter: StatsCounter,
+) -> impl Future<Item = (), Error = ()> {
+    let logger = info.logger().clone();
+    let error_logger = logger.clone();
+    Interval::new_interval(interval)
+        .for_each(move |_instant| {
+            let logger = logger.clone();
+            let stats_counter = stats_counter.clone();
+            blockchain.purge().map(move |collected| {
+                debug!(logger, "garbage collected the blockchain states"; "collected" => collected);
+                stats_counter.add_gc_collected_cnt(collected);
+            })
+        })
+        .map_err(move |error| {
+            error!(error_logger, "Cannot run the blockchain garbage collection" ; "reason" => error.to_string());
+        })
 }
@@ -1,7 +1,7 @@
-use crate::blockcfg::{
-    BlockDate, ChainLength, Header, HeaderHash, Leadership, Ledger, LedgerParameters,
+use crate::{
+    blockcfg::{BlockDate, ChainLength, Header, HeaderHash, Leadership, Ledger, LedgerParameters},
+    blockchain::GCRoot,
 };
-use chain_impl_mockchain::multiverse::GCRoot;
 use chain_time::TimeFrame;
 use std::sync::Arc;
 
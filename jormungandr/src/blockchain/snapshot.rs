@@ -0,0 +1,327 @@
+//! persistent snapshots of the ledger state
+//!
+//! Restarting a node requires to re-apply every block from the block0
+//! up to the `HEAD` of the storage. To avoid this, the blockchain task
+//! periodically writes on disk the state associated to its tip: the
+//! ledger, the ledger of the previous epoch's last block and the ledger
+//! used to compute the current epoch's leadership schedule.
+//!
+//! At start up the node resumes from the newest valid snapshot that is
+//! an ancestor of the `HEAD` and only re-applies the blocks after it.
+//!
+//! A snapshot file is made of:
+//!
+//! * the magic `JORMSNAP` and the format version (u32, big endian);
+//! * the hash of the block of the snapshot;
+//! * the hash of the last block of the previous epoch;
+//! * the 3 ledgers (ledger, previous epoch's ledger and the epoch
+//!   leadership's ledger), each prefixed by its length in bytes
+//!   (u64, big endian).
+
+use crate::{
+    blockcfg::{HeaderHash, Ledger},
+    blockchain::{chain::epoch_leadership_state, Ref},
+};
+use chain_core::property::{Deserialize, Serialize};
+use slog::Logger;
+use std::{
+    fs,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+
+const SNAPSHOT_MAGIC: &[u8; 8] = b"JORMSNAP";
+const SNAPSHOT_VERSION: u32 = 1;
+const SNAPSHOT_EXTENSION: &str = "snapshot";
+
+custom_error! {pub Error
+    Io { source: io::Error } = "I/O error while accessing the snapshot",
+    InvalidMagic = "the file is not a ledger snapshot",
+    UnsupportedVersion { version: u32 } = "unsupported snapshot format version {version}",
+    Encoding { reason: String } = "cannot encode or decode the snapshot: {reason}",
+    HashMismatch = "the block hash of the snapshot does not match its file name",
+}
+
+/// the state to save in order to re-create a blockchain [`Ref`]
+/// without re-applying the blocks before it.
+///
+/// [`Ref`]: ../struct.Ref.html
+pub struct Snapshot {
+    pub hash: HeaderHash,
+    pub ledger: Arc<Ledger>,
+    pub previous_epoch_hash: HeaderHash,
+    pub previous_epoch_ledger: Arc<Ledger>,
+    pub epoch_leadership_ledger: Arc<Ledger>,
+}
+
+/// a snapshot file in the snapshot directory
+pub struct SnapshotEntry {
+    pub chain_length: u32,
+    pub hash: HeaderHash,
+    path: PathBuf,
+}
+
+/// manage the snapshots directory: when to take a snapshot, writing
+/// the snapshots and keeping only the most recent ones.
+#[derive(Clone)]
+pub struct Snapshots {
+    dir: PathBuf,
+    interval: u32,
+    keep: usize,
+}
+
+impl Snapshot {
+    /// prepare the snapshot of the given `Ref`
+    ///
+    /// returns `None` if the `Ref` does not have a previous epoch
+    /// state (i.e. it is still in the block0's epoch), there is no
+    /// interest in making a snapshot of it.
+    pub fn from_ref(reference: &Ref) -> Option<Self> {
+        let previous_epoch = reference.last_ref_previous_epoch()?;
+
+        Some(Snapshot {
+            hash: reference.hash(),
+            ledger: reference.ledger().clone(),
+            previous_epoch_hash: previous_epoch.hash(),
+            previous_epoch_ledger: previous_epoch.ledger().clone(),
+            epoch_leadership_ledger: epoch_leadership_state(previous_epoch),
+        })
+    }
+
+    fn write<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        writer.write_all(SNAPSHOT_MAGIC)?;
+        writer.write_all(&SNAPSHOT_VERSION.to_be_bytes())?;
+        write_serialized(&mut writer, &self.hash)?;
+        write_serialized(&mut writer, &self.previous_epoch_hash)?;
+        write_serialized(&mut writer, self.ledger.as_ref())?;
+        write_serialized(&mut writer, self.previous_epoch_ledger.as_ref())?;
+        write_serialized(&mut writer, self.epoch_leadership_ledger.as_ref())?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn read<R: Read>(mut reader: R) -> Result<Self, Error> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != SNAPSHOT_MAGIC {
+            return Err(Error::InvalidMagic);
+        }
+        let mut version = [0; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_be_bytes(version);
+        if version != SNAPSHOT_VERSION {
+            return Err(Error::UnsupportedVersion { version });
+        }
+
+        Ok(Snapshot {
+            hash: read_deserialized(&mut reader)?,
+            previous_epoch_hash: read_deserialized(&mut reader)?,
+            ledger: Arc::new(read_deserialized(&mut reader)?),
+            previous_epoch_ledger: Arc::new(read_deserialized(&mut reader)?),
+            epoch_leadership_ledger: Arc::new(read_deserialized(&mut reader)?),
+        })
+    }
+}
+
+impl Snapshots {
+    /// create the snapshot manager, snapshots are taken every `interval`
+    /// blocks and only the `keep` most recent snapshots are kept in `dir`.
+    pub fn new(dir: PathBuf, interval: u32, keep: usize) -> Result<Self, io::Error> {
+        fs::create_dir_all(&dir)?;
+        Ok(Snapshots {
+            dir,
+            interval,
+            keep,
+        })
+    }
+
+    /// check if a snapshot needs to be taken for the given `Ref`
+    pub fn should_take(&self, reference: &Ref) -> bool {
+        let chain_length: u32 = reference.chain_length().into();
+        self.interval != 0 && chain_length % self.interval == 0
+    }
+
+    /// write the snapshot of the given `Ref` and remove the older
+    /// snapshots.
+    pub fn save(&self, reference: &Ref) -> Result<(), Error> {
+        let snapshot = match Snapshot::from_ref(reference) {
+            None => return Ok(()),
+            Some(snapshot) => snapshot,
+        };
+        let chain_length: u32 = reference.chain_length().into();
+        let path = self.path_of(chain_length, &snapshot.hash);
+
+        // write in a temporary file first so an interrupted write
+        // does not leave a truncated snapshot behind
+        let tmp_path = path.with_extension("tmp");
+        snapshot.write(BufWriter::new(fs::File::create(&tmp_path)?))?;
+        fs::rename(&tmp_path, &path)?;
+
+        for entry in self.list()?.into_iter().skip(self.keep) {
+            fs::remove_file(&entry.path)?;
+        }
+        Ok(())
+    }
+
+    /// list the snapshots available in the snapshot directory, the
+    /// most recent (in chain length) first.
+    pub fn list(&self) -> Result<Vec<SnapshotEntry>, Error> {
+        let mut entries = Vec::new();
+        for dir_entry in fs::read_dir(&self.dir)? {
+            let path = dir_entry?.path();
+            if let Some(entry) = SnapshotEntry::from_path(path) {
+                entries.push(entry);
+            }
+        }
+        entries.sort_by(|a, b| b.chain_length.cmp(&a.chain_length));
+        Ok(entries)
+    }
+
+    /// read the snapshot of the given entry
+    pub fn read(&self, entry: &SnapshotEntry) -> Result<Snapshot, Error> {
+        let file = fs::File::open(&entry.path)?;
+        let snapshot = Snapshot::read(BufReader::new(file))?;
+        if snapshot.hash != entry.hash {
+            return Err(Error::HashMismatch);
+        }
+        Ok(snapshot)
+    }
+
+    /// load the snapshot, returns `None` and logs the reason if the
+    /// snapshot file is not valid.
+    pub fn load(&self, entry: &SnapshotEntry, logger: &Logger) -> Option<Snapshot> {
+        match self.read(entry) {
+            Ok(snapshot) => Some(snapshot),
+            Err(error) => {
+                warn!(logger, "ignoring invalid snapshot";
+                    "path" => entry.path.display().to_string(),
+                    "reason" => error.to_string());
+                None
+            }
+        }
+    }
+
+    fn path_of(&self, chain_length: u32, hash: &HeaderHash) -> PathBuf {
+        self.dir
+            .join(format!("{:010}-{}", chain_length, hash))
+            .with_extension(SNAPSHOT_EXTENSION)
+    }
+}
+
+impl SnapshotEntry {
+    fn from_path(path: PathBuf) -> Option<Self> {
+        if path.extension()?.to_str()? != SNAPSHOT_EXTENSION {
+            return None;
+        }
+        let (chain_length, hash) = {
+            let stem = path.file_stem()?.to_str()?;
+            let mut parts = stem.splitn(2, '-');
+            let chain_length = parts.next()?.parse().ok()?;
+            let hash = parts.next()?.parse().ok()?;
+            (chain_length, hash)
+        };
+        Some(SnapshotEntry {
+            chain_length,
+            hash,
+            path,
+        })
+    }
+}
+
+fn write_serialized<W, T>(writer: &mut W, value: &T) -> Result<(), Error>
+where
+    W: Write,
+    T: Serialize,
+{
+    let bytes = value.serialize_as_vec().map_err(|err| Error::Encoding {
+        reason: err.to_string(),
+    })?;
+    writer.write_all(&(bytes.len() as u64).to_be_bytes())?;
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
+fn read_deserialized<R, T>(reader: &mut R) -> Result<T, Error>
+where
+    R: Read,
+    T: Deserialize,
+{
+    let mut len = [0; 8];
+    reader.read_exact(&mut len)?;
+    let mut bytes = vec![0; u64::from_be_bytes(len) as usize];
+    reader.read_exact(&mut bytes)?;
+    T::deserialize(bytes.as_slice()).map_err(|err| Error::Encoding {
+        reason: err.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::test_utils::{block0, child, ledger};
+
+    fn serialized(ledger: &Ledger) -> Vec<u8> {
+        ledger.serialize_as_vec().unwrap()
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let block0 = block0();
+        let block1 = child(&block0, 1);
+        let ledger = Arc::new(ledger());
+        let snapshot = Snapshot {
+            hash: block1.header.hash(),
+            ledger: ledger.clone(),
+            previous_epoch_hash: block0.header.hash(),
+            previous_epoch_ledger: ledger.clone(),
+            epoch_leadership_ledger: ledger.clone(),
+        };
+
+        let mut bytes = Vec::new();
+        snapshot.write(&mut bytes).unwrap();
+        let loaded = Snapshot::read(bytes.as_slice()).unwrap();
+
+        assert_eq!(loaded.hash, snapshot.hash);
+        assert_eq!(loaded.previous_epoch_hash, snapshot.previous_epoch_hash);
+        assert_eq!(serialized(&loaded.ledger), serialized(&ledger));
+        assert_eq!(
+            serialized(&loaded.previous_epoch_ledger),
+            serialized(&ledger)
+        );
+        assert_eq!(
+            serialized(&loaded.epoch_leadership_ledger),
+            serialized(&ledger)
+        );
+    }
+
+    #[test]
+    fn snapshot_invalid_magic() {
+        match Snapshot::read(&b"JORMBLKS\0\0\0\x01"[..]) {
+            Err(Error::InvalidMagic) => {}
+            _ => panic!("read a file which is not a snapshot"),
+        }
+    }
+
+    #[test]
+    fn snapshot_truncated() {
+        let ledger = Arc::new(ledger());
+        let block0 = block0();
+        let snapshot = Snapshot {
+            hash: block0.header.hash(),
+            ledger: ledger.clone(),
+            previous_epoch_hash: block0.header.hash(),
+            previous_epoch_ledger: ledger.clone(),
+            epoch_leadership_ledger: ledger,
+        };
+
+        let mut bytes = Vec::new();
+        snapshot.write(&mut bytes).unwrap();
+        bytes.truncate(bytes.len() - 1);
+
+        match Snapshot::read(bytes.as_slice()) {
+            Err(Error::Io { .. }) => {}
+            _ => panic!("read a truncated snapshot"),
+        }
+    }
+}
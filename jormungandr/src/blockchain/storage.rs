@@ -95,6 +95,21 @@ impl Storage {
         })
     }
 
+    pub fn get_block_info(
+        &self,
+        header_hash: HeaderHash,
+    ) -> impl Future<Item = Option<BlockInfo<HeaderHash>>, Error = StorageError> {
+        let mut inner = self.inner.clone();
+
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |guard| {
+            match guard.get_block_info(&header_hash) {
+                Err(StorageError::BlockNotFound) => future::ok(None),
+                Err(error) => future::err(error),
+                Ok(block_info) => future::ok(Some(block_info)),
+            }
+        })
+    }
+
     pub fn block_exists(
         &self,
         header_hash: HeaderHash,
@@ -110,6 +125,19 @@ impl Storage {
         })
     }
 
+    /// check if `ancestor` is an ancestor of `descendant`, the future returns
+    /// the distance between the 2 blocks if it is the case.
+    pub fn is_ancestor(
+        &self,
+        ancestor: HeaderHash,
+        descendant: HeaderHash,
+    ) -> impl Future<Item = Option<u64>, Error = StorageError> {
+        let mut inner = self.inner.clone();
+
+        future::poll_fn(move || Ok(inner.poll_lock()))
+            .and_then(move |guard| guard.is_ancestor(&ancestor, &descendant))
+    }
+
     pub fn put_block(&mut self, block: Block) -> impl Future<Item = (), Error = StorageError> {
         let mut inner = self.inner.clone();
 
@@ -147,6 +175,80 @@ impl Storage {
         })
     }
 
+    /// find the closest common ancestor of the blocks `current` and `candidate`.
+    ///
+    /// The future returns the hash of the common ancestor along with the
+    /// distance between `current` and that ancestor (i.e. the number of
+    /// blocks of the `current` branch that are not in the `candidate`
+    /// branch). If the ancestor is further than `max_distance` blocks
+    /// from `current`, the future returns `None`.
+    pub fn find_common_ancestor(
+        &self,
+        current: HeaderHash,
+        candidate: HeaderHash,
+        max_distance: u64,
+    ) -> impl Future<Item = Option<(HeaderHash, u64)>, Error = StorageError> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |store| {
+            let current_info = store.get_block_info(&current)?;
+            let candidate_info = store.get_block_info(&candidate)?;
+            let current_depth = current_info.depth;
+
+            // bring both blocks at the same depth first
+            let mut current_info = if current_info.depth > candidate_info.depth {
+                let distance = current_info.depth - candidate_info.depth;
+                if distance > max_distance {
+                    return Ok(None);
+                }
+                for_path_to_nth_ancestor(&*store, &current, distance, |_| {})?
+            } else {
+                current_info
+            };
+            let mut candidate_info = if candidate_info.depth > current_info.depth {
+                let distance = candidate_info.depth - current_info.depth;
+                for_path_to_nth_ancestor(&*store, &candidate, distance, |_| {})?
+            } else {
+                candidate_info
+            };
+
+            while current_info.block_hash != candidate_info.block_hash {
+                if current_depth - current_info.depth >= max_distance {
+                    return Ok(None);
+                }
+                current_info = store.get_block_info(&current_info.parent_id())?;
+                candidate_info = store.get_block_info(&candidate_info.parent_id())?;
+            }
+
+            let distance = current_depth - current_info.depth;
+            Ok(Some((current_info.block_hash, distance)))
+        })
+    }
+
+    /// get the hash of the ancestor of `descendant` at the given chain
+    /// length. The future returns `None` if the chain length of
+    /// `descendant` is lower than `chain_length`.
+    pub fn get_ancestor_at_chain_length(
+        &self,
+        descendant: HeaderHash,
+        chain_length: u32,
+    ) -> impl Future<Item = Option<HeaderHash>, Error = StorageError> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |store| {
+            let descendant_info = store.get_block_info(&descendant)?;
+            // the block0 is of chain length 0 and of depth 1
+            let depth = u64::from(chain_length) + 1;
+            if depth > descendant_info.depth {
+                return Ok(None);
+            }
+            if depth == descendant_info.depth {
+                return Ok(Some(descendant));
+            }
+            let distance = descendant_info.depth - depth;
+            let ancestor_info = for_path_to_nth_ancestor(&*store, &descendant, distance, |_| {})?;
+            Ok(Some(ancestor_info.block_hash))
+        })
+    }
+
     pub fn get_checkpoints(
         &self,
         tip: HeaderHash,
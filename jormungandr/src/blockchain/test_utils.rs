@@ -0,0 +1,93 @@
+//! helpers to build chains of blocks in the tests of the blockchain
+//! module. The blocks are signed by a BFT leader but are not valid
+//! against a ledger, they are only meant for the storage. A valid
+//! ledger is made from the documented example of the block0.
+
+use crate::{
+    blockcfg::{Block, BlockBuilder, BlockDate, HeaderHash, Leadership, Ledger},
+    blockchain::{Multiverse, Ref, Storage},
+};
+use chain_core::property::BlockId as _;
+use chain_crypto::{Ed25519, SecretKey};
+use chain_storage::{memory::MemoryBlockStore, store::BlockStore as _};
+use chain_time::{SlotDuration, TimeFrame, Timeline};
+use jormungandr_lib::interfaces::{block0_configuration_documented_example, Block0Configuration};
+use std::{sync::Arc, time::SystemTime};
+use tokio::prelude::*;
+
+/// make the block0 of a test chain
+pub fn block0() -> Block {
+    make_block(HeaderHash::zero(), 0, 0)
+}
+
+/// make a child of `parent`, `slot_id` distinguishes the siblings
+pub fn child(parent: &Block, slot_id: u32) -> Block {
+    let chain_length: u32 = parent.header.chain_length().into();
+    make_block(parent.header.hash(), chain_length + 1, slot_id)
+}
+
+/// make `length` blocks on top of `parent`, from the oldest to the newest
+pub fn branch(parent: &Block, length: usize, slot_id: u32) -> Vec<Block> {
+    let mut blocks: Vec<Block> = Vec::with_capacity(length);
+    for _ in 0..length {
+        let block = child(blocks.last().unwrap_or(parent), slot_id);
+        blocks.push(block);
+    }
+    blocks
+}
+
+/// make an in memory storage holding the given blocks, parents first
+pub fn storage<'a, I>(blocks: I) -> Storage
+where
+    I: IntoIterator<Item = &'a Block>,
+{
+    let mut store = MemoryBlockStore::new();
+    for block in blocks {
+        store.put_block(block).unwrap();
+    }
+    Storage::new(Box::new(store))
+}
+
+/// make the initial ledger of the documented example of the block0
+pub fn ledger() -> Ledger {
+    let configuration: Block0Configuration =
+        serde_yaml::from_str(&block0_configuration_documented_example()).unwrap();
+    let block0 = configuration.to_block();
+    Ledger::new(block0.header.hash(), block0.contents.iter()).unwrap()
+}
+
+/// make a `Ref` to the given block, its state is the initial ledger of
+/// the documented example of the block0 whatever the block
+pub fn reference(block: &Block) -> Ref {
+    reference_with_ledger(block, ledger())
+}
+
+/// make a `Ref` to the given block with the given ledger as its state
+pub fn reference_with_ledger(block: &Block, ledger: Ledger) -> Ref {
+    let leadership = Leadership::new(block.header.block_date().epoch, &ledger);
+    let parameters = leadership.ledger_parameters().clone();
+    let time_frame = TimeFrame::new(Timeline::new(SystemTime::now()), SlotDuration::from_secs(1));
+    let ledger = Arc::new(ledger);
+    let gc_root = Multiverse::new()
+        .insert(block.header.hash(), ledger.clone())
+        .wait()
+        .unwrap();
+    Ref::new(
+        gc_root,
+        ledger,
+        Arc::new(time_frame),
+        Arc::new(leadership),
+        Arc::new(parameters),
+        block.header.clone(),
+        None,
+    )
+}
+
+fn make_block(parent: HeaderHash, chain_length: u32, slot_id: u32) -> Block {
+    let mut builder = BlockBuilder::new();
+    builder
+        .date(BlockDate { epoch: 0, slot_id })
+        .parent(parent)
+        .chain_length(chain_length.into());
+    builder.make_bft_block(&SecretKey::<Ed25519>::generate(rand::thread_rng()))
+}
@@ -0,0 +1,248 @@
+//! offline verification of the block storage
+//!
+//! The blocks from the block0 to the `HEAD` of the storage are replayed
+//! on a fresh, in memory, blockchain: every header is verified again and
+//! every block is applied again on the ledger. The replay walks forward
+//! from the block0, keeping only the `Ref` of the last replayed block, and
+//! stops at the first inconsistency found. It reports the last good block,
+//! so the `HEAD` can be rewound to it.
+
+use crate::{
+    blockcfg::{Block, HeaderHash},
+    blockchain::{chain::MAIN_BRANCH_TAG, Blockchain, Error as BlockchainError, Ref, Storage},
+};
+use chain_storage::{error::Error as StorageError, memory::MemoryBlockStore};
+use slog::Logger;
+use std::{collections::BTreeMap, convert::Infallible, time::Duration};
+use tokio::prelude::*;
+
+custom_error! {pub Error
+    Storage { source: StorageError } = "cannot access the blocks in the storage",
+    Blockchain { source: BlockchainError } = "cannot load the block0",
+    NoHead = "the storage has no HEAD tag",
+    CannotRepair { source: Inconsistency } = "cannot find a good block to rewind the HEAD to",
+}
+
+custom_error! {pub Inconsistency
+    BrokenChain { hash: HeaderHash } = "block {hash} is not connected to the block0",
+    MissingBlock { hash: HeaderHash } = "block {hash} is referenced but is missing from the storage",
+    InvalidBlock { hash: HeaderHash, source: BlockchainError } = "block {hash} is not valid",
+}
+
+/// the result of the verification of the storage
+pub struct Verification {
+    /// the `HEAD` of the storage
+    pub head: HeaderHash,
+    /// the deepest block successfully verified from the block0, `None`
+    /// if the block0 of the storage is missing or does not match
+    pub last_good: Option<HeaderHash>,
+    /// the number of blocks verified after the block0
+    pub verified: u64,
+    /// the first inconsistency found, if any
+    pub inconsistency: Option<Inconsistency>,
+}
+
+/// replay the blocks from the block0 to the `HEAD` of the storage
+pub fn verify(storage: &Storage, block0: Block, logger: &Logger) -> Result<Verification, Error> {
+    let block0_hash = block0.header.hash();
+    let head = storage
+        .get_tag(MAIN_BRANCH_TAG.to_owned())
+        .wait()?
+        .ok_or(Error::NoHead)?;
+
+    let chain = match chain_to_head(storage, head)? {
+        Err(inconsistency) => {
+            // nothing is known of the chain of the `HEAD`, it can only be
+            // rewound to the block0
+            let block0_stored = storage.block_exists(block0_hash).wait()?;
+            return Ok(Verification {
+                head,
+                last_good: if block0_stored {
+                    Some(block0_hash)
+                } else {
+                    None
+                },
+                verified: 0,
+                inconsistency: Some(inconsistency),
+            });
+        }
+        Ok(chain) => chain,
+    };
+
+    let mut verification = Verification {
+        head,
+        last_good: None,
+        verified: 0,
+        inconsistency: None,
+    };
+
+    match chain.blocks.get(&BLOCK0_DEPTH) {
+        Some(hash) if *hash == block0_hash => verification.last_good = Some(block0_hash),
+        Some(hash) => {
+            verification.inconsistency = Some(Inconsistency::BrokenChain { hash: *hash });
+            return Ok(verification);
+        }
+        None => {
+            verification.inconsistency = Some(chain.gap_at(BLOCK0_DEPTH));
+            return Ok(verification);
+        }
+    }
+
+    // the replayed blocks are not kept in the cache, only the `Ref` of
+    // the last replayed block is held
+    let mut blockchain =
+        Blockchain::new(Box::new(MemoryBlockStore::new()), Duration::default(), None);
+    let mut current = blockchain
+        .load_from_block0(block0)
+        .and_then(|branch| branch.get_ref().map_err(|_: Infallible| unreachable!()))
+        .wait()?;
+
+    for depth in BLOCK0_DEPTH + 1..=chain.head_depth {
+        let hash = match chain.blocks.get(&depth) {
+            None => {
+                verification.inconsistency = Some(chain.gap_at(depth));
+                break;
+            }
+            Some(hash) => *hash,
+        };
+        let block = match storage.get(hash).wait()? {
+            None => {
+                verification.inconsistency = Some(Inconsistency::MissingBlock { hash });
+                break;
+            }
+            Some(block) => block,
+        };
+        current = match replay_block(&mut blockchain, current, hash, block) {
+            Err(inconsistency) => {
+                verification.inconsistency = Some(inconsistency);
+                break;
+            }
+            Ok(new_ref) => new_ref,
+        };
+        verification.last_good = Some(hash);
+        verification.verified += 1;
+        if verification.verified % 10_000 == 0 {
+            info!(logger, "{} blocks verified", verification.verified);
+        }
+    }
+
+    Ok(verification)
+}
+
+/// rewind the `HEAD` of the storage to the last good block of the
+/// given verification.
+pub fn rewind_head(storage: &mut Storage, verification: Verification) -> Result<HeaderHash, Error> {
+    match verification.last_good {
+        Some(last_good) => {
+            storage
+                .put_tag(MAIN_BRANCH_TAG.to_owned(), last_good)
+                .wait()?;
+            Ok(last_good)
+        }
+        None => Err(Error::CannotRepair {
+            source: verification
+                .inconsistency
+                .unwrap_or(Inconsistency::BrokenChain {
+                    hash: verification.head,
+                }),
+        }),
+    }
+}
+
+/// the depth of the block0 in the storage, it is the only block without
+/// a parent
+const BLOCK0_DEPTH: u64 = 1;
+
+/// the hashes of the blocks of the chain of the `HEAD`, indexed by depth
+struct Chain {
+    head_depth: u64,
+    blocks: BTreeMap<u64, HeaderHash>,
+    /// the blocks referenced by their children, or by the back links of
+    /// their descendants, but missing from the storage
+    missing: BTreeMap<u64, HeaderHash>,
+}
+
+impl Chain {
+    /// the inconsistency found when no block of the chain is known at the
+    /// given depth
+    fn gap_at(&self, depth: u64) -> Inconsistency {
+        match self.missing.get(&depth) {
+            Some(hash) => Inconsistency::MissingBlock { hash: *hash },
+            None => Inconsistency::BrokenChain {
+                // the `HEAD` is always known, there is a block above the gap
+                hash: *self.blocks.range(depth..).next().unwrap().1,
+            },
+        }
+    }
+}
+
+/// walk the storage back from the `head` to the block0. When a block is
+/// missing, the walk carries on from its closest ancestor known through
+/// the back links of its descendants, so the blocks below the gap can
+/// still be replayed.
+fn chain_to_head(
+    storage: &Storage,
+    head: HeaderHash,
+) -> Result<Result<Chain, Inconsistency>, Error> {
+    let head_depth = match storage.get_block_info(head).wait()? {
+        None => return Ok(Err(Inconsistency::MissingBlock { hash: head })),
+        Some(block_info) => block_info.depth,
+    };
+    let mut chain = Chain {
+        head_depth,
+        blocks: BTreeMap::new(),
+        missing: BTreeMap::new(),
+    };
+    // the ancestors known through the back links, not visited yet
+    let mut back_links = BTreeMap::new();
+    let mut next = Some((head_depth, head));
+
+    while let Some((depth, current)) = next {
+        match storage.get_block_info(current).wait()? {
+            Some(ref block_info) if block_info.depth == depth => {
+                chain.blocks.insert(depth, current);
+                if depth == BLOCK0_DEPTH {
+                    break;
+                }
+                for back_link in block_info.back_links.iter() {
+                    if let Some(ancestor_depth) = depth.checked_sub(back_link.distance) {
+                        back_links.insert(ancestor_depth, back_link.block_hash);
+                    }
+                }
+                next = Some((depth - 1, block_info.parent_id()));
+            }
+            _ => {
+                chain.missing.insert(depth, current);
+                next = back_links
+                    .range(..depth)
+                    .next_back()
+                    .map(|(depth, hash)| (*depth, *hash));
+            }
+        }
+    }
+
+    Ok(Ok(chain))
+}
+
+fn replay_block(
+    blockchain: &mut Blockchain,
+    parent: Ref,
+    hash: HeaderHash,
+    block: Block,
+) -> Result<Ref, Inconsistency> {
+    let invalid = |source: BlockchainError| Inconsistency::InvalidBlock { hash, source };
+
+    if block.header.hash() != hash {
+        return Err(invalid(
+            "block's hash does not match its storage key".into(),
+        ));
+    }
+    if block.header.block_parent_hash() != &parent.hash() {
+        return Err(Inconsistency::BrokenChain { hash });
+    }
+
+    blockchain
+        .replay_block(parent, block)
+        .wait()
+        .map_err(invalid)
+}
@@ -2,7 +2,8 @@ use crate::{
     blockcfg::{Value, ValueError},
     fragment::{Fragment, FragmentId},
 };
-use std::time::SystemTime;
+use chain_impl_mockchain::transaction::Transaction;
+use std::{cmp::Ordering, time::SystemTime};
 
 pub struct PoolEntry {
     // reference of the fragment stored in the pool
@@ -33,11 +34,7 @@ impl PoolEntry {
         let raw = fragment.to_raw();
         let fragment_size = raw.size_bytes_plus_size();
         let fragment_ref = raw.id();
-        // TODO: the fragment fee is not yet computed. Yet we should
-        // have an explicit fee in the message. So we need to be able
-        // to extract this information without the need to compute the
-        // fee from the ledger's fee settings.
-        let fragment_fee = Value::zero();
+        let fragment_fee = fragment_fee(fragment);
 
         PoolEntry {
             fragment_ref: fragment_ref,
@@ -73,6 +70,12 @@ impl PoolEntry {
     pub fn received_at(&self) -> &SystemTime {
         &self.received_at
     }
+    /// compare the fee per byte of the 2 entries, without loss of precision
+    pub fn cmp_fee_per_byte(&self, other: &PoolEntry) -> Ordering {
+        let self_weight = u128::from(self.fragment_fee.0) * other.fragment_size as u128;
+        let other_weight = u128::from(other.fragment_fee.0) * self.fragment_size as u128;
+        self_weight.cmp(&other_weight)
+    }
     #[inline]
     pub fn with_descendants_fee(&self) -> Result<Value, ValueError> {
         self.descendants_fee + self.fragment_fee
@@ -90,3 +93,24 @@ impl PoolEntry {
         self.ancestors_size + self.fragment_size
     }
 }
+
+/// the fee paid by the fragment: the difference between the sum of its
+/// inputs and the sum of its outputs. The fragments without inputs do
+/// not pay any fee.
+fn fragment_fee(fragment: &Fragment) -> Value {
+    match fragment {
+        Fragment::Transaction(tx) => transaction_fee(&tx.transaction),
+        Fragment::Certificate(tx) => transaction_fee(&tx.transaction),
+        _ => Value::zero(),
+    }
+}
+
+fn transaction_fee<OutAddress, Extra>(transaction: &Transaction<OutAddress, Extra>) -> Value {
+    let input_sum = Value::sum(transaction.inputs.iter().map(|input| input.value));
+    let output_sum = Value::sum(transaction.outputs.iter().map(|output| output.value));
+    match (input_sum, output_sum) {
+        // input < output implies minting, so no fee
+        (Ok(input_sum), Ok(output_sum)) => (input_sum - output_sum).unwrap_or(Value::zero()),
+        _ => Value::zero(),
+    }
+}
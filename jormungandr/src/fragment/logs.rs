@@ -58,6 +58,16 @@ impl Logs {
             .and_then(move |mut guard| future::poll_fn(move || guard.poll_purge()))
     }
 
+    /// get the log of the fragment with the given id, if it is known
+    pub fn get(
+        &self,
+        fragment_id: FragmentId,
+    ) -> impl Future<Item = Option<FragmentLog>, Error = ()> {
+        let mut lock = self.0.clone();
+        future::poll_fn(move || Ok(lock.poll_lock()))
+            .and_then(move |guard| future::ok(guard.get(&fragment_id.into()).cloned()))
+    }
+
     pub fn logs(&self) -> impl Future<Item = Vec<FragmentLog>, Error = ()> {
         let mut lock = self.0.clone();
         future::poll_fn(move || Ok(lock.poll_lock()))
@@ -71,9 +81,10 @@ impl Logs {
 }
 
 pub(super) mod internal {
+    use chain_impl_mockchain::key;
     use jormungandr_lib::{
         crypto::hash::Hash,
-        interfaces::{FragmentLog, FragmentStatus},
+        interfaces::{FragmentLog, FragmentOrigin, FragmentStatus},
     };
     use std::{
         collections::HashMap,
@@ -126,6 +137,21 @@ pub(super) mod internal {
             }
         }
 
+        pub fn revert(&mut self, fragment_id: key::Hash, block_hash: key::Hash) {
+            let id: Hash = fragment_id.into();
+            if let Some((ref mut log, ref key)) = self.entries.get_mut(&id) {
+                log.revert(block_hash);
+
+                self.expirations.reset_at(key, Instant::now() + self.ttl);
+                return;
+            }
+
+            // the log may have expired while the fragment was in a block
+            let mut log = FragmentLog::new(fragment_id, FragmentOrigin::Rollback);
+            log.revert(block_hash);
+            self.insert(log);
+        }
+
         pub fn remove(&mut self, fragment_id: &Hash) {
             if let Some((_, cache_key)) = self.entries.remove(fragment_id) {
                 self.expirations.remove(&cache_key);
@@ -144,6 +170,10 @@ pub(super) mod internal {
             }
         }
 
+        pub fn get(&self, fragment_id: &Hash) -> Option<&FragmentLog> {
+            self.entries.get(fragment_id).map(|(log, _)| log)
+        }
+
         pub fn logs<'a>(&'a self) -> impl Iterator<Item = &'a FragmentLog> {
             self.entries.values().map(|(v, _)| v)
         }
@@ -1,12 +1,18 @@
 mod entry;
 mod logs;
+mod persistence;
 mod pool;
 mod process;
 pub mod selection;
+mod senders;
+#[cfg(test)]
+mod test_utils;
 
 pub use self::entry::PoolEntry;
 pub use self::logs::Logs;
-pub use self::pool::Pool;
+pub use self::persistence::PoolPersistence;
+pub use self::pool::{Pool, PoolLimits};
 pub use self::process::Process;
+pub use self::senders::{Refusal, SenderLimits, Senders};
 
 pub use crate::blockcfg::{Fragment, FragmentId};
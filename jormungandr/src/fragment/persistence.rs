@@ -0,0 +1,239 @@
+//! persistence of the fragment pool across restarts
+//!
+//! The fragments pending in the pool and the fragment logs are written in
+//! a file of the storage directory periodically and when the node shuts
+//! down, either on the REST shutdown request or on SIGINT or SIGTERM. A
+//! node killed otherwise loses the fragments received since the last
+//! periodic save. At start up the node reloads the file: the fragments still valid
+//! against the ledger of the tip are put back in the pool, the others are
+//! dropped and logged as rejected.
+//!
+//! The file is made of:
+//!
+//! * the magic `JORMPOOL` and the format version (u32, big endian);
+//! * the number of fragments (u64, big endian);
+//! * the fragments in the order they were received, each prefixed by its
+//!   length in bytes (u64, big endian), at most `MAX_FRAGMENT_SIZE`;
+//! * the fragment logs, encoded in JSON and prefixed by their length in
+//!   bytes (u64, big endian), at most `MAX_LOGS_SIZE`.
+
+use crate::fragment::{Fragment, Pool};
+use chain_core::property::{Deserialize, Serialize};
+use jormungandr_lib::interfaces::FragmentLog;
+use slog::Logger;
+use std::{
+    fs,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::PathBuf,
+    time::Duration,
+};
+use tokio::prelude::*;
+
+const POOL_MAGIC: &[u8; 8] = b"JORMPOOL";
+const POOL_VERSION: u32 = 1;
+
+/// the fragments are prefixed by their size as a u16 on the wire
+const MAX_FRAGMENT_SIZE: u64 = u16::max_value() as u64;
+/// bound of the size of the encoded logs, so a corrupted length does not
+/// make the node allocate an arbitrary amount of memory
+const MAX_LOGS_SIZE: u64 = 256 * 1024 * 1024;
+
+custom_error! {pub Error
+    Io { source: io::Error } = "I/O error while accessing the persisted fragment pool",
+    InvalidMagic = "the file is not a persisted fragment pool",
+    UnsupportedVersion { version: u32 } = "unsupported fragment pool format version {version}",
+    Encoding { reason: String } = "cannot encode or decode the fragment pool: {reason}",
+    TooLarge { size: u64, max: u64 } = "invalid record of {size} bytes in the fragment pool, the maximum is {max} bytes",
+}
+
+/// the content of the fragment pool and its logs
+pub struct PersistedPool {
+    pub fragments: Vec<Fragment>,
+    pub logs: Vec<FragmentLog>,
+}
+
+/// where and how often the fragment pool is persisted
+#[derive(Clone)]
+pub struct PoolPersistence {
+    path: PathBuf,
+    interval: Duration,
+}
+
+impl PersistedPool {
+    fn write<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        writer.write_all(POOL_MAGIC)?;
+        writer.write_all(&POOL_VERSION.to_be_bytes())?;
+        writer.write_all(&(self.fragments.len() as u64).to_be_bytes())?;
+        for fragment in &self.fragments {
+            let bytes = fragment.serialize_as_vec().map_err(|err| Error::Encoding {
+                reason: err.to_string(),
+            })?;
+            write_bytes(&mut writer, &bytes)?;
+        }
+        let logs = serde_json::to_vec(&self.logs).map_err(|err| Error::Encoding {
+            reason: err.to_string(),
+        })?;
+        write_bytes(&mut writer, &logs)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn read<R: Read>(mut reader: R) -> Result<Self, Error> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != POOL_MAGIC {
+            return Err(Error::InvalidMagic);
+        }
+        let mut version = [0; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_be_bytes(version);
+        if version != POOL_VERSION {
+            return Err(Error::UnsupportedVersion { version });
+        }
+
+        let count = read_u64(&mut reader)?;
+        let mut fragments = Vec::new();
+        for _ in 0..count {
+            let bytes = read_bytes(&mut reader, MAX_FRAGMENT_SIZE)?;
+            let fragment =
+                Fragment::deserialize(bytes.as_slice()).map_err(|err| Error::Encoding {
+                    reason: err.to_string(),
+                })?;
+            fragments.push(fragment);
+        }
+        let logs = read_bytes(&mut reader, MAX_LOGS_SIZE)?;
+        let logs = serde_json::from_slice(&logs).map_err(|err| Error::Encoding {
+            reason: err.to_string(),
+        })?;
+        Ok(PersistedPool { fragments, logs })
+    }
+}
+
+impl PoolPersistence {
+    /// the pool is persisted in the file at `path` every `interval`
+    pub fn new(path: PathBuf, interval: Duration) -> Self {
+        PoolPersistence { path, interval }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn save(&self, persisted: &PersistedPool) -> Result<(), Error> {
+        // write in a temporary file first so an interrupted write
+        // does not leave a truncated file behind, the content is on disk
+        // before the file replaces the previous one
+        let tmp_path = self.path.with_extension("tmp");
+        let mut writer = BufWriter::new(fs::File::create(&tmp_path)?);
+        persisted.write(&mut writer)?;
+        writer.get_ref().sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+
+    /// load the persisted pool, returns `None` if the pool has never been
+    /// persisted.
+    pub fn load(&self) -> Result<Option<PersistedPool>, Error> {
+        match fs::File::open(&self.path) {
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+            Ok(file) => PersistedPool::read(BufReader::new(file)).map(Some),
+        }
+    }
+
+    /// write the current content of the pool, the errors are logged
+    pub fn save_pool(&self, pool: &Pool, logger: Logger) -> impl Future<Item = (), Error = ()> {
+        let persistence = self.clone();
+        pool.persisted()
+            .map(move |persisted| match persistence.save(&persisted) {
+                Ok(()) => debug!(logger, "fragment pool saved";
+                    "fragments" => persisted.fragments.len(),
+                    "logs" => persisted.logs.len()),
+                Err(error) => warn!(logger, "cannot save the fragment pool";
+                    "path" => persistence.path.display().to_string(),
+                    "reason" => error.to_string()),
+            })
+    }
+}
+
+fn write_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> Result<(), Error> {
+    writer.write_all(&(bytes.len() as u64).to_be_bytes())?;
+    writer.write_all(bytes)?;
+    Ok(())
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, Error> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+fn read_bytes<R: Read>(reader: &mut R, max: u64) -> Result<Vec<u8>, Error> {
+    let size = read_u64(reader)?;
+    if size > max {
+        return Err(Error::TooLarge { size, max });
+    }
+    let mut bytes = vec![0; size as usize];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fragment::test_utils::TestLedger;
+    use chain_core::property::Fragment as _;
+    use jormungandr_lib::interfaces::FragmentOrigin;
+
+    #[test]
+    fn write_and_read() {
+        let test_ledger = TestLedger::new();
+        let utxos = test_ledger.initial_utxos();
+        let fragment = test_ledger.transaction(&utxos[0..1], 1);
+        let persisted = PersistedPool {
+            fragments: vec![fragment.clone()],
+            logs: vec![FragmentLog::new(
+                fragment.id().into(),
+                FragmentOrigin::Rest { addr: None },
+            )],
+        };
+
+        let mut bytes = Vec::new();
+        persisted.write(&mut bytes).unwrap();
+        let read = PersistedPool::read(bytes.as_slice()).unwrap();
+        assert_eq!(read.fragments.len(), 1);
+        assert_eq!(read.fragments[0].id(), fragment.id());
+        assert_eq!(read.logs, persisted.logs);
+    }
+
+    #[test]
+    fn read_oversized_fragment() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(POOL_MAGIC);
+        bytes.extend_from_slice(&POOL_VERSION.to_be_bytes());
+        bytes.extend_from_slice(&1u64.to_be_bytes());
+        bytes.extend_from_slice(&u64::max_value().to_be_bytes());
+
+        match PersistedPool::read(bytes.as_slice()) {
+            Err(Error::TooLarge { size, max }) => {
+                assert_eq!(size, u64::max_value());
+                assert_eq!(max, MAX_FRAGMENT_SIZE);
+            }
+            _ => panic!("the oversized fragment is not refused"),
+        }
+    }
+
+    #[test]
+    fn read_oversized_logs() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(POOL_MAGIC);
+        bytes.extend_from_slice(&POOL_VERSION.to_be_bytes());
+        bytes.extend_from_slice(&0u64.to_be_bytes());
+        bytes.extend_from_slice(&(MAX_LOGS_SIZE + 1).to_be_bytes());
+
+        match PersistedPool::read(bytes.as_slice()) {
+            Err(Error::TooLarge { max, .. }) => assert_eq!(max, MAX_LOGS_SIZE),
+            _ => panic!("the oversized logs are not refused"),
+        }
+    }
+}
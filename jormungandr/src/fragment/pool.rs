@@ -1,22 +1,44 @@
 use crate::{
-    blockcfg::{HeaderContentEvalContext, Ledger, LedgerParameters},
-    fragment::{selection::FragmentSelectionAlgorithm, Fragment, Logs},
+    blockcfg::{BlockDate, HeaderContentEvalContext, HeaderHash, Ledger, LedgerParameters},
+    blockchain::Ref,
+    fragment::{
+        persistence::PersistedPool,
+        selection::{rejection_reason, FragmentSelectionAlgorithm},
+        Fragment, FragmentId, Logs, PoolEntry,
+    },
+    settings::start::EvictionPolicy,
 };
-use jormungandr_lib::interfaces::{FragmentLog, FragmentOrigin};
-use std::time::Duration;
+use jormungandr_lib::interfaces::{
+    FragmentLog, FragmentOrigin, FragmentStatus, PendingFragment, PendingFragments,
+};
+use std::{collections::HashMap, time::Duration};
 use tokio::{prelude::*, sync::lock::Lock, timer};
 
 #[derive(Clone)]
 pub struct Pool {
     logs: Logs,
+    limits: PoolLimits,
     pool: Lock<internal::Pool>,
 }
 
+/// limits of the pool, once one of the limits is exceeded fragments are
+/// evicted from the pool following the eviction policy.
+#[derive(Debug, Clone, Copy)]
+pub struct PoolLimits {
+    /// maximum number of fragments in the pool
+    pub max_entries: usize,
+    /// maximum cumulated size of the fragments of the pool, in bytes
+    pub max_size: usize,
+    /// how to select the fragments to evict when the pool is full
+    pub eviction_policy: EvictionPolicy,
+}
+
 impl Pool {
-    pub fn new(ttl: Duration, logs: Logs) -> Self {
+    pub fn new(ttl: Duration, limits: PoolLimits, logs: Logs) -> Self {
         Pool {
             logs,
-            pool: Lock::new(internal::Pool::new(ttl)),
+            limits,
+            pool: Lock::new(internal::Pool::new(ttl, limits)),
         }
     }
 
@@ -24,6 +46,21 @@ impl Pool {
         &self.logs
     }
 
+    /// check the fragment is not larger than the pool: such a fragment
+    /// would evict all the other fragments before being evicted itself.
+    /// The function returns the reason of the rejection.
+    pub fn check_size(&self, fragment: &Fragment) -> Result<(), String> {
+        let size = *PoolEntry::new(fragment).fragment_size();
+        if size > self.limits.max_size {
+            Err(format!(
+                "the fragment size ({} bytes) exceeds the size of the fragment pool ({} bytes)",
+                size, self.limits.max_size
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn insert(
         &mut self,
         origin: FragmentOrigin,
@@ -33,7 +70,7 @@ impl Pool {
 
         let id = fragment.id();
         let mut lock = self.pool.clone();
-        let mut logs = self.logs.clone();
+        let logs = self.logs.clone();
 
         self.logs()
             .exists(vec![id.clone()])
@@ -43,16 +80,294 @@ impl Pool {
                 } else {
                     future::Either::B(future::poll_fn(move || Ok(lock.poll_lock())).and_then(
                         move |mut guard| {
-                            guard.insert(fragment);
+                            // the new fragment may be evicted right away
+                            let evicted = guard.insert(fragment);
+                            let status = eviction_status(guard.limits().eviction_policy);
 
-                            let log = FragmentLog::new(id.into(), origin);
-                            logs.insert(log).map(|()| true)
+                            logs.inner().map(move |mut logs| {
+                                logs.insert(FragmentLog::new(id.into(), origin));
+                                for id in evicted {
+                                    logs.modify(&id.into(), status.clone());
+                                }
+                                true
+                            })
                         },
                     ))
                 }
             })
     }
 
+    /// get the fragments of the pool with the given ids, the ids of the
+    /// fragments not in the pool are ignored.
+    pub fn get(
+        &self,
+        fragment_ids: Vec<FragmentId>,
+    ) -> impl Future<Item = Vec<Fragment>, Error = ()> {
+        let mut lock = self.pool.clone();
+
+        future::poll_fn(move || Ok(lock.poll_lock())).map(move |guard| {
+            fragment_ids
+                .iter()
+                .filter_map(|id| guard.entries.get(id))
+                .map(|(_, fragment, _)| fragment.clone())
+                .collect()
+        })
+    }
+
+    /// the fragments pending in the pool, in the order they were
+    /// received, along with the totals and the limits of the pool. The
+    /// first `offset` fragments are skipped and at most `limit`
+    /// fragments are returned.
+    pub fn pending(
+        &self,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> impl Future<Item = PendingFragments, Error = ()> {
+        let mut lock = self.pool.clone();
+
+        future::poll_fn(move || Ok(lock.poll_lock())).map(move |guard| {
+            let fragments = guard
+                .entries_by_time
+                .iter()
+                .skip(offset)
+                .take(limit.unwrap_or(usize::max_value()))
+                .map(|id| pending_fragment(&guard.entries_by_id[id]))
+                .collect();
+            PendingFragments::new(
+                guard.entries_by_time.len(),
+                guard.total_size(),
+                guard.limits().max_entries,
+                guard.limits().max_size,
+                fragments,
+            )
+        })
+    }
+
+    /// get the pending fragment with the given id, if it is in the pool
+    pub fn get_pending(
+        &self,
+        fragment_id: FragmentId,
+    ) -> impl Future<Item = Option<(PendingFragment, Fragment)>, Error = ()> {
+        let mut lock = self.pool.clone();
+
+        future::poll_fn(move || Ok(lock.poll_lock())).map(move |guard| {
+            guard
+                .entries
+                .get(&fragment_id)
+                .map(|(entry, fragment, _)| (pending_fragment(entry), fragment.clone()))
+        })
+    }
+
+    /// log the fragment as rejected, unless the fragment is already
+    /// known by the logs.
+    pub fn reject(
+        &mut self,
+        origin: FragmentOrigin,
+        fragment_id: FragmentId,
+        reason: String,
+    ) -> impl Future<Item = (), Error = ()> {
+        self.logs.inner().map(move |mut logs| {
+            if !logs.exists(Some(fragment_id.into()))[0] {
+                let mut log = FragmentLog::new(fragment_id, origin);
+                log.modify(FragmentStatus::Rejected { reason });
+                logs.insert(log);
+            }
+        })
+    }
+
+    /// put back in the pool the fragments of a block that has been
+    /// rolled back. The fragments' logs are set back to `Pending` and
+    /// record the hash of the rolled back block.
+    pub fn revert(
+        &mut self,
+        block_hash: HeaderHash,
+        fragments: Vec<Fragment>,
+    ) -> impl Future<Item = (), Error = ()> {
+        use chain_core::property::Fragment as _;
+
+        let mut lock = self.pool.clone();
+        let logs = self.logs.clone();
+
+        future::poll_fn(move || Ok(lock.poll_lock())).and_then(move |mut guard| {
+            let mut evicted = Vec::new();
+            let ids: Vec<_> = fragments
+                .into_iter()
+                .map(|fragment| {
+                    let id = fragment.id();
+                    if !guard.entries.contains_key(&id) {
+                        evicted.extend(guard.insert(fragment));
+                    }
+                    id
+                })
+                .collect();
+            let status = eviction_status(guard.limits().eviction_policy);
+
+            logs.inner().map(move |mut logs| {
+                for id in ids {
+                    logs.revert(id, block_hash);
+                }
+                for id in evicted {
+                    logs.modify(&id.into(), status.clone());
+                }
+            })
+        })
+    }
+
+    /// the fragments of the pool, in the order they were received, and
+    /// the fragment logs
+    pub fn persisted(&self) -> impl Future<Item = PersistedPool, Error = ()> {
+        let mut lock = self.pool.clone();
+        let logs = self.logs.clone();
+
+        future::poll_fn(move || Ok(lock.poll_lock()))
+            .and_then(move |pool| logs.inner().map(|logs| (pool, logs)))
+            .map(|(pool, logs)| PersistedPool {
+                fragments: pool
+                    .entries_by_time
+                    .iter()
+                    .map(|id| pool.entries[id].1.clone())
+                    .collect(),
+                logs: logs.logs().cloned().collect(),
+            })
+    }
+
+    /// put back the persisted fragments and logs in the pool. The
+    /// fragments no longer valid against the ledger of the tip, or whose
+    /// log has expired, are dropped. The future returns the number of
+    /// dropped fragments.
+    pub fn restore(
+        &mut self,
+        persisted: PersistedPool,
+        tip: &Ref,
+    ) -> impl Future<Item = usize, Error = ()> {
+        use chain_core::property::Fragment as _;
+
+        let mut lock = self.pool.clone();
+        let logs = self.logs.clone();
+        let mut ledger = tip.ledger().clone();
+        let ledger_params = tip.epoch_ledger_parameters().clone();
+        let metadata = tip_context(tip);
+
+        future::poll_fn(move || Ok(lock.poll_lock()))
+            .and_then(move |pool| logs.inner().map(|logs| (pool, logs)))
+            .map(move |(mut pool, mut logs)| {
+                let PersistedPool {
+                    fragments,
+                    logs: persisted_logs,
+                } = persisted;
+                for log in persisted_logs {
+                    logs.insert(log);
+                }
+
+                let status = eviction_status(pool.limits().eviction_policy);
+                let mut dropped = 0;
+                for fragment in fragments {
+                    let id = fragment.id();
+                    if !logs.exists(Some(id.into()))[0] {
+                        dropped += 1;
+                        continue;
+                    }
+                    match ledger.apply_fragment(&ledger_params, &fragment, &metadata) {
+                        Ok(ledger_new) => {
+                            ledger = ledger_new;
+                            for id in pool.insert(fragment) {
+                                logs.modify(&id.into(), status.clone());
+                            }
+                        }
+                        Err(error) => {
+                            dropped += 1;
+                            logs.modify(
+                                &id.into(),
+                                FragmentStatus::Rejected {
+                                    reason: rejection_reason(error),
+                                },
+                            );
+                        }
+                    }
+                }
+                dropped
+            })
+    }
+
+    /// make the ledger of the tip with the fragments of the pool applied in
+    /// the order they were received, the fragments that cannot be applied
+    /// are skipped. The fragments depending on fragments still in the pool
+    /// (spending one of their outputs for example) are checked against it.
+    pub fn pending_ledger(&self, tip: &Ref) -> impl Future<Item = Ledger, Error = ()> {
+        let mut lock = self.pool.clone();
+        let ledger = tip.ledger().clone();
+        let ledger_params = tip.epoch_ledger_parameters().clone();
+        let metadata = tip_context(tip);
+
+        future::poll_fn(move || Ok(lock.poll_lock())).map(move |pool| {
+            pool.entries_by_time.iter().fold(ledger, |ledger, id| {
+                let fragment = &pool.entries[id].1;
+                ledger
+                    .apply_fragment(&ledger_params, fragment, &metadata)
+                    .unwrap_or(ledger)
+            })
+        })
+    }
+
+    /// check the fragments of the pool, in the order they were received,
+    /// against the ledger of the new tip: the fragments no longer valid
+    /// are removed from the pool. Those found
+    /// in `included`, the fragments of the blocks applied since the last
+    /// revalidation, are logged as in a block at the date of their block,
+    /// the others are logged as rejected. The future returns the number of
+    /// fragments found in a block and the number of rejected fragments.
+    pub fn revalidate(
+        &mut self,
+        tip: &Ref,
+        included: HashMap<FragmentId, BlockDate>,
+    ) -> impl Future<Item = (usize, usize), Error = ()> {
+        let mut lock = self.pool.clone();
+        let logs = self.logs.clone();
+        let ledger = tip.ledger().clone();
+        let ledger_params = tip.epoch_ledger_parameters().clone();
+        let metadata = tip_context(tip);
+
+        future::poll_fn(move || Ok(lock.poll_lock()))
+            .and_then(move |pool| logs.inner().map(|logs| (pool, logs)))
+            .map(move |(mut pool, mut logs)| {
+                let mut ledger = ledger;
+                let invalid: Vec<_> = pool
+                    .entries_by_time
+                    .iter()
+                    .filter_map(|id| {
+                        let fragment = &pool.entries[id].1;
+                        let error = match ledger.apply_fragment(&ledger_params, fragment, &metadata)
+                        {
+                            Ok(ledger_new) => {
+                                ledger = ledger_new;
+                                return None;
+                            }
+                            Err(error) => error,
+                        };
+                        let status = match included.get(id) {
+                            Some(date) => FragmentStatus::InABlock {
+                                date: (*date).into(),
+                            },
+                            None => FragmentStatus::Rejected {
+                                reason: rejection_reason(error),
+                            },
+                        };
+                        Some((id.clone(), status))
+                    })
+                    .collect();
+                let removed = invalid.len();
+                let mut in_block = 0;
+                for (id, status) in invalid {
+                    if let FragmentStatus::InABlock { .. } = status {
+                        in_block += 1;
+                    }
+                    pool.remove(&id);
+                    logs.modify(&id.into(), status);
+                }
+                (in_block, removed - in_block)
+            })
+    }
+
     pub fn poll_purge(&mut self) -> impl Future<Item = (), Error = timer::Error> {
         let mut lock = self.pool.clone();
         let purge_logs = self.logs.poll_purge();
@@ -84,10 +399,47 @@ impl Pool {
     }
 }
 
+/// the context of the fragments applied on top of the tip
+fn tip_context(tip: &Ref) -> HeaderContentEvalContext {
+    use chain_core::property::ChainLength as _;
+
+    HeaderContentEvalContext {
+        block_date: *tip.block_date(),
+        chain_length: tip.chain_length().next(),
+        nonce: None,
+    }
+}
+
+fn pending_fragment(entry: &PoolEntry) -> PendingFragment {
+    PendingFragment::new(
+        entry.fragment_ref().clone().into(),
+        *entry.fragment_size(),
+        (*entry.fragment_fee()).into(),
+        (*entry.received_at()).into(),
+    )
+}
+
+fn eviction_status(eviction_policy: EvictionPolicy) -> FragmentStatus {
+    let reason = match eviction_policy {
+        EvictionPolicy::LowestFee => {
+            "the fragment pool is full and the fragment pays the lowest fee per byte"
+        }
+        EvictionPolicy::OldestFirst => "the fragment pool is full and the fragment is the oldest",
+    };
+    FragmentStatus::Evicted {
+        reason: reason.to_owned(),
+    }
+}
+
 pub(super) mod internal {
-    use crate::fragment::{Fragment, FragmentId, PoolEntry};
+    use super::PoolLimits;
+    use crate::{
+        fragment::{Fragment, FragmentId, PoolEntry},
+        settings::start::EvictionPolicy,
+    };
     use std::{
-        collections::{BTreeMap, HashMap, VecDeque},
+        cmp::Ordering,
+        collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
         sync::Arc,
         time::Duration,
     };
@@ -100,46 +452,107 @@ pub(super) mod internal {
         pub entries: HashMap<FragmentId, (Arc<PoolEntry>, Fragment, delay_queue::Key)>,
         pub entries_by_id: BTreeMap<FragmentId, Arc<PoolEntry>>,
         pub entries_by_time: VecDeque<FragmentId>,
+        entries_by_fee: BTreeSet<ByFeePerByte>,
         expirations: DelayQueue<FragmentId>,
         ttl: Duration,
+        limits: PoolLimits,
+        /// cumulated size of the fragments of the pool
+        total_size: usize,
     }
 
     impl Pool {
-        pub fn new(ttl: Duration) -> Self {
+        pub fn new(ttl: Duration, limits: PoolLimits) -> Self {
             Pool {
                 entries: HashMap::new(),
                 entries_by_id: BTreeMap::new(),
                 entries_by_time: VecDeque::new(),
+                entries_by_fee: BTreeSet::new(),
                 expirations: DelayQueue::new(),
                 ttl,
+                limits,
+                total_size: 0,
             }
         }
 
-        pub fn insert(&mut self, fragment: Fragment) {
+        pub fn limits(&self) -> &PoolLimits {
+            &self.limits
+        }
+
+        /// cumulated size of the fragments of the pool, in bytes
+        pub fn total_size(&self) -> usize {
+            self.total_size
+        }
+
+        /// insert the fragment in the pool, the function returns the
+        /// fragments evicted to keep the pool within its limits. This
+        /// may include the fragment just inserted. A fragment larger than
+        /// the pool is not inserted, it is returned as evicted right away.
+        pub fn insert(&mut self, fragment: Fragment) -> Vec<FragmentId> {
             let entry = Arc::new(PoolEntry::new(&fragment));
             let fragment_id = entry.fragment_ref().clone();
+            if *entry.fragment_size() > self.limits.max_size {
+                return vec![fragment_id];
+            }
             let delay = self.expirations.insert(fragment_id.clone(), self.ttl);
 
+            self.total_size += *entry.fragment_size();
             self.entries
                 .insert(fragment_id.clone(), (entry.clone(), fragment, delay));
             self.entries_by_id
                 .insert(fragment_id.clone(), entry.clone());
             self.entries_by_time.push_back(fragment_id);
+            self.entries_by_fee.insert(ByFeePerByte(entry));
+
+            self.evict()
         }
 
         pub fn remove(&mut self, fragment_id: &FragmentId) -> Option<Fragment> {
-            if let Some((_, fragment, cache_key)) = self.entries.remove(fragment_id) {
-                self.entries_by_id.remove(fragment_id);
-                self.entries_by_time
-                    .iter()
-                    .position(|id| id == fragment_id)
-                    .map(|position| {
-                        self.entries_by_time.remove(position);
-                    });
+            self.remove_entry(fragment_id).map(|(fragment, cache_key)| {
                 self.expirations.remove(&cache_key);
-                Some(fragment)
-            } else {
-                None
+                fragment
+            })
+        }
+
+        fn remove_entry(
+            &mut self,
+            fragment_id: &FragmentId,
+        ) -> Option<(Fragment, delay_queue::Key)> {
+            let (entry, fragment, cache_key) = self.entries.remove(fragment_id)?;
+            self.entries_by_id.remove(fragment_id);
+            self.entries_by_fee.remove(&ByFeePerByte(entry.clone()));
+            self.entries_by_time
+                .iter()
+                .position(|id| id == fragment_id)
+                .map(|position| {
+                    self.entries_by_time.remove(position);
+                });
+            self.total_size -= *entry.fragment_size();
+            Some((fragment, cache_key))
+        }
+
+        fn evict(&mut self) -> Vec<FragmentId> {
+            let mut evicted = Vec::new();
+            while self.entries.len() > self.limits.max_entries
+                || self.total_size > self.limits.max_size
+            {
+                let fragment_id = match self.eviction_candidate() {
+                    Some(fragment_id) => fragment_id,
+                    None => break,
+                };
+                self.remove(&fragment_id);
+                evicted.push(fragment_id);
+            }
+            evicted
+        }
+
+        fn eviction_candidate(&self) -> Option<FragmentId> {
+            match self.limits.eviction_policy {
+                EvictionPolicy::OldestFirst => self.entries_by_time.front().cloned(),
+                EvictionPolicy::LowestFee => self
+                    .entries_by_fee
+                    .iter()
+                    .next()
+                    .map(|entry| entry.0.fragment_ref().clone()),
             }
         }
 
@@ -149,17 +562,111 @@ pub(super) mod internal {
                     Async::NotReady => return Ok(Async::Ready(())),
                     Async::Ready(None) => return Ok(Async::Ready(())),
                     Async::Ready(Some(entry)) => {
-                        self.entries.remove(entry.get_ref());
-                        self.entries_by_id.remove(entry.get_ref());
-                        self.entries_by_time
-                            .iter()
-                            .position(|id| id == entry.get_ref())
-                            .map(|position| {
-                                self.entries_by_time.remove(position);
-                            });
+                        self.remove_entry(entry.get_ref());
                     }
                 }
             }
         }
     }
+    /// pool entry ordered by fee per byte, then by the time it was
+    /// received: on equal fee per byte, the oldest entry comes first.
+    struct ByFeePerByte(Arc<PoolEntry>);
+
+    impl Ord for ByFeePerByte {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.0
+                .cmp_fee_per_byte(&other.0)
+                .then_with(|| self.0.received_at().cmp(other.0.received_at()))
+                .then_with(|| self.0.fragment_ref().cmp(other.0.fragment_ref()))
+        }
+    }
+
+    impl PartialOrd for ByFeePerByte {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl PartialEq for ByFeePerByte {
+        fn eq(&self, other: &Self) -> bool {
+            self.cmp(other) == Ordering::Equal
+        }
+    }
+
+    impl Eq for ByFeePerByte {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{internal, PoolLimits};
+    use crate::{
+        fragment::{test_utils::TestLedger, PoolEntry},
+        settings::start::EvictionPolicy,
+    };
+    use chain_core::property::Fragment as _;
+    use std::time::Duration;
+    use tokio::{prelude::future, runtime::current_thread::Runtime};
+
+    const TTL: Duration = Duration::from_secs(3600);
+
+    fn run<F, R>(f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        let mut runtime = Runtime::new().unwrap();
+        runtime
+            .block_on(future::lazy(|| future::ok::<_, ()>(f())))
+            .unwrap()
+    }
+
+    #[test]
+    fn evicts_the_lowest_fee_per_byte() {
+        let test_ledger = TestLedger::new();
+        let utxos = test_ledger.initial_utxos();
+        let medium_fee = test_ledger.transaction(&utxos[0..1], 1);
+        let low_fee = test_ledger.transaction(&utxos[1..4], 1);
+        let high_fee = test_ledger.transaction(&utxos[4..5], 4);
+        let limits = PoolLimits {
+            max_entries: 2,
+            max_size: 1 << 20,
+            eviction_policy: EvictionPolicy::LowestFee,
+        };
+
+        run(move || {
+            let mut pool = internal::Pool::new(TTL, limits);
+            assert!(pool.insert(medium_fee.clone()).is_empty());
+            assert!(pool.insert(low_fee.clone()).is_empty());
+            assert_eq!(pool.insert(high_fee.clone()), vec![low_fee.id()]);
+            assert_eq!(pool.insert(low_fee.clone()), vec![low_fee.id()]);
+            assert_eq!(
+                pool.entries_by_time.iter().cloned().collect::<Vec<_>>(),
+                vec![medium_fee.id(), high_fee.id()]
+            );
+        })
+    }
+
+    #[test]
+    fn rejects_a_fragment_larger_than_the_pool() {
+        let test_ledger = TestLedger::new();
+        let utxos = test_ledger.initial_utxos();
+        let small = test_ledger.transaction(&utxos[0..1], 1);
+        let large = test_ledger.transaction(&utxos[1..4], 1);
+        let limits = PoolLimits {
+            max_entries: 10,
+            max_size: *PoolEntry::new(&small).fragment_size(),
+            eviction_policy: EvictionPolicy::LowestFee,
+        };
+
+        run(move || {
+            let mut pool = internal::Pool::new(TTL, limits);
+            assert!(pool.insert(small.clone()).is_empty());
+            // the fragment already in the pool is not evicted
+            assert_eq!(pool.insert(large.clone()), vec![large.id()]);
+            assert_eq!(
+                pool.entries_by_time.iter().cloned().collect::<Vec<_>>(),
+                vec![small.id()]
+            );
+            assert_eq!(pool.total_size(), *PoolEntry::new(&small).fragment_size());
+        })
+    }
 }
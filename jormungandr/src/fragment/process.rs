@@ -1,11 +1,23 @@
 use crate::{
-    fragment::{Logs, Pool},
-    intercom::TransactionMsg,
+    blockcfg::{HeaderContentEvalContext, Ledger},
+    blockchain::{Branch, ChainEvent, Ref, Storage},
+    fragment::{
+        selection::rejection_reason, Fragment, Logs, Pool, PoolLimits, PoolPersistence,
+        SenderLimits, Senders,
+    },
+    intercom::{NetworkMsg, PropagateMsg, TransactionMsg},
     stats_counter::StatsCounter,
-    utils::{async_msg::MessageQueue, task::TokioServiceInfo},
+    utils::{
+        async_msg::{MessageBox, MessageQueue},
+        task::TokioServiceInfo,
+    },
+};
+use chain_impl_mockchain::ledger;
+use jormungandr_lib::interfaces::{
+    FragmentOrigin, FragmentStatus, FragmentSubmission, FragmentSubmissionStatus,
 };
 use slog::Logger;
-use std::time::Duration;
+use std::{collections::HashMap, time::Duration};
 use tokio::{
     prelude::{
         future::Either::{A, B},
@@ -17,19 +29,26 @@ use tokio::{
 pub struct Process {
     pool: Pool,
     logs: Logs,
+    senders: Senders,
+    persistence: Option<PoolPersistence>,
     garbage_collection_interval: Duration,
 }
 
 impl Process {
     pub fn new(
         pool_ttl: Duration,
+        pool_limits: PoolLimits,
         logs_ttl: Duration,
+        sender_limits: SenderLimits,
+        persistence: Option<PoolPersistence>,
         garbage_collection_interval: Duration,
     ) -> Self {
         let logs = Logs::new(logs_ttl);
         Process {
-            pool: Pool::new(pool_ttl, logs.clone()),
+            pool: Pool::new(pool_ttl, pool_limits, logs.clone()),
             logs,
+            senders: Senders::new(sender_limits),
+            persistence,
             garbage_collection_interval,
         }
     }
@@ -40,19 +59,44 @@ impl Process {
     pub fn pool(&self) -> &Pool {
         &self.pool
     }
+    pub fn senders(&self) -> &Senders {
+        &self.senders
+    }
+    pub fn persistence(&self) -> Option<&PoolPersistence> {
+        self.persistence.as_ref()
+    }
 
     pub fn start(
         self,
         service_info: TokioServiceInfo,
         stats_counter: StatsCounter,
+        blockchain_tip: Branch,
+        network_msg_box: MessageBox<NetworkMsg>,
+        chain_events: MessageQueue<ChainEvent>,
+        storage: Storage,
         input: MessageQueue<TransactionMsg>,
     ) -> impl Future<Item = (), Error = ()> {
         service_info.spawn(self.start_pool_garbage_collector(service_info.logger().clone()));
+        service_info.spawn(self.start_pool_revalidation(
+            chain_events,
+            blockchain_tip.clone(),
+            storage,
+            service_info.logger().clone(),
+        ));
+        if let Some(persistence) = &self.persistence {
+            service_info.spawn(
+                self.start_pool_persistence(persistence.clone(), service_info.logger().clone()),
+            );
+        }
+        // the persisted fragments are put back in the pool before
+        // processing the new ones
+        let restore_pool = self.restore_pool(&blockchain_tip, service_info.logger().clone());
 
-        let pool = self.pool.clone();
-        let pool_copy = self.pool;
+        let pool = self.pool;
+        let senders = self.senders;
+        let logger = service_info.logger().clone();
 
-        input.for_each(move |input| {
+        let process_input = input.for_each(move |input| {
             match input {
                 TransactionMsg::ProposeTransaction(txids, reply) => {
                     let logs = pool.logs().clone();
@@ -62,47 +106,191 @@ impl Process {
                         future::ok(())
                     })))
                 }
-                TransactionMsg::SendTransaction(origin, txs) => {
+                TransactionMsg::SendTransaction(origin, txs, reply) => {
                     // TODO? stats_counter.add_tx_recv_cnt(txs.len());
 
-                    // Note that we cannot use apply_block here, since we don't have a valid context to which to apply
-                    // those blocks. one valid tx in a given context, could be invalid in another. for example
-                    // fee calculations, existence utxo / account solvency.
+                    // The fragments are checked against the ledger of the current tip, a fragment
+                    // valid in this context may still be invalid in the context of the next block
+                    // (fee calculations, existence utxo / account solvency...), the fragment
+                    // selection checks the fragments again.
 
-                    // FIXME/TODO check that the txs are valid within themselves with basic requirements (e.g. inputs >= outputs).
                     // we also want to keep a basic capability to filter away repetitive queries or definitely discarded txid.
 
                     // This interface only makes sense for messages coming from arbitrary users (like transaction, certificates),
                     // for other message we don't want to receive them through this interface, and possibly
                     // put them in another pool.
 
-                    let mut pool_copy = pool_copy.clone();
-                    let stats_counter = stats_counter.clone();
+                    let context = SubmitContext {
+                        pool: pool.clone(),
+                        senders: senders.clone(),
+                        stats_counter: stats_counter.clone(),
+                        network_msg_box: network_msg_box.clone(),
+                        logger: logger.clone(),
+                    };
 
-                    A(B(stream::iter_ok(txs).for_each(move |tx| {
-                        let stats_counter = stats_counter.clone();
-                        pool_copy.insert(origin, tx).map(move |inserted| {
-                            if inserted {
-                                stats_counter.add_tx_recv_cnt(1)
-                            }
+                    let pending_pool = pool.clone();
+                    A(B(blockchain_tip
+                        .get_ref()
+                        .map_err(|infallible| match infallible {})
+                        .and_then(move |tip| {
+                            // the fragments are checked against the ledger of the tip with the
+                            // fragments of the pool applied, a fragment may depend on fragments
+                            // not yet in a block
+                            pending_pool
+                                .pending_ledger(&tip)
+                                .map(move |ledger| (tip, ledger))
                         })
-                    })))
+                        .and_then(move |(tip, ledger)| {
+                            stream::iter_ok(txs).fold(
+                                (Vec::new(), ledger),
+                                move |(mut submissions, ledger), tx| {
+                                    submit_fragment(
+                                        context.clone(),
+                                        tip.clone(),
+                                        ledger,
+                                        origin.clone(),
+                                        tx,
+                                    )
+                                    .map(
+                                        move |(submission, ledger)| {
+                                            submissions.push(submission);
+                                            (submissions, ledger)
+                                        },
+                                    )
+                                },
+                            )
+                        })
+                        .map(move |(submissions, _ledger)| {
+                            if let Some(reply) = reply {
+                                reply.reply_ok(submissions);
+                            }
+                        })))
                 }
-                TransactionMsg::GetTransactions(_txids, _handler) => {
-                    // this function is no yet implemented, this is not handled in the
-                    B(future::ok(unimplemented!()))
+                TransactionMsg::GetTransactions(txids, mut handler) => {
+                    B(pool.get(txids).map(move |fragments| {
+                        for fragment in fragments {
+                            handler.send(fragment);
+                        }
+                        handler.close();
+                    }))
                 }
             }
-        })
+        });
+
+        restore_pool.and_then(move |()| process_input)
+    }
+
+    /// put back in the pool the fragments persisted before the node
+    /// was stopped
+    fn restore_pool(
+        &self,
+        blockchain_tip: &Branch,
+        logger: Logger,
+    ) -> impl Future<Item = (), Error = ()> {
+        let persisted = match self.persistence.as_ref().map(|p| p.load()) {
+            None | Some(Ok(None)) => return A(future::ok(())),
+            Some(Ok(Some(persisted))) => persisted,
+            Some(Err(error)) => {
+                warn!(logger, "cannot load the persisted fragment pool" ; "reason" => error.to_string());
+                return A(future::ok(()));
+            }
+        };
+        let mut pool = self.pool().clone();
+
+        B(blockchain_tip
+            .get_ref()
+            .map_err(|infallible| match infallible {})
+            .and_then(move |tip| {
+                let persisted_count = persisted.fragments.len();
+                pool.restore(persisted, &tip).map(move |dropped| {
+                    info!(logger, "fragment pool restored" ;
+                        "fragments" => persisted_count - dropped,
+                        "dropped" => dropped);
+                })
+            }))
+    }
+
+    fn start_pool_persistence(
+        &self,
+        persistence: PoolPersistence,
+        logger: Logger,
+    ) -> impl Future<Item = (), Error = ()> {
+        let pool = self.pool().clone();
+        let error_logger = logger.clone();
+        Interval::new_interval(persistence.interval())
+            .map_err(move |error| {
+                error!(error_logger, "Cannot run the fragment pool persistence" ; "reason" => error.to_string());
+            })
+            .for_each(move |_instant| persistence.save_pool(&pool, logger.clone()))
+    }
+
+    /// check the fragments of the pool again every time the tip of the
+    /// blockchain moves, the fragments no longer valid are removed.
+    /// revalidate the pool each time the tip changes. The fragments of
+    /// the blocks applied in between are collected so the fragments of the
+    /// pool included in these blocks are not logged as rejected.
+    fn start_pool_revalidation(
+        &self,
+        chain_events: MessageQueue<ChainEvent>,
+        blockchain_tip: Branch,
+        storage: Storage,
+        logger: Logger,
+    ) -> impl Future<Item = (), Error = ()> {
+        let pool = self.pool().clone();
+        chain_events
+            .fold(HashMap::new(), move |mut included, event| match event {
+                ChainEvent::BlockApplied { hash, date, .. } => {
+                    let logger = logger.clone();
+                    A(storage.get(hash).then(move |result| {
+                        match result {
+                            Ok(Some(block)) => {
+                                for fragment in block.contents.iter() {
+                                    included.insert(fragment.id(), date);
+                                }
+                            }
+                            Ok(None) => {
+                                warn!(logger, "applied block not found in the storage" ;
+                                    "hash" => hash.to_string());
+                            }
+                            Err(error) => {
+                                warn!(logger, "cannot retrieve the applied block" ;
+                                    "hash" => hash.to_string(),
+                                    "reason" => error.to_string());
+                            }
+                        }
+                        Ok(included)
+                    }))
+                }
+                ChainEvent::TipChanged { .. } => {
+                    let mut pool = pool.clone();
+                    let logger = logger.clone();
+                    B(A(blockchain_tip
+                        .get_ref()
+                        .map_err(|infallible| match infallible {})
+                        .and_then(move |tip| pool.revalidate(&tip, included))
+                        .map(move |(in_block, rejected)| {
+                            if in_block + rejected > 0 {
+                                debug!(logger, "invalid fragments removed from the pool" ;
+                                    "in_block" => in_block,
+                                    "rejected" => rejected);
+                            }
+                            HashMap::new()
+                        })))
+                }
+                _ => B(B(future::ok(included))),
+            })
+            .map(|_included| ())
     }
 
     fn start_pool_garbage_collector(&self, logger: Logger) -> impl Future<Item = (), Error = ()> {
         let mut pool = self.pool().clone();
+        let senders = self.senders().clone();
         let garbage_collection_interval = self.garbage_collection_interval;
         let error_logger = logger.clone();
         Interval::new_interval(garbage_collection_interval)
             .for_each(move |_instant| {
                 debug!(logger, "garbage collect entries in the MemPool and in the logs");
+                senders.purge();
                 pool.poll_purge()
             })
             .map_err(move |error| {
@@ -110,3 +298,312 @@ impl Process {
             })
     }
 }
+
+/// the handles used to process the submitted fragments
+#[derive(Clone)]
+struct SubmitContext {
+    pool: Pool,
+    senders: Senders,
+    stats_counter: StatsCounter,
+    network_msg_box: MessageBox<NetworkMsg>,
+    logger: Logger,
+}
+
+/// submit a fragment sent by the given origin. A fragment already known by
+/// the logs is neither checked again nor accounted against the quota of its
+/// sender, its submission status follows its log. A new fragment is
+/// admitted against the quota of its sender then processed against the
+/// given ledger, its rejection is accounted to the sender only if the
+/// fragment is invalid by itself.
+/// The future returns the ledger with the fragment applied if it was
+/// accepted, the given ledger otherwise.
+fn submit_fragment(
+    context: SubmitContext,
+    tip: Ref,
+    ledger: Ledger,
+    origin: FragmentOrigin,
+    fragment: Fragment,
+) -> impl Future<Item = (FragmentSubmission, Ledger), Error = ()> {
+    use chain_core::property::Fragment as _;
+
+    let SubmitContext {
+        pool,
+        senders,
+        stats_counter,
+        network_msg_box,
+        logger,
+    } = context;
+    let fragment_id = fragment.id();
+    pool.logs().get(fragment_id).and_then(move |log| {
+        if let Some(log) = log {
+            let status = match log.status() {
+                FragmentStatus::Rejected { reason } | FragmentStatus::Evicted { reason } => {
+                    FragmentSubmissionStatus::Rejected {
+                        reason: reason.clone(),
+                    }
+                }
+                FragmentStatus::Pending | FragmentStatus::InABlock { .. } => {
+                    FragmentSubmissionStatus::Accepted
+                }
+            };
+            return A(future::ok((
+                FragmentSubmission::new(fragment_id, status),
+                ledger,
+            )));
+        }
+        if let Err(refusal) = senders.admit(&origin) {
+            debug!(logger, "fragment refused" ; "reason" => refusal.to_string());
+            let status = FragmentSubmissionStatus::Refused {
+                reason: refusal.to_string(),
+            };
+            return A(future::ok((
+                FragmentSubmission::new(fragment_id, status),
+                ledger,
+            )));
+        }
+        B(process_fragment(
+            pool,
+            &tip,
+            ledger.clone(),
+            stats_counter,
+            network_msg_box,
+            &logger,
+            origin.clone(),
+            fragment,
+        )
+        .map(move |result| {
+            let (status, ledger) = match result {
+                Ok(ledger_new) => (FragmentSubmissionStatus::Accepted, ledger_new),
+                Err(rejection) => {
+                    if rejection.invalid {
+                        record_rejection(&senders, &logger, &origin);
+                    }
+                    let reason = rejection.reason;
+                    (FragmentSubmissionStatus::Rejected { reason }, ledger)
+                }
+            };
+            (FragmentSubmission::new(fragment_id, status), ledger)
+        }))
+    })
+}
+
+/// account for the rejection of a fragment sent by the given origin, the
+/// ban of the sender is logged if the rejection gets it banned.
+fn record_rejection(senders: &Senders, logger: &Logger, origin: &FragmentOrigin) {
+    if let Some(ban) = senders.record_rejection(origin) {
+        info!(logger, "fragment sender banned" ;
+            "sender" => ban.sender().to_string(),
+            "reason" => ban.reason());
+    }
+}
+
+/// check the fragment against the size of the pool and the given ledger,
+/// the ledger of the tip of the blockchain with the pending fragments
+/// applied: the valid fragment is inserted in the pool and propagated to
+/// the network if it was not known already, the invalid one is logged as
+/// rejected. The future returns the ledger with the fragment applied or
+/// the rejection.
+fn process_fragment(
+    mut pool: Pool,
+    tip: &Ref,
+    ledger: Ledger,
+    stats_counter: StatsCounter,
+    mut network_msg_box: MessageBox<NetworkMsg>,
+    logger: &Logger,
+    origin: FragmentOrigin,
+    fragment: Fragment,
+) -> impl Future<Item = Result<Ledger, Rejection>, Error = ()> {
+    use chain_core::property::{ChainLength as _, Fragment as _};
+
+    let metadata = HeaderContentEvalContext {
+        block_date: *tip.block_date(),
+        chain_length: tip.chain_length().next(),
+        nonce: None,
+    };
+
+    let validation = pool
+        .check_size(&fragment)
+        .map_err(Rejection::invalid)
+        .and_then(|()| {
+            ledger
+                .apply_fragment(tip.epoch_ledger_parameters(), &fragment, &metadata)
+                .map_err(Rejection::from_ledger)
+        });
+
+    match validation {
+        Ok(ledger_new) => {
+            let logger = logger.clone();
+            A(pool.insert(origin, fragment.clone()).map(move |inserted| {
+                if inserted {
+                    stats_counter.add_tx_recv_cnt(1);
+                    network_msg_box
+                        .try_send(NetworkMsg::Propagate(PropagateMsg::Message(fragment)))
+                        .unwrap_or_else(|err| {
+                            error!(logger, "cannot propagate fragment to network: {}", err)
+                        });
+                }
+                Ok(ledger_new)
+            }))
+        }
+        Err(rejection) => B(pool
+            .reject(origin, fragment.id(), rejection.reason.clone())
+            .map(move |()| Err(rejection))),
+    }
+}
+
+/// the rejection of a submitted fragment
+struct Rejection {
+    reason: String,
+    /// the fragment is invalid whatever the state of the ledger: it is
+    /// malformed, wrongly signed or larger than the pool. A fragment that
+    /// only conflicts with the state of the ledger (spent input, counter
+    /// or balance for example) may have been relayed in good faith, its
+    /// rejection is not held against its sender.
+    invalid: bool,
+}
+
+impl Rejection {
+    fn invalid(reason: String) -> Self {
+        Rejection {
+            reason,
+            invalid: true,
+        }
+    }
+
+    fn from_ledger(error: ledger::Error) -> Self {
+        let invalid = match &error {
+            ledger::Error::NotEnoughSignatures { .. }
+            | ledger::Error::UtxoInvalidSignature { .. }
+            | ledger::Error::OldUtxoInvalidSignature { .. }
+            | ledger::Error::OldUtxoInvalidPublicKey { .. }
+            | ledger::Error::AccountInvalidSignature { .. }
+            | ledger::Error::MultisigInvalidSignature { .. }
+            | ledger::Error::CertificateInvalidSignature { .. }
+            | ledger::Error::TransactionMalformed { .. }
+            | ledger::Error::ExpectingAccountWitness { .. }
+            | ledger::Error::ExpectingUtxoWitness { .. }
+            | ledger::Error::InvalidDiscrimination { .. } => true,
+            _ => false,
+        };
+        Rejection {
+            reason: rejection_reason(error),
+            invalid,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{submit_fragment, SubmitContext};
+    use crate::{
+        blockcfg::Fragment,
+        blockchain::{test_utils, Ref},
+        fragment::{
+            test_utils::{output, TestLedger},
+            Logs, Pool, PoolLimits, SenderLimits, Senders,
+        },
+        settings::start::EvictionPolicy,
+        stats_counter::StatsCounter,
+        utils::async_msg,
+    };
+    use jormungandr_lib::interfaces::{FragmentOrigin, FragmentSubmissionStatus};
+    use slog::{Discard, Logger};
+    use std::time::Duration;
+    use tokio::{prelude::*, runtime::current_thread::Runtime};
+
+    const TTL: Duration = Duration::from_secs(3600);
+
+    /// make the handles to submit fragments, a sender is banned as soon as
+    /// one of its fragments is held against it
+    fn context() -> SubmitContext {
+        let limits = PoolLimits {
+            max_entries: 10,
+            max_size: 1 << 20,
+            eviction_policy: EvictionPolicy::LowestFee,
+        };
+        let sender_limits = SenderLimits {
+            max_fragments: 10,
+            interval: TTL,
+            max_rejections: 1,
+            ban_duration: TTL,
+        };
+        // the queue is dropped, the propagation of the fragments fails
+        let (network_msg_box, _network_queue) = async_msg::channel(10);
+        SubmitContext {
+            pool: Pool::new(TTL, limits, Logs::new(TTL)),
+            senders: Senders::new(sender_limits),
+            stats_counter: StatsCounter::default(),
+            network_msg_box,
+            logger: Logger::root(Discard, o!()),
+        }
+    }
+
+    fn node() -> FragmentOrigin {
+        FragmentOrigin::Network {
+            node_id: "node".to_owned(),
+            addr: None,
+        }
+    }
+
+    /// submit the fragment in a request of its own, against the ledger of
+    /// the tip with the fragments of the pool applied
+    fn submit(
+        runtime: &mut Runtime,
+        context: &SubmitContext,
+        tip: &Ref,
+        origin: FragmentOrigin,
+        fragment: Fragment,
+    ) -> FragmentSubmissionStatus {
+        let context = context.clone();
+        let tip = tip.clone();
+        runtime
+            .block_on(future::lazy(move || {
+                context
+                    .pool
+                    .pending_ledger(&tip)
+                    .and_then(move |ledger| submit_fragment(context, tip, ledger, origin, fragment))
+                    .map(|(submission, _ledger)| submission.status().clone())
+            }))
+            .unwrap()
+    }
+
+    #[test]
+    fn accept_a_transaction_spending_a_pending_transaction() {
+        let test_ledger = TestLedger::new();
+        let utxos = test_ledger.initial_utxos();
+        let parent = test_ledger.transaction(&utxos[0..1], 1);
+        let child = test_ledger.transaction(&[output(&parent)], 1);
+        let tip = test_utils::reference_with_ledger(&test_utils::block0(), test_ledger.ledger);
+        let context = context();
+
+        let mut runtime = Runtime::new().unwrap();
+        assert_eq!(
+            submit(&mut runtime, &context, &tip, node(), parent),
+            FragmentSubmissionStatus::Accepted
+        );
+        // the child spends the output of its parent, which is still in the
+        // pool and not yet in a block
+        assert_eq!(
+            submit(&mut runtime, &context, &tip, node(), child),
+            FragmentSubmissionStatus::Accepted
+        );
+    }
+
+    #[test]
+    fn do_not_ban_the_relay_of_a_conflicting_transaction() {
+        let test_ledger = TestLedger::new();
+        let utxos = test_ledger.initial_utxos();
+        let parent = test_ledger.transaction(&utxos[0..1], 1);
+        let orphan = test_ledger.transaction(&[output(&parent)], 1);
+        let tip = test_utils::reference_with_ledger(&test_utils::block0(), test_ledger.ledger);
+        let context = context();
+
+        let mut runtime = Runtime::new().unwrap();
+        // the input of the orphan is unknown to the ledger
+        match submit(&mut runtime, &context, &tip, node(), orphan) {
+            FragmentSubmissionStatus::Rejected { .. } => {}
+            status => panic!("the orphan is not rejected: {:?}", status),
+        }
+        assert!(context.senders.admit(&node()).is_ok());
+    }
+}
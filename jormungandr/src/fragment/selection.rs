@@ -1,10 +1,12 @@
 use super::logs::internal::Logs;
 use super::pool::internal::Pool;
 use crate::{
-    blockcfg::{BlockBuilder, HeaderContentEvalContext, Ledger, LedgerParameters},
-    fragment::FragmentId,
+    blockcfg::{HeaderContentEvalContext, Ledger, LedgerParameters},
+    fragment::{Fragment, FragmentId, PoolEntry},
 };
+use chain_impl_mockchain::ledger;
 use jormungandr_lib::interfaces::FragmentStatus;
+use std::sync::Arc;
 
 pub enum SelectionOutput {
     Commit { fragment_id: FragmentId },
@@ -23,26 +25,37 @@ pub trait FragmentSelectionAlgorithm {
         pool: &mut Pool,
     );
 
-    fn finalize(self) -> BlockBuilder;
+    /// the selected fragments, in the order they are to be put in the block
+    fn finalize(self) -> Vec<Fragment>;
 }
 
+/// limits of the contents of the blocks built by the selection algorithms
+#[derive(Debug, Clone, Copy)]
+pub struct BlockLimits {
+    /// maximum number of fragments in a block
+    pub max_fragments: usize,
+    /// maximum cumulated size of the fragments of a block, in bytes
+    pub max_size: usize,
+}
+
+/// the fragments are selected in the order they were received
 pub struct OldestFirst {
-    builder: BlockBuilder,
-    max_per_block: usize,
+    fragments: Vec<Fragment>,
+    limits: BlockLimits,
 }
 
 impl OldestFirst {
-    pub fn new(max_per_block: usize) -> Self {
+    pub fn new(limits: BlockLimits) -> Self {
         OldestFirst {
-            builder: BlockBuilder::new(),
-            max_per_block,
+            fragments: Vec::new(),
+            limits,
         }
     }
 }
 
 impl FragmentSelectionAlgorithm for OldestFirst {
-    fn finalize(self) -> BlockBuilder {
-        self.builder
+    fn finalize(self) -> Vec<Fragment> {
+        self.fragments
     }
 
     fn select(
@@ -54,18 +67,25 @@ impl FragmentSelectionAlgorithm for OldestFirst {
         pool: &mut Pool,
     ) {
         let mut total = 0usize;
+        let mut total_size = 0usize;
         let mut ledger_simulation = ledger.clone();
 
-        while let Some(id) = pool.entries_by_time.pop_front() {
-            if total >= self.max_per_block {
+        let ids: Vec<FragmentId> = pool.entries_by_time.iter().cloned().collect();
+        for id in ids {
+            if total >= self.limits.max_fragments {
                 break;
             }
+            let size = *pool.entries[&id].0.fragment_size();
+            if total_size + size > self.limits.max_size {
+                // a smaller fragment may still fit in the block
+                continue;
+            }
 
             let fragment = pool.remove(&id).unwrap();
 
             match ledger_simulation.apply_fragment(ledger_params, &fragment, metadata) {
                 Ok(ledger_new) => {
-                    self.builder.message(fragment);
+                    self.fragments.push(fragment);
 
                     logs.modify(
                         &id.into(),
@@ -75,18 +95,362 @@ impl FragmentSelectionAlgorithm for OldestFirst {
                     );
 
                     total += 1;
+                    total_size += size;
                     ledger_simulation = ledger_new;
                 }
-                Err(error) => {
-                    use std::error::Error as _;
-                    let error = if let Some(source) = error.source() {
-                        format!("{}: {}", error, source)
-                    } else {
-                        error.to_string()
-                    };
-                    logs.modify(&id.into(), FragmentStatus::Rejected { reason: error })
+                Err(error) => logs.modify(
+                    &id.into(),
+                    FragmentStatus::Rejected {
+                        reason: rejection_reason(error),
+                    },
+                ),
+            }
+        }
+    }
+}
+
+/// the fragments paying the highest fee per byte are selected first,
+/// the fragments paying the same fee per byte are selected in the
+/// order they were received.
+///
+/// A fragment depending on a fragment paying a lower fee (spending one
+/// of its outputs for example) cannot be applied before it: such a
+/// fragment is set aside and tried again once the other fragments have
+/// been selected. The fragments that still cannot be applied once no
+/// more fragment can be selected are rejected, unless the block is full.
+pub struct FeePerByte {
+    fragments: Vec<Fragment>,
+    limits: BlockLimits,
+}
+
+impl FeePerByte {
+    pub fn new(limits: BlockLimits) -> Self {
+        FeePerByte {
+            fragments: Vec::new(),
+            limits,
+        }
+    }
+}
+
+impl FragmentSelectionAlgorithm for FeePerByte {
+    fn finalize(self) -> Vec<Fragment> {
+        self.fragments
+    }
+
+    fn select(
+        &mut self,
+        ledger: &Ledger,
+        ledger_params: &LedgerParameters,
+        metadata: &HeaderContentEvalContext,
+        logs: &mut Logs,
+        pool: &mut Pool,
+    ) {
+        let mut total = 0usize;
+        let mut total_size = 0usize;
+        let mut ledger_simulation = ledger.clone();
+
+        let mut candidates: Vec<Arc<PoolEntry>> = pool
+            .entries_by_time
+            .iter()
+            .map(|id| pool.entries[id].0.clone())
+            .collect();
+        // stable sort: the fragments paying the same fee per byte
+        // remain in the order they were received
+        candidates.sort_by(|a, b| b.cmp_fee_per_byte(a));
+
+        let mut set_aside = Vec::new();
+        let mut limited = false;
+        loop {
+            let mut progress = false;
+            for entry in candidates.drain(..) {
+                if total >= self.limits.max_fragments {
+                    limited = true;
+                    break;
+                }
+                let size = *entry.fragment_size();
+                if total_size + size > self.limits.max_size {
+                    // a smaller fragment may still fit in the block
+                    limited = true;
+                    continue;
+                }
+
+                let id = entry.fragment_ref().clone();
+                let fragment = &pool.entries[&id].1;
+                match ledger_simulation.apply_fragment(ledger_params, fragment, metadata) {
+                    Ok(ledger_new) => {
+                        let fragment = pool.remove(&id).unwrap();
+                        self.fragments.push(fragment);
+
+                        logs.modify(
+                            &id.into(),
+                            FragmentStatus::InABlock {
+                                date: metadata.block_date.into(),
+                            },
+                        );
+
+                        total += 1;
+                        total_size += size;
+                        ledger_simulation = ledger_new;
+                        progress = true;
+                    }
+                    Err(error) => set_aside.push((entry, error)),
                 }
             }
+
+            if !progress || set_aside.is_empty() || total >= self.limits.max_fragments {
+                break;
+            }
+            candidates.extend(set_aside.drain(..).map(|(entry, _)| entry));
+        }
+
+        if limited {
+            // the fragments set aside may depend on a fragment that did
+            // not fit in the block, they are kept for the next blocks
+            return;
+        }
+
+        for (entry, error) in set_aside {
+            let id = entry.fragment_ref().clone();
+            pool.remove(&id);
+            logs.modify(
+                &id.into(),
+                FragmentStatus::Rejected {
+                    reason: rejection_reason(error),
+                },
+            );
         }
     }
 }
+
+pub(super) fn rejection_reason(error: ledger::Error) -> String {
+    use std::error::Error as _;
+    if let Some(source) = error.source() {
+        format!("{}: {}", error, source)
+    } else {
+        error.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fragment::{
+            test_utils::{self, TestLedger},
+            PoolLimits,
+        },
+        settings::start::EvictionPolicy,
+    };
+    use chain_core::property::Fragment as _;
+    use jormungandr_lib::interfaces::{FragmentLog, FragmentOrigin};
+    use std::{cmp::Ordering, time::Duration};
+    use tokio::{prelude::future, runtime::current_thread::Runtime};
+
+    const TTL: Duration = Duration::from_secs(3600);
+
+    const LIMITS: BlockLimits = BlockLimits {
+        max_fragments: 100,
+        max_size: 1 << 20,
+    };
+
+    struct Selection {
+        selected: Vec<FragmentId>,
+        pending: Vec<FragmentId>,
+        logs: Logs,
+    }
+
+    /// insert the fragments in the pool, in the given order, and select
+    /// the fragments of a block on top of the ledger
+    fn select<S>(test_ledger: &TestLedger, fragments: &[Fragment], mut algorithm: S) -> Selection
+    where
+        S: FragmentSelectionAlgorithm,
+    {
+        let limits = PoolLimits {
+            max_entries: 100,
+            max_size: 1 << 20,
+            eviction_policy: EvictionPolicy::OldestFirst,
+        };
+        let mut runtime = Runtime::new().unwrap();
+        runtime
+            .block_on(future::lazy(move || {
+                let mut pool = Pool::new(TTL, limits);
+                let mut logs = Logs::new(TTL);
+                for fragment in fragments {
+                    logs.insert(FragmentLog::new(
+                        fragment.id().into(),
+                        FragmentOrigin::Rest { addr: None },
+                    ));
+                    assert!(pool.insert(fragment.clone()).is_empty());
+                }
+
+                algorithm.select(
+                    &test_ledger.ledger,
+                    &test_ledger.ledger_params,
+                    &test_utils::context(),
+                    &mut logs,
+                    &mut pool,
+                );
+                future::ok::<_, ()>(Selection {
+                    selected: algorithm.finalize().iter().map(|f| f.id()).collect(),
+                    pending: pool.entries_by_time.iter().cloned().collect(),
+                    logs,
+                })
+            }))
+            .unwrap()
+    }
+
+    fn assert_lower_fee_per_byte(fragment: &Fragment, other: &Fragment) {
+        let entry = PoolEntry::new(fragment);
+        let other_entry = PoolEntry::new(other);
+        assert_eq!(entry.cmp_fee_per_byte(&other_entry), Ordering::Less);
+    }
+
+    fn size(fragment: &Fragment) -> usize {
+        *PoolEntry::new(fragment).fragment_size()
+    }
+
+    #[test]
+    fn oldest_first_selects_in_the_order_received() {
+        let test_ledger = TestLedger::new();
+        let utxos = test_ledger.initial_utxos();
+        let low_fee = test_ledger.transaction(&utxos[0..3], 1);
+        let high_fee = test_ledger.transaction(&utxos[3..4], 4);
+        assert_lower_fee_per_byte(&low_fee, &high_fee);
+
+        let selection = select(
+            &test_ledger,
+            &[low_fee.clone(), high_fee.clone()],
+            OldestFirst::new(LIMITS),
+        );
+        assert_eq!(selection.selected, vec![low_fee.id(), high_fee.id()]);
+        assert!(selection.pending.is_empty());
+    }
+
+    #[test]
+    fn oldest_first_skips_the_fragments_too_large() {
+        let test_ledger = TestLedger::new();
+        let utxos = test_ledger.initial_utxos();
+        let large = test_ledger.transaction(&utxos[0..3], 1);
+        let small = test_ledger.transaction(&utxos[3..4], 1);
+        let limits = BlockLimits {
+            max_size: size(&small),
+            ..LIMITS
+        };
+
+        // the oldest fragment does not fit in the block, the next one does
+        let selection = select(
+            &test_ledger,
+            &[large.clone(), small.clone()],
+            OldestFirst::new(limits),
+        );
+        assert_eq!(selection.selected, vec![small.id()]);
+        assert_eq!(selection.pending, vec![large.id()]);
+    }
+
+    #[test]
+    fn fee_per_byte_selects_the_highest_fee_first() {
+        let test_ledger = TestLedger::new();
+        let utxos = test_ledger.initial_utxos();
+        let low_fee = test_ledger.transaction(&utxos[0..3], 1);
+        let medium_fee = test_ledger.transaction(&utxos[3..4], 1);
+        let high_fee = test_ledger.transaction(&utxos[4..5], 4);
+        assert_lower_fee_per_byte(&low_fee, &medium_fee);
+        assert_lower_fee_per_byte(&medium_fee, &high_fee);
+
+        let selection = select(
+            &test_ledger,
+            &[low_fee.clone(), medium_fee.clone(), high_fee.clone()],
+            FeePerByte::new(LIMITS),
+        );
+        assert_eq!(
+            selection.selected,
+            vec![high_fee.id(), medium_fee.id(), low_fee.id()]
+        );
+        assert!(selection.pending.is_empty());
+    }
+
+    #[test]
+    fn fee_per_byte_skips_the_fragments_too_large() {
+        let test_ledger = TestLedger::new();
+        let utxos = test_ledger.initial_utxos();
+        let small = test_ledger.transaction(&utxos[0..1], 1);
+        let large = test_ledger.transaction(&utxos[1..2], 4);
+        assert_lower_fee_per_byte(&small, &large);
+        assert!(size(&small) < size(&large));
+        let limits = BlockLimits {
+            max_size: size(&small),
+            ..LIMITS
+        };
+
+        // the fragment paying the highest fee per byte does not fit in
+        // the block, the next one does
+        let selection = select(
+            &test_ledger,
+            &[small.clone(), large.clone()],
+            FeePerByte::new(limits),
+        );
+        assert_eq!(selection.selected, vec![small.id()]);
+        assert_eq!(selection.pending, vec![large.id()]);
+    }
+
+    #[test]
+    fn fee_per_byte_selects_the_dependencies_first() {
+        let test_ledger = TestLedger::new();
+        let utxos = test_ledger.initial_utxos();
+        let parent = test_ledger.transaction(&utxos[0..3], 1);
+        let child = test_ledger.transaction(&[test_utils::output(&parent)], 4);
+        assert_lower_fee_per_byte(&parent, &child);
+
+        // the child cannot be applied before its parent, although it
+        // pays a higher fee per byte
+        let selection = select(
+            &test_ledger,
+            &[parent.clone(), child.clone()],
+            FeePerByte::new(LIMITS),
+        );
+        assert_eq!(selection.selected, vec![parent.id(), child.id()]);
+        assert!(selection.pending.is_empty());
+    }
+
+    #[test]
+    fn fee_per_byte_keeps_the_dependents_of_a_fragment_left_out() {
+        let test_ledger = TestLedger::new();
+        let utxos = test_ledger.initial_utxos();
+        let parent = test_ledger.transaction(&utxos[0..3], 1);
+        let child = test_ledger.transaction(&[test_utils::output(&parent)], 1);
+        assert!(size(&child) < size(&parent));
+        let limits = BlockLimits {
+            max_size: size(&child),
+            ..LIMITS
+        };
+
+        // the parent does not fit in the block, the child is kept to be
+        // applied after it in a next block
+        let selection = select(
+            &test_ledger,
+            &[parent.clone(), child.clone()],
+            FeePerByte::new(limits),
+        );
+        assert!(selection.selected.is_empty());
+        assert_eq!(selection.pending, vec![parent.id(), child.id()]);
+    }
+
+    #[test]
+    fn fee_per_byte_rejects_the_invalid_fragments() {
+        let test_ledger = TestLedger::new();
+        let utxos = test_ledger.initial_utxos();
+        let spent = test_ledger.transaction(&utxos[0..1], 1);
+        let double_spent = test_ledger.transaction(&utxos[0..2], 1);
+        assert_lower_fee_per_byte(&double_spent, &spent);
+
+        let selection = select(
+            &test_ledger,
+            &[double_spent.clone(), spent.clone()],
+            FeePerByte::new(LIMITS),
+        );
+        assert_eq!(selection.selected, vec![spent.id()]);
+        assert!(selection.pending.is_empty());
+        let log = selection.logs.get(&double_spent.id().into()).unwrap();
+        assert!(log.is_rejected());
+    }
+}
@@ -0,0 +1,324 @@
+//! accounting of the fragments received from each sender
+//!
+//! The peers and the REST clients may send only a limited number of
+//! fragments within a given interval. A sender whose fragments are
+//! repeatedly invalid (malformed, wrongly signed or too large) is banned
+//! for a while: its fragments are dropped without being checked against
+//! the ledger.
+
+use jormungandr_lib::interfaces::{FragmentOrigin, FragmentSender, FragmentSenderBan};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct SenderLimits {
+    /// maximum number of fragments a sender may send within `interval`
+    pub max_fragments: usize,
+    /// interval over which the fragments of a sender are accounted for
+    pub interval: Duration,
+    /// number of invalid fragments within `interval` after which the
+    /// sender is banned
+    pub max_rejections: usize,
+    /// duration of the bans
+    pub ban_duration: Duration,
+}
+
+/// reason for refusing the fragments of a sender
+#[derive(Debug, Clone)]
+pub enum Refusal {
+    Banned(FragmentSenderBan),
+    QuotaExceeded(FragmentSender),
+}
+
+#[derive(Clone)]
+pub struct Senders {
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    limits: SenderLimits,
+    senders: HashMap<FragmentSender, SenderState>,
+}
+
+struct SenderState {
+    window_start: Instant,
+    received: usize,
+    rejections: usize,
+    ban: Option<(Instant, FragmentSenderBan)>,
+}
+
+impl Senders {
+    pub fn new(limits: SenderLimits) -> Self {
+        Senders {
+            inner: Arc::new(Mutex::new(Inner {
+                limits,
+                senders: HashMap::new(),
+            })),
+        }
+    }
+
+    /// account for a fragment received from the given origin, the fragment
+    /// is refused if its sender is banned or has exceeded its quota.
+    pub fn admit(&self, origin: &FragmentOrigin) -> Result<(), Refusal> {
+        let sender = match FragmentSender::from_origin(origin) {
+            None => return Ok(()),
+            Some(sender) => sender,
+        };
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        let limits = inner.limits;
+        let state = inner
+            .senders
+            .entry(sender.clone())
+            .or_insert_with(|| SenderState::new(now));
+
+        if let Some(ban) = state.current_ban(now) {
+            return Err(Refusal::Banned(ban.clone()));
+        }
+        state.refresh(now, limits.interval);
+        state.received += 1;
+        if state.received > limits.max_fragments {
+            return Err(Refusal::QuotaExceeded(sender));
+        }
+        Ok(())
+    }
+
+    /// account for an invalid fragment of the given origin, returns the
+    /// ban of the sender if it has just been banned.
+    pub fn record_rejection(&self, origin: &FragmentOrigin) -> Option<FragmentSenderBan> {
+        let sender = FragmentSender::from_origin(origin)?;
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        let limits = inner.limits;
+        let state = inner
+            .senders
+            .entry(sender.clone())
+            .or_insert_with(|| SenderState::new(now));
+
+        if state.current_ban(now).is_some() {
+            return None;
+        }
+        state.refresh(now, limits.interval);
+        state.rejections += 1;
+        if state.rejections < limits.max_rejections {
+            return None;
+        }
+
+        let ban = FragmentSenderBan::new(
+            sender,
+            limits.ban_duration,
+            format!(
+                "{} invalid fragments within {:?}",
+                state.rejections, limits.interval
+            ),
+        );
+        state.ban = Some((now + limits.ban_duration, ban.clone()));
+        state.received = 0;
+        state.rejections = 0;
+        Some(ban)
+    }
+
+    /// whether the sender of the given origin is currently banned
+    pub fn is_banned(&self, origin: &FragmentOrigin) -> bool {
+        let sender = match FragmentSender::from_origin(origin) {
+            None => return false,
+            Some(sender) => sender,
+        };
+        let now = Instant::now();
+        let inner = self.inner.lock().unwrap();
+        inner
+            .senders
+            .get(&sender)
+            .map_or(false, |state| state.current_ban(now).is_some())
+    }
+
+    /// the senders currently banned
+    pub fn bans(&self) -> Vec<FragmentSenderBan> {
+        let now = Instant::now();
+        let inner = self.inner.lock().unwrap();
+        inner
+            .senders
+            .values()
+            .filter_map(|state| state.current_ban(now).cloned())
+            .collect()
+    }
+
+    /// forget the senders that are no longer banned and that did not send
+    /// any fragment in the current interval
+    pub fn purge(&self) {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        let interval = inner.limits.interval;
+        inner.senders.retain(|_, state| {
+            state.current_ban(now).is_some() || now.duration_since(state.window_start) < interval
+        });
+    }
+}
+
+impl SenderState {
+    fn new(now: Instant) -> Self {
+        SenderState {
+            window_start: now,
+            received: 0,
+            rejections: 0,
+            ban: None,
+        }
+    }
+
+    fn current_ban(&self, now: Instant) -> Option<&FragmentSenderBan> {
+        match &self.ban {
+            Some((until, ban)) if *until > now => Some(ban),
+            _ => None,
+        }
+    }
+
+    /// start a new accounting interval if the current one has elapsed
+    fn refresh(&mut self, now: Instant, interval: Duration) {
+        if self.ban.is_some() && self.current_ban(now).is_none() {
+            self.ban = None;
+        }
+        if now.duration_since(self.window_start) >= interval {
+            self.window_start = now;
+            self.received = 0;
+            self.rejections = 0;
+        }
+    }
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Refusal::Banned(ban) => write!(
+                f,
+                "{} is banned from sending fragments: {}",
+                ban.sender(),
+                ban.reason()
+            ),
+            Refusal::QuotaExceeded(sender) => {
+                write!(f, "{} has sent too many fragments", sender)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    const LONG: Duration = Duration::from_secs(3600);
+    const SHORT: Duration = Duration::from_millis(50);
+
+    fn limits(interval: Duration, ban_duration: Duration) -> SenderLimits {
+        SenderLimits {
+            max_fragments: 2,
+            interval,
+            max_rejections: 2,
+            ban_duration,
+        }
+    }
+
+    fn node(node_id: &str) -> FragmentOrigin {
+        FragmentOrigin::Network {
+            node_id: node_id.to_owned(),
+            addr: None,
+        }
+    }
+
+    #[test]
+    fn refuse_beyond_the_quota() {
+        let senders = Senders::new(limits(LONG, LONG));
+        assert!(senders.admit(&node("a")).is_ok());
+        assert!(senders.admit(&node("a")).is_ok());
+        match senders.admit(&node("a")) {
+            Err(Refusal::QuotaExceeded(_)) => {}
+            _ => panic!("the quota of the sender is not enforced"),
+        }
+        // the quota is per sender
+        assert!(senders.admit(&node("b")).is_ok());
+    }
+
+    #[test]
+    fn reset_the_quota_after_the_interval() {
+        let senders = Senders::new(limits(SHORT, LONG));
+        assert!(senders.admit(&node("a")).is_ok());
+        assert!(senders.admit(&node("a")).is_ok());
+        assert!(senders.admit(&node("a")).is_err());
+
+        thread::sleep(SHORT);
+        assert!(senders.admit(&node("a")).is_ok());
+    }
+
+    #[test]
+    fn do_not_account_for_unknown_senders() {
+        let senders = Senders::new(limits(LONG, LONG));
+        let origin = FragmentOrigin::Rest { addr: None };
+        for _ in 0..4 {
+            assert!(senders.admit(&origin).is_ok());
+            assert!(senders.record_rejection(&origin).is_none());
+        }
+        assert!(senders.bans().is_empty());
+    }
+
+    #[test]
+    fn ban_after_too_many_rejections() {
+        let senders = Senders::new(limits(LONG, LONG));
+        assert!(senders.record_rejection(&node("a")).is_none());
+        let ban = senders.record_rejection(&node("a")).unwrap();
+        assert_eq!(
+            ban.sender(),
+            &FragmentSender::from_origin(&node("a")).unwrap()
+        );
+
+        match senders.admit(&node("a")) {
+            Err(Refusal::Banned(_)) => {}
+            _ => panic!("the banned sender is admitted"),
+        }
+        assert_eq!(senders.bans().len(), 1);
+        // the rejections of a banned sender do not extend its ban
+        assert!(senders.record_rejection(&node("a")).is_none());
+        assert!(senders.admit(&node("b")).is_ok());
+    }
+
+    #[test]
+    fn lift_the_ban_after_its_duration() {
+        let senders = Senders::new(limits(LONG, SHORT));
+        senders.record_rejection(&node("a"));
+        assert!(senders.record_rejection(&node("a")).is_some());
+        assert!(senders.admit(&node("a")).is_err());
+
+        thread::sleep(SHORT);
+        assert!(senders.bans().is_empty());
+        assert!(senders.admit(&node("a")).is_ok());
+        // the rejections before the ban are forgotten
+        assert!(senders.record_rejection(&node("a")).is_none());
+    }
+
+    #[test]
+    fn count_the_rejections_within_the_interval() {
+        let senders = Senders::new(limits(SHORT, LONG));
+        assert!(senders.record_rejection(&node("a")).is_none());
+
+        thread::sleep(SHORT);
+        assert!(senders.record_rejection(&node("a")).is_none());
+        assert!(senders.bans().is_empty());
+    }
+
+    #[test]
+    fn purge_the_inactive_senders() {
+        let senders = Senders::new(limits(SHORT, LONG));
+        senders.admit(&node("a")).unwrap();
+        senders.record_rejection(&node("b"));
+        senders.record_rejection(&node("b"));
+
+        thread::sleep(SHORT);
+        senders.purge();
+        // only the banned sender is remembered
+        assert_eq!(senders.inner.lock().unwrap().senders.len(), 1);
+        assert_eq!(senders.bans().len(), 1);
+    }
+}
@@ -0,0 +1,135 @@
+//! helpers to build the fragments of the tests of the fragment module.
+//! The fragments are transactions spending the initial UTxOs of a test
+//! ledger, which are all held by a single key.
+
+use crate::blockcfg::{
+    BlockDate, Fragment, HeaderContentEvalContext, Leadership, Ledger, LedgerParameters,
+};
+use chain_addr::{Address, Kind};
+use chain_core::property::Fragment as _;
+use chain_crypto::{Ed25519, SecretKey};
+use chain_impl_mockchain::{
+    fee::{FeeAlgorithm as _, LinearFee},
+    key::Hash,
+    transaction::{
+        AuthenticatedTransaction, Input, InputEnum, NoExtra, Output, Transaction, UtxoPointer,
+        Witness,
+    },
+    value::Value,
+};
+use jormungandr_lib::interfaces::{
+    block0_configuration_documented_example, Block0Configuration, Initial, InitialUTxO,
+};
+
+/// number of initial UTxOs of the test ledger
+const INITIAL_UTXOS: usize = 8;
+/// value of each initial UTxO of the test ledger
+const INITIAL_VALUE: u64 = 1_000;
+
+pub struct TestLedger {
+    pub ledger: Ledger,
+    pub ledger_params: LedgerParameters,
+    block0_hash: Hash,
+    secret_key: SecretKey<Ed25519>,
+    address: Address,
+}
+
+impl TestLedger {
+    /// make the ledger of the documented example of the block0, its
+    /// initial funds replaced by `INITIAL_UTXOS` UTxOs of a new key. The
+    /// fee is proportional to the number of inputs and outputs.
+    pub fn new() -> Self {
+        let mut configuration: Block0Configuration =
+            serde_yaml::from_str(&block0_configuration_documented_example()).unwrap();
+        let secret_key = SecretKey::<Ed25519>::generate(rand::thread_rng());
+        let address = Address(
+            configuration.blockchain_configuration.discrimination,
+            Kind::Single(secret_key.to_public()),
+        );
+        let funds = vec![
+            InitialUTxO {
+                address: address.clone().into(),
+                value: INITIAL_VALUE.into(),
+            };
+            INITIAL_UTXOS
+        ];
+        configuration.initial = vec![Initial::Fund(funds)];
+        configuration.blockchain_configuration.linear_fees = LinearFee::new(0, 1, 0);
+
+        let block0 = configuration.to_block();
+        let block0_hash = block0.header.hash();
+        let ledger = Ledger::new(block0_hash, block0.contents.iter()).unwrap();
+        let ledger_params = Leadership::new(0, &ledger).ledger_parameters().clone();
+        TestLedger {
+            ledger,
+            ledger_params,
+            block0_hash,
+            secret_key,
+            address,
+        }
+    }
+
+    /// the initial UTxOs of the ledger
+    pub fn initial_utxos(&self) -> Vec<UtxoPointer> {
+        self.ledger
+            .utxos()
+            .map(|utxo| UtxoPointer {
+                transaction_id: utxo.fragment_id,
+                output_index: utxo.output_index,
+                value: utxo.output.value,
+            })
+            .collect()
+    }
+
+    /// make a transaction spending the inputs into the given number of
+    /// outputs. An output weighs less than an input and its witness: the
+    /// more outputs the higher the fee per byte, the more inputs the lower.
+    pub fn transaction(&self, inputs: &[UtxoPointer], outputs: usize) -> Fragment {
+        let mut transaction = Transaction {
+            inputs: inputs
+                .iter()
+                .map(|input| Input::from_enum(InputEnum::UtxoInput(input.clone())))
+                .collect(),
+            outputs: vec![Output::from_address(self.address.clone(), Value(0)); outputs],
+            extra: NoExtra,
+        };
+        let fee = self.ledger_params.fees.calculate(&transaction).unwrap();
+        let input_sum = Value::sum(inputs.iter().map(|input| input.value)).unwrap();
+        let output_sum = (input_sum - fee).unwrap().0;
+        let output_value = output_sum / outputs as u64;
+        for output in transaction.outputs.iter_mut() {
+            output.value = Value(output_value);
+        }
+        transaction.outputs[0].value = Value(output_sum - output_value * (outputs as u64 - 1));
+
+        let witness = Witness::new_utxo(&self.block0_hash, &transaction.hash(), &self.secret_key);
+        Fragment::Transaction(AuthenticatedTransaction {
+            transaction,
+            witnesses: vec![witness; inputs.len()],
+        })
+    }
+}
+
+/// the first output of the transaction
+pub fn output(fragment: &Fragment) -> UtxoPointer {
+    match fragment {
+        Fragment::Transaction(tx) => UtxoPointer {
+            transaction_id: fragment.id(),
+            output_index: 0,
+            value: tx.transaction.outputs[0].value,
+        },
+        _ => panic!("not a transaction"),
+    }
+}
+
+/// the context of the fragments applied in the first block after block0
+pub fn context() -> HeaderContentEvalContext {
+    HeaderContentEvalContext {
+        block_date: BlockDate {
+            epoch: 0,
+            slot_id: 1,
+        },
+        chain_length: 1.into(),
+        nonce: None,
+    }
+}
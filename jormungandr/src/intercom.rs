@@ -2,7 +2,7 @@ use crate::blockcfg::{Block, Epoch, Fragment, FragmentId, Header, HeaderHash};
 use crate::network::p2p::topology::NodeId;
 use futures::prelude::*;
 use futures::sync::{mpsc, oneshot};
-use jormungandr_lib::interfaces::FragmentOrigin;
+use jormungandr_lib::interfaces::{FragmentOrigin, FragmentSubmission};
 use network_core::error as core_error;
 use slog::Logger;
 use std::{
@@ -255,7 +255,13 @@ where
 #[derive(Debug)]
 pub enum TransactionMsg {
     ProposeTransaction(Vec<FragmentId>, ReplyHandle<Vec<bool>>),
-    SendTransaction(FragmentOrigin, Vec<Fragment>),
+    /// send fragments to the pool, the optional reply gives the outcome of
+    /// the submission of each fragment
+    SendTransaction(
+        FragmentOrigin,
+        Vec<Fragment>,
+        Option<ReplyHandle<Vec<FragmentSubmission>>>,
+    ),
     GetTransactions(Vec<FragmentId>, ReplyStreamHandle<Fragment>),
 }
 
@@ -343,8 +349,10 @@ pub enum NetworkMsg {
     Propagate(PropagateMsg),
     GetBlocks(Vec<HeaderHash>),
     GetNextBlock(NodeId, HeaderHash),
+    /// pull the headers from the given node, or from any connected
+    /// node if `node_id` is `None`.
     PullHeaders {
-        node_id: NodeId,
+        node_id: Option<NodeId>,
         from: Vec<HeaderHash>,
         to: HeaderHash,
     },
@@ -1,69 +1,127 @@
 use crate::{
-    blockcfg::{Block, BlockBuilder, Leadership},
-    secure::enclave::Enclave as SecureEnclave,
+    blockcfg::{Block, Leadership, Ledger},
+    secure::{
+        enclave::{BlockTemplate, Enclave as SecureEnclave},
+        protocol::{self, EnclaveKey, Request},
+    },
 };
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 use tokio::{prelude::*, sync::lock::Lock};
 
 pub use crate::secure::enclave::LeaderEvent;
 
-error_chain! {}
+error_chain! {
+    foreign_links {
+        Protocol(protocol::Error);
+    }
+}
 
 /// represent the client side of an enclave. From there we will query the
 /// actual enclave about schedules and signing blocks
 ///
 #[derive(Clone)]
 pub struct Enclave {
-    /// TODO: we will need to remove this to instead query data to an
-    /// external service (running in the secure enclave). For now we
-    /// hold on the `SecureEnclave` . But it will need to be separated
-    /// when we have the necessary crypto done.
-    inner: Lock<SecureEnclave>,
+    inner: Inner,
+}
+
+#[derive(Clone)]
+enum Inner {
+    /// the enclave runs in the node, holding the leader keys
+    Local(Lock<SecureEnclave>),
+    /// the enclave runs in an external daemon, listening on the given
+    /// Unix-domain socket: the leader keys are not loaded in the node
+    Remote { socket: PathBuf, key: EnclaveKey },
 }
 
 impl Enclave {
-    /// create a new enclave structure. This will need to change in the future
-    /// with a parameter to contact the secure enclave instead of the dummy type.
+    /// create a new enclave structure, holding the leader keys in the node
     pub fn new(secure_enclave: SecureEnclave) -> Self {
         Enclave {
-            inner: Lock::new(secure_enclave),
+            inner: Inner::Local(Lock::new(secure_enclave)),
+        }
+    }
+
+    /// create an enclave structure querying the enclave daemon listening
+    /// on the given socket
+    pub fn remote(socket: PathBuf, key: EnclaveKey) -> Self {
+        Enclave {
+            inner: Inner::Remote { socket, key },
         }
     }
 
     /// ask the enclave to attempt computing some leadership schedule for the
     /// given settings
     ///
-    /// TODO: for now we are utilizing the Leadership object fully but on the long
-    ///       run this might be limited to only the required data.
+    /// The external enclave computes the leadership from the `ledger` the
+    /// `leadership` has been computed from.
     pub fn query_schedules(
         &self,
         leadership: Arc<Leadership>,
+        ledger: Arc<Ledger>,
         slot_start: u32,
         nb_slots: u32,
     ) -> impl Future<Item = Vec<LeaderEvent>, Error = Error> {
-        let mut inner = self.inner.clone();
-        future::poll_fn(move || Ok(inner.poll_lock()))
-            .map(move |guard| guard.leadership_evaluate(&leadership, slot_start, nb_slots))
+        match &self.inner {
+            Inner::Local(inner) => {
+                let mut inner = inner.clone();
+                future::Either::A(
+                    future::poll_fn(move || Ok(inner.poll_lock())).map(move |guard| {
+                        guard.leadership_evaluate(&leadership, slot_start, nb_slots)
+                    }),
+                )
+            }
+            Inner::Remote { socket, key } => {
+                let socket = socket.clone();
+                let key = key.clone();
+                future::Either::B(
+                    future::result(Request::schedules(
+                        leadership.epoch(),
+                        &ledger,
+                        slot_start,
+                        nb_slots,
+                    ))
+                    .and_then(move |request| protocol::query(socket, key, request))
+                    .and_then(|response| response.into_schedules())
+                    .map_err(Error::from),
+                )
+            }
+        }
     }
 
     /// ask the leader associated to the `LeaderEvent` to finalize the given
     /// block by providing the proof.
     ///
-    /// TODO: for now we are querying the whole with the block builder but on the long
-    ///       run we will only need the block signing data.
+    /// The external enclave uses the leadership it has computed when
+    /// queried for the schedules of the epoch of the block.
     pub fn query_block_finalize(
         &self,
-        block_builder: BlockBuilder,
+        leadership: Arc<Leadership>,
+        template: BlockTemplate,
         event: LeaderEvent,
     ) -> impl Future<Item = Block, Error = Error> {
-        let mut inner = self.inner.clone();
-
-        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |guard| {
-            if let Some(block) = guard.create_block(block_builder, event) {
-                future::ok(block)
-            } else {
-                future::err("Leader is not in the enclave to sign the block".into())
+        match &self.inner {
+            Inner::Local(inner) => {
+                let mut inner = inner.clone();
+                future::Either::A(future::poll_fn(move || Ok(inner.poll_lock())).and_then(
+                    move |guard| {
+                        if let Some(block) = guard.create_block(&leadership, template, event) {
+                            future::ok(block)
+                        } else {
+                            future::err("Leader is not in the enclave to sign the block".into())
+                        }
+                    },
+                ))
             }
-        })
+            Inner::Remote { socket, key } => {
+                let socket = socket.clone();
+                let key = key.clone();
+                future::Either::B(
+                    future::result(Request::block_finalize(&template, event))
+                        .and_then(move |request| protocol::query(socket, key, request))
+                        .and_then(|response| response.into_block())
+                        .map_err(Error::from),
+                )
+            }
+        }
     }
 }
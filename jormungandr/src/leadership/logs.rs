@@ -1,4 +1,4 @@
-use jormungandr_lib::interfaces::{LeadershipLog, LeadershipLogId};
+use jormungandr_lib::interfaces::{LeadershipLog, LeadershipLogId, LeadershipLogStatus};
 use std::time::Duration;
 use tokio::{
     prelude::*,
@@ -47,6 +47,11 @@ impl LeadershipLogHandle {
     pub fn mark_finished(&self) -> impl Future<Item = (), Error = ()> {
         self.logs.mark_finished(self.internal_id)
     }
+
+    /// set the outcome of the leadership event
+    pub fn set_status(&self, status: LeadershipLogStatus) -> impl Future<Item = (), Error = ()> {
+        self.logs.set_status(self.internal_id, status)
+    }
 }
 
 impl Logs {
@@ -93,6 +98,34 @@ impl Logs {
         })
     }
 
+    /// set the outcome of the leadership event of the given log
+    pub fn set_status(
+        &self,
+        leadership_log_id: LeadershipLogId,
+        status: LeadershipLogStatus,
+    ) -> impl Future<Item = (), Error = ()> {
+        self.inner().and_then(move |mut guard| {
+            guard.set_status(&leadership_log_id, status);
+            future::ok(())
+        })
+    }
+
+    /// the logs of the leadership events which created a block, with
+    /// their current status
+    pub fn produced_blocks(
+        &self,
+    ) -> impl Future<Item = Vec<(LeadershipLogId, LeadershipLogStatus)>, Error = ()> {
+        self.inner().and_then(|guard| {
+            future::ok(
+                guard
+                    .logs()
+                    .filter(|log| log.status().block().is_some())
+                    .map(|log| (log.leadership_log_id(), log.status().clone()))
+                    .collect(),
+            )
+        })
+    }
+
     pub fn poll_purge(&mut self) -> impl Future<Item = (), Error = timer::Error> {
         self.inner()
             .and_then(move |mut guard| future::poll_fn(move || guard.poll_purge()))
@@ -110,7 +143,7 @@ impl Logs {
 }
 
 pub(super) mod internal {
-    use super::{LeadershipLog, LeadershipLogId};
+    use super::{LeadershipLog, LeadershipLogId, LeadershipLogStatus};
     use std::{
         collections::HashMap,
         time::{Duration, Instant},
@@ -175,6 +208,18 @@ pub(super) mod internal {
             }
         }
 
+        pub fn set_status(
+            &mut self,
+            leadership_log_id: &LeadershipLogId,
+            status: LeadershipLogStatus,
+        ) {
+            if let Some((ref mut log, ref key)) = self.entries.get_mut(leadership_log_id) {
+                log.set_status(status);
+
+                self.expirations.reset_at(key, Instant::now() + self.ttl);
+            }
+        }
+
         pub fn poll_purge(&mut self) -> Poll<(), timer::Error> {
             loop {
                 match self.expirations.poll()? {
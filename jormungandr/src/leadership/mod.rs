@@ -19,12 +19,14 @@
 //!       have information when the node is expected to create blocks);
 //!     * optional but useful: have a way to update if a schedule has been
 //!       executed (and what time);
-//!     * optional: have a way for the blockchain task to update
-//!       the schedule to know if the scheduled block as been accepted in the
-//!       branch;
+//!     * the leadership module follows the tip changes published by the
+//!       blockchain task to update the logs of the created blocks: adopted
+//!       while they are on the main chain, orphaned when the main chain
+//!       switched to a fork without them;
 //!
-//! The enclave is not yet implemented, but we will need to separate the crypto
-//! from the representation here.
+//! The enclave either runs within the node or in an external daemon the node
+//! queries over a Unix-domain socket (see `secure::daemon`), in which case the
+//! leader keys are never loaded in the node.
 //!
 //! ## workflow
 //!
@@ -53,8 +55,12 @@
 //! 1. the blockchain detects an epoch transition,
 //! 2. the leadership sent an end of epoch signal to the blockchain;
 //!
-//! Now doing so we may trigger the same leader schedule twice. We will need to make sure
-//! we don't duplicate the work everywhere.
+//! Now doing so we may trigger the same leader schedule twice: the `Schedules` do not
+//! hold the same leader event twice.
+//!
+//! The schedule of the current epoch is also evaluated again when the leaders of
+//! the enclave change (see `LeadersChanged`): the leader events no longer elected
+//! are removed and the new ones are added.
 //!
 
 mod enclave;
@@ -66,18 +72,27 @@ pub use self::logs::{LeadershipLogHandle, Logs};
 pub use self::schedule::{Schedule, Schedules};
 use crate::{
     blockcfg::{
-        BlockBuilder, BlockDate, Epoch, HeaderContentEvalContext, Leadership, LedgerParameters,
+        BlockDate, ChainLength, Epoch, HeaderContentEvalContext, HeaderHash, Leadership, Ledger,
+        LedgerParameters,
     },
-    blockchain::Branch,
-    fragment,
+    blockchain::{Branch, ChainEvent, Storage},
+    fragment::{self, selection::BlockLimits},
     intercom::BlockMsg,
-    utils::{async_msg::MessageBox, task::TokioServiceInfo},
+    secure::enclave::BlockTemplate,
+    settings::start::FragmentSelection,
+    stats_counter::StatsCounter,
+    utils::{
+        async_msg::{MessageBox, MessageQueue},
+        task::TokioServiceInfo,
+    },
 };
+use chain_storage::error::Error as StorageError;
 use chain_time::{
     era::{EpochPosition, EpochSlotOffset},
     TimeFrame,
 };
-use jormungandr_lib::time::SystemTime;
+use jormungandr_lib::{interfaces::LeadershipLogStatus, time::SystemTime};
+use slog::Logger;
 use std::{sync::Arc, time::Duration};
 use tokio::{
     prelude::*,
@@ -102,15 +117,26 @@ error_chain! {
         CannotSendLeadershipBlock {
             description("Cannot send the leadership's new created block")
         }
+        LeaderEventSkipped(reason: String) {
+            description("leader event skipped"),
+            display("leader event skipped: {}", reason),
+        }
     }
 }
 
+#[derive(Clone)]
 pub struct NewEpochToSchedule {
     pub new_schedule: Arc<Leadership>,
     pub new_parameters: Arc<LedgerParameters>,
+    /// the ledger the leadership has been computed from
+    pub epoch_ledger: Arc<Ledger>,
     pub time_frame: TimeFrame,
 }
 
+/// notification that the leaders of the enclave changed: the leadership
+/// schedule of the current epoch is evaluated again
+pub struct LeadersChanged;
+
 pub struct LeadershipModule {
     logs: Logs,
     service_info: TokioServiceInfo,
@@ -119,41 +145,87 @@ pub struct LeadershipModule {
     tip: Branch,
     block_message: MessageBox<BlockMsg>,
     garbage_collection_interval: Duration,
+    fragment_selection: FragmentSelection,
+    block_limits: BlockLimits,
+    stats_counter: StatsCounter,
+    /// the epoch whose end has been scheduled
+    scheduled_end_of_epoch: Option<Epoch>,
 }
 
 impl LeadershipModule {
     fn handle_schedule(&self, schedule: Schedule) {
         let logger = self.service_info.logger().new(o!("leader" => schedule.leader_event().id.to_string(), "date" => schedule.leader_event().date.to_string()));
         let fragment_pool = self.fragment_pool.clone();
+        let fragment_selection = self.fragment_selection;
+        let block_limits = self.block_limits;
         let tip = self.tip.clone();
         let enclave = self.enclave.clone();
         let leader_event: LeaderEvent = schedule.leader_event;
+        let leadership = schedule.leadership;
         let date = leader_event.date.clone();
         let ledger_parameters = schedule.epoch_ledger_parameters;
         let sender = self.block_message.clone();
-        let log_awake = schedule.log.mark_wake();
-        let log_finish = schedule.log.mark_finished();
-
+        let log = schedule.log;
+        let log_awake = log.mark_wake();
+        let log_finish = log.mark_finished();
+        let stats_counter = self.stats_counter.clone();
+        let outcome_logger = logger.clone();
+
+        let create_block = log_awake
+            .map_err(|()| unreachable!())
+            .and_then(move |()| {
+                info!(logger, "leader event starting");
+
+                prepare_block(
+                    fragment_pool,
+                    fragment_selection,
+                    block_limits,
+                    date,
+                    tip,
+                    ledger_parameters,
+                )
+            })
+            .and_then(move |template| {
+                enclave
+                    .query_block_finalize(leadership, template, leader_event)
+                    .map_err(|e| Error::with_chain(e, ErrorKind::Enclave))
+            })
+            .and_then(|block| {
+                let block_id = block.header.hash();
+                sender
+                    .send(BlockMsg::LeadershipBlock(block))
+                    .map(move |_| block_id)
+                    .map_err(|_send_error| ErrorKind::CannotSendLeadershipBlock.into())
+            });
+
+        // a failing leader event is recorded in the leadership logs, it
+        // does not prevent the next leader events from being processed
         self.service_info.spawn(
-            log_awake
-                .map_err(|()| unreachable!())
-                .and_then(move |()| {
-                    info!(logger, "leader event starting");
-
-                    prepare_block(fragment_pool, date, tip, ledger_parameters)
-                })
-                .and_then(move |bb| {
-                    enclave
-                        .query_block_finalize(bb, leader_event)
-                        .map_err(|_| unimplemented!())
-                })
-                .and_then(|block| {
-                    sender
-                        .send(BlockMsg::LeadershipBlock(block))
-                        .map_err(|_send_error| ErrorKind::CannotSendLeadershipBlock.into())
+            create_block
+                .then(move |result: Result<HeaderHash, Error>| {
+                    let status = match result {
+                        Ok(block_id) => {
+                            stats_counter.add_leader_block_cnt(1);
+                            info!(outcome_logger, "block created" ; "block" => block_id.to_string());
+                            LeadershipLogStatus::Block {
+                                block: block_id.into(),
+                            }
+                        }
+                        Err(Error(ErrorKind::LeaderEventSkipped(reason), _)) => {
+                            stats_counter.add_leader_skipped_cnt(1);
+                            info!(outcome_logger, "leader event skipped" ; "reason" => &reason);
+                            LeadershipLogStatus::Skipped { reason }
+                        }
+                        Err(error) => {
+                            let reason = error_reason(&error);
+                            stats_counter.add_leader_failed_cnt(1);
+                            error!(outcome_logger, "leader event failed" ; "reason" => &reason);
+                            LeadershipLogStatus::Failed { reason }
+                        }
+                    };
+                    log.set_status(status)
                 })
-                .and_then(|_: MessageBox<BlockMsg>| log_finish.map_err(|()| unreachable!()))
-                .map_err(|_: Error| unimplemented!()),
+                .and_then(move |()| log_finish),
         );
     }
 
@@ -191,16 +263,18 @@ impl LeadershipModule {
     }
 
     fn handle_new_epoch_event(
-        self,
+        mut self,
         scheduler: Schedules,
         new_epoch_event: NewEpochToSchedule,
     ) -> impl Future<Item = (Self, Schedules), Error = Error> {
         let leadership = new_epoch_event.new_schedule;
         let epoch_parameters = new_epoch_event.new_parameters;
+        let epoch_ledger = new_epoch_event.epoch_ledger;
         let era = leadership.era().clone();
         let epoch = leadership.epoch();
         let time_frame = new_epoch_event.time_frame;
         let logs = self.logs.clone();
+        let stats_counter = self.stats_counter.clone();
 
         let current_slot = time_frame.slot_at(&std::time::SystemTime::now()).unwrap();
         let within_era = era.from_slot_to_era(current_slot).unwrap();
@@ -209,28 +283,40 @@ impl LeadershipModule {
         let nb_slots = era.slots_per_epoch() - slot_start;
 
         let logger = self.service_info.logger().new(o!("epoch" => epoch));
+        let error_logger = logger.clone();
 
         debug!(logger, "handling new epoch event";
             "slot start" => slot_start,
             "nb_slots" => nb_slots,
         );
 
-        self.spawn_end_of_epoch(
-            &time_frame,
-            epoch,
-            era.from_era_to_slot(EpochPosition {
-                epoch: chain_time::Epoch(epoch + 1),
-                slot: EpochSlotOffset(0),
-            }),
-        );
+        // the schedule of an epoch is evaluated again when the leaders
+        // change, the end of the epoch is only scheduled once
+        if self.scheduled_end_of_epoch != Some(epoch) {
+            self.spawn_end_of_epoch(
+                &time_frame,
+                epoch,
+                era.from_era_to_slot(EpochPosition {
+                    epoch: chain_time::Epoch(epoch + 1),
+                    slot: EpochSlotOffset(0),
+                }),
+            );
+            self.scheduled_end_of_epoch = Some(epoch);
+        }
 
-        self.enclave
-            .query_schedules(leadership.clone(), slot_start, nb_slots)
-            .map_err(|e| Error::with_chain(e, ErrorKind::Enclave))
-            .and_then(move |schedules| {
+        let query = self
+            .enclave
+            .query_schedules(leadership.clone(), epoch_ledger, slot_start, nb_slots)
+            .map_err(|e| Error::with_chain(e, ErrorKind::Enclave));
+        retain_elected(scheduler, epoch, query, stats_counter, error_logger)
+            .and_then(move |(scheduler, schedules)| {
                 stream::iter_ok::<_, Error>(schedules).fold(
                     scheduler,
                     move |scheduler, schedule| {
+                        if scheduler.contains(&schedule) {
+                            return future::Either::A(future::ok(scheduler));
+                        }
+
                         let slot = era.from_era_to_slot(EpochPosition {
                             epoch: chain_time::Epoch(schedule.date.epoch),
                             slot: EpochSlotOffset(schedule.date.slot_id),
@@ -293,16 +379,64 @@ impl LeadershipModule {
             })
     }
 
+    /// follow the tip of the blockchain to update the status of the blocks
+    /// created by the leadership events
+    fn spawn_block_adoption(&self, chain_events: MessageQueue<ChainEvent>, storage: Storage) {
+        let logs = self.logs.clone();
+        let logger = self
+            .service_info
+            .logger()
+            .new(o!("sub task" => "block adoption"));
+        self.service_info
+            .spawn(chain_events.for_each(move |event| match event {
+                ChainEvent::TipChanged {
+                    hash, chain_length, ..
+                } => future::Either::A(update_block_adoption(
+                    logs.clone(),
+                    storage.clone(),
+                    logger.clone(),
+                    hash,
+                    chain_length,
+                )),
+                _ => future::Either::B(future::ok(())),
+            }));
+    }
+
     pub fn start(
         service_info: TokioServiceInfo,
         logs: Logs,
         garbage_collection_interval: Duration,
         enclave: Enclave,
         fragment_pool: fragment::Pool,
+        fragment_selection: FragmentSelection,
+        block_limits: BlockLimits,
         tip_branch: Branch,
         new_epoch_events: mpsc::Receiver<NewEpochToSchedule>,
         block_message: MessageBox<BlockMsg>,
+        stats_counter: StatsCounter,
+        chain_events: MessageQueue<ChainEvent>,
+        storage: Storage,
+        leaders_changed: MessageQueue<LeadersChanged>,
     ) -> impl Future<Item = (), Error = Error> {
+        // the schedule of the last epoch is evaluated again when the
+        // leaders of the enclave change
+        let mut last_epoch_event = None;
+        let new_epoch_events = new_epoch_events
+            .map(Some)
+            .map_err(|error| Error::with_chain(error, ErrorKind::NewEpochToScheduleReceiverError))
+            .select(
+                leaders_changed
+                    .map(|LeadersChanged| None)
+                    .map_err(|()| ErrorKind::NewEpochToScheduleReceiverError.into()),
+            )
+            .filter_map(move |new_epoch_event| match new_epoch_event {
+                Some(new_epoch_event) => {
+                    last_epoch_event = Some(new_epoch_event.clone());
+                    Some(new_epoch_event)
+                }
+                None => last_epoch_event.clone(),
+            });
+
         let scheduler_future = Schedules::new().into_future();
         let new_epoch_future = new_epoch_events.into_future();
 
@@ -314,9 +448,14 @@ impl LeadershipModule {
             tip: tip_branch,
             block_message,
             garbage_collection_interval,
+            fragment_selection,
+            block_limits,
+            stats_counter,
+            scheduled_end_of_epoch: None,
         };
 
         leadership_module.spawn_log_purge();
+        leadership_module.spawn_block_adoption(chain_events, storage);
 
         future::loop_fn(
             (leadership_module, scheduler_future, new_epoch_future),
@@ -327,9 +466,7 @@ impl LeadershipModule {
                         future::Either::A(((error, _scheduler), _new_epoch_events)) => {
                             Error::with_chain(error, ErrorKind::ScheduleError)
                         }
-                        future::Either::B(((error, _new_epoch_events), _scheduler)) => {
-                            Error::with_chain(error, ErrorKind::NewEpochToScheduleReceiverError)
-                        }
+                        future::Either::B(((error, _new_epoch_events), _scheduler)) => error,
                     })
                     .and_then(move |either| {
                         match either {
@@ -384,21 +521,74 @@ impl LeadershipModule {
     }
 }
 
+/// remove from the scheduler the leader events of the epoch no longer
+/// returned by the query of the enclave, they are logged as skipped. A
+/// failed query is counted and logged, the scheduler is then left
+/// unchanged: the node keeps on processing the next epochs and the next
+/// changes of leaders. The future returns the leader events of the query,
+/// none if it failed.
+fn retain_elected<Q>(
+    mut scheduler: Schedules,
+    epoch: Epoch,
+    query: Q,
+    stats_counter: StatsCounter,
+    logger: Logger,
+) -> impl Future<Item = (Schedules, Vec<LeaderEvent>), Error = Error>
+where
+    Q: Future<Item = Vec<LeaderEvent>, Error = Error>,
+{
+    query.then(move |result| match result {
+        Ok(schedules) => {
+            // the leader events which are no longer elected: the leaders
+            // of the enclave changed since they were scheduled
+            let outdated_logs = scheduler
+                .retain_epoch(epoch, &schedules)
+                .into_iter()
+                .map(|outdated| {
+                    outdated.log.set_status(LeadershipLogStatus::Skipped {
+                        reason: "the leader is no longer in the enclave".to_owned(),
+                    })
+                })
+                .collect::<Vec<_>>();
+            future::Either::A(
+                future::join_all(outdated_logs)
+                    .map_err(|()| unreachable!())
+                    .map(move |_| (scheduler, schedules)),
+            )
+        }
+        Err(error) => {
+            stats_counter.add_leader_failed_cnt(1);
+            error!(logger, "cannot query the leadership schedules" ; "reason" => error_reason(&error));
+            future::Either::B(future::ok((scheduler, Vec::new())))
+        }
+    })
+}
+
 fn prepare_block(
     mut fragment_pool: fragment::Pool,
+    fragment_selection: FragmentSelection,
+    block_limits: BlockLimits,
     date: BlockDate,
     tip: Branch,
     epoch_parameters: Arc<LedgerParameters>,
-) -> impl Future<Item = BlockBuilder, Error = Error> {
-    use crate::fragment::selection::{FragmentSelectionAlgorithm as _, OldestFirst};
-
-    let selection_algorithm = OldestFirst::new(250 /* TODO!! */);
+) -> impl Future<Item = BlockTemplate, Error = Error> {
+    use crate::fragment::selection::{FeePerByte, FragmentSelectionAlgorithm as _, OldestFirst};
 
     tip.get_ref()
         .map_err(|_: std::convert::Infallible| unreachable!())
         .and_then(move |tip_reference| {
             use chain_core::property::ChainLength as _;
 
+            if tip_reference.block_date() >= &date {
+                return future::Either::A(future::err(
+                    ErrorKind::LeaderEventSkipped(format!(
+                        "the tip is already at date {}",
+                        tip_reference.block_date()
+                    ))
+                    .into(),
+                ));
+            }
+
             let parent_id = tip_reference.hash().clone();
             let chain_length = tip_reference.chain_length().next();
             let ledger = tip_reference.ledger();
@@ -409,18 +599,191 @@ fn prepare_block(
                 nonce: None,
             };
 
-            fragment_pool
-                .select(
-                    ledger.as_ref().clone(),
-                    metadata,
-                    epoch_parameters.as_ref().clone(),
-                    selection_algorithm,
-                )
-                .map(|selection_algorithm| selection_algorithm.finalize())
-                .map(move |mut bb| {
-                    bb.date(date).parent(parent_id).chain_length(chain_length);
-                    bb
-                })
-                .map_err(|()| ErrorKind::FragmentSelectionFailed.into())
+            let ledger = ledger.as_ref().clone();
+            let epoch_parameters = epoch_parameters.as_ref().clone();
+            let block_template = match fragment_selection {
+                FragmentSelection::OldestFirst => future::Either::A(
+                    fragment_pool
+                        .select(
+                            ledger,
+                            metadata,
+                            epoch_parameters,
+                            OldestFirst::new(block_limits),
+                        )
+                        .map(|selection_algorithm| selection_algorithm.finalize()),
+                ),
+                FragmentSelection::FeePerByte => future::Either::B(
+                    fragment_pool
+                        .select(
+                            ledger,
+                            metadata,
+                            epoch_parameters,
+                            FeePerByte::new(block_limits),
+                        )
+                        .map(|selection_algorithm| selection_algorithm.finalize()),
+                ),
+            }
+            .map(move |fragments| BlockTemplate {
+                date,
+                parent: parent_id,
+                chain_length,
+                fragments,
+            })
+            .map_err(|()| ErrorKind::FragmentSelectionFailed.into());
+            future::Either::B(block_template)
+        })
+}
+
+/// update the status of the blocks created by the leadership events against
+/// the new tip of the blockchain
+fn update_block_adoption(
+    logs: Logs,
+    storage: Storage,
+    logger: Logger,
+    tip: HeaderHash,
+    tip_chain_length: ChainLength,
+) -> impl Future<Item = (), Error = ()> {
+    let tip_chain_length = u32::from(tip_chain_length);
+    logs.produced_blocks().and_then(move |produced| {
+        stream::iter_ok(produced).for_each(move |(id, status)| {
+            let block = status
+                .block()
+                .cloned()
+                .expect("only the logs of created blocks are returned")
+                .into_hash();
+            let logs = logs.clone();
+            let logger = logger.clone();
+            is_block_adopted(
+                storage.clone(),
+                block.clone(),
+                tip.clone(),
+                tip_chain_length,
+            )
+            .then(move |result| {
+                let new_status = match result {
+                    Ok(Some(true)) => LeadershipLogStatus::Adopted {
+                        block: block.clone().into(),
+                    },
+                    Ok(Some(false)) => LeadershipLogStatus::Orphaned {
+                        block: block.clone().into(),
+                    },
+                    Ok(None) => return future::Either::A(future::ok(())),
+                    Err(error) => {
+                        warn!(logger, "cannot check if the created block is on the main chain";
+                            "block" => block.to_string(),
+                            "reason" => error.to_string());
+                        return future::Either::A(future::ok(()));
+                    }
+                };
+                if new_status == status {
+                    future::Either::A(future::ok(()))
+                } else {
+                    debug!(logger, "created block status changed";
+                        "block" => block.to_string(),
+                        "status" => format!("{:?}", new_status));
+                    future::Either::B(logs.set_status(id, new_status))
+                }
+            })
         })
+    })
+}
+
+/// check if `block` is on the chain of `tip`. The future returns `None` if
+/// it cannot be told yet: the block has not been applied or it is further
+/// than the tip.
+fn is_block_adopted(
+    storage: Storage,
+    block: HeaderHash,
+    tip: HeaderHash,
+    tip_chain_length: u32,
+) -> impl Future<Item = Option<bool>, Error = StorageError> {
+    if block == tip {
+        return future::Either::A(future::ok(Some(true)));
+    }
+    // the block0 is of chain length 0 and of depth 1
+    let tip_depth = u64::from(tip_chain_length) + 1;
+    future::Either::B(
+        storage
+            .get_block_info(block.clone())
+            .and_then(move |block_info| match block_info {
+                Some(ref block_info) if block_info.depth <= tip_depth => future::Either::A(
+                    storage
+                        .is_ancestor(block, tip)
+                        .map(|distance| Some(distance.is_some())),
+                ),
+                _ => future::Either::B(future::ok(None)),
+            }),
+    )
+}
+
+/// the description of the error and of its causes
+fn error_reason(error: &Error) -> String {
+    error
+        .iter()
+        .map(|error| error.to_string())
+        .collect::<Vec<_>>()
+        .join(": ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::test_utils;
+    use jormungandr_lib::interfaces::EnclaveLeaderId as LeaderId;
+    use slog::Discard;
+    use tokio::runtime::current_thread::Runtime;
+
+    #[test]
+    fn keep_the_schedules_when_the_enclave_fails_once() {
+        let event = LeaderEvent {
+            id: LeaderId::new(),
+            date: BlockDate {
+                epoch: 0,
+                slot_id: 1,
+            },
+        };
+        let ledger = test_utils::ledger();
+        let leadership = Arc::new(Leadership::new(0, &ledger));
+        let parameters = Arc::new(leadership.ledger_parameters().clone());
+        let logs = Logs::new(Duration::from_secs(3600));
+        let scheduled_at: SystemTime =
+            (std::time::SystemTime::now() + Duration::from_secs(3600)).into();
+        let stats_counter = StatsCounter::default();
+        let logger = Logger::root(Discard, o!());
+        let mut runtime = Runtime::new().unwrap();
+        let scheduler = runtime
+            .block_on(future::lazy(move || {
+                Schedules::new().schedule(logs, leadership, parameters, scheduled_at, event)
+            }))
+            .unwrap();
+
+        // the query fails: the leader events already scheduled are kept
+        let failure = future::err::<Vec<LeaderEvent>, Error>("enclave unreachable".into());
+        let (scheduler, events) = runtime
+            .block_on(retain_elected(
+                scheduler,
+                0,
+                failure,
+                stats_counter.clone(),
+                logger.clone(),
+            ))
+            .unwrap();
+        assert!(events.is_empty());
+        assert!(scheduler.contains(&event));
+        assert_eq!(stats_counter.leader_failed_cnt(), 1);
+
+        // the next query succeeds: the leader event is no longer elected
+        let (scheduler, events) = runtime
+            .block_on(retain_elected(
+                scheduler,
+                0,
+                future::ok(Vec::new()),
+                stats_counter.clone(),
+                logger,
+            ))
+            .unwrap();
+        assert!(events.is_empty());
+        assert!(!scheduler.contains(&event));
+        assert_eq!(stats_counter.leader_failed_cnt(), 1);
+    }
 }
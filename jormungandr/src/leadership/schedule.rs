@@ -1,9 +1,15 @@
 use crate::{
-    blockcfg::{Leadership, LedgerParameters},
+    blockcfg::{BlockDate, Epoch, Leadership, LedgerParameters},
     leadership::{LeaderEvent, LeadershipLogHandle, Logs},
 };
-use jormungandr_lib::{interfaces::LeadershipLog, time::SystemTime};
-use std::sync::Arc;
+use jormungandr_lib::{
+    interfaces::{EnclaveLeaderId as LeaderId, LeadershipLog},
+    time::SystemTime,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 use tokio::{
     prelude::*,
     timer::delay_queue::{self, DelayQueue},
@@ -27,6 +33,9 @@ pub struct Schedule {
 /// one of the main issue with the current build for the
 pub struct Schedules {
     scheduler: DelayQueue<Schedule>,
+    /// the keys of the leader events in the `scheduler`, to not schedule
+    /// the same leader event twice
+    keys: HashMap<(LeaderId, BlockDate), delay_queue::Key>,
 }
 
 impl Schedule {
@@ -51,7 +60,37 @@ impl Schedules {
     pub fn new() -> Self {
         Schedules {
             scheduler: DelayQueue::new(),
+            keys: HashMap::new(),
+        }
+    }
+
+    /// check if the given leader event is already scheduled
+    pub fn contains(&self, leader_event: &LeaderEvent) -> bool {
+        self.keys
+            .contains_key(&(leader_event.id, leader_event.date))
+    }
+
+    /// remove the leader events of the given epoch which are not in
+    /// `leader_events`, returns the removed schedules
+    pub fn retain_epoch(&mut self, epoch: Epoch, leader_events: &[LeaderEvent]) -> Vec<Schedule> {
+        let retained: HashSet<_> = leader_events
+            .iter()
+            .map(|leader_event| (leader_event.id, leader_event.date))
+            .collect();
+        let removed: Vec<_> = self
+            .keys
+            .keys()
+            .filter(|key| key.1.epoch == epoch && !retained.contains(key))
+            .cloned()
+            .collect();
+
+        let mut schedules = Vec::with_capacity(removed.len());
+        for key in removed {
+            if let Some(key) = self.keys.remove(&key) {
+                schedules.push(self.scheduler.remove(&key).into_inner());
+            }
         }
+        schedules
     }
 
     pub fn schedule(
@@ -78,7 +117,9 @@ impl Schedules {
                 leader_event,
             })
             .map(move |schedule| {
-                self.scheduler.insert_at(schedule, scheduled_time);
+                let leader_event = schedule.leader_event;
+                let key = self.scheduler.insert_at(schedule, scheduled_time);
+                self.keys.insert((leader_event.id, leader_event.date), key);
                 self
             })
     }
@@ -90,8 +131,108 @@ impl Stream for Schedules {
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
         match try_ready!(self.scheduler.poll()) {
-            Some(item) => Ok(Async::Ready(Some(item))),
+            Some(item) => {
+                let leader_event = &item.get_ref().leader_event;
+                self.keys.remove(&(leader_event.id, leader_event.date));
+                Ok(Async::Ready(Some(item)))
+            }
             None => Ok(Async::NotReady),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::test_utils;
+    use std::time::Duration;
+    use tokio::runtime::current_thread::Runtime;
+
+    fn leader_event(id: LeaderId, epoch: Epoch, slot_id: u32) -> LeaderEvent {
+        LeaderEvent {
+            id,
+            date: BlockDate { epoch, slot_id },
+        }
+    }
+
+    /// schedule the given leader events an hour from now, the
+    /// schedules are only usable within the runtime
+    fn schedules(runtime: &mut Runtime, leader_events: &[LeaderEvent]) -> Schedules {
+        let ledger = test_utils::ledger();
+        let leadership = Arc::new(Leadership::new(0, &ledger));
+        let parameters = Arc::new(leadership.ledger_parameters().clone());
+        let logs = Logs::new(Duration::from_secs(3600));
+        let scheduled_at: SystemTime =
+            (std::time::SystemTime::now() + Duration::from_secs(3600)).into();
+        let leader_events = leader_events.to_vec();
+        runtime
+            .block_on(future::lazy(move || {
+                stream::iter_ok(leader_events).fold(Schedules::new(), move |schedules, event| {
+                    schedules.schedule(
+                        logs.clone(),
+                        leadership.clone(),
+                        parameters.clone(),
+                        scheduled_at,
+                        event,
+                    )
+                })
+            }))
+            .unwrap()
+    }
+
+    fn dates(schedules: &[Schedule]) -> HashSet<BlockDate> {
+        schedules
+            .iter()
+            .map(|schedule| schedule.leader_event().date)
+            .collect()
+    }
+
+    #[test]
+    fn remove_the_leader_events_no_longer_elected() {
+        let leader = LeaderId::new();
+        let other_leader = leader.next();
+        let elected = leader_event(leader, 0, 1);
+        let not_elected = leader_event(leader, 0, 2);
+        let other_not_elected = leader_event(other_leader, 0, 3);
+        let mut runtime = Runtime::new().unwrap();
+        let mut schedules = schedules(&mut runtime, &[elected, not_elected, other_not_elected]);
+
+        let removed = schedules.retain_epoch(0, &[elected]);
+
+        assert_eq!(
+            dates(&removed),
+            vec![not_elected.date, other_not_elected.date]
+                .into_iter()
+                .collect()
+        );
+        assert!(schedules.contains(&elected));
+        assert!(!schedules.contains(&not_elected));
+        assert!(!schedules.contains(&other_not_elected));
+    }
+
+    #[test]
+    fn keep_the_leader_events_of_the_other_epochs() {
+        let leader = LeaderId::new();
+        let current_epoch = leader_event(leader, 0, 1);
+        let next_epoch = leader_event(leader, 1, 1);
+        let mut runtime = Runtime::new().unwrap();
+        let mut schedules = schedules(&mut runtime, &[current_epoch, next_epoch]);
+
+        let removed = schedules.retain_epoch(1, &[]);
+
+        assert_eq!(dates(&removed), vec![next_epoch.date].into_iter().collect());
+        assert!(schedules.contains(&current_epoch));
+        assert!(!schedules.contains(&next_epoch));
+    }
+
+    #[test]
+    fn keep_the_leader_events_still_elected() {
+        let leader = LeaderId::new();
+        let events = [leader_event(leader, 0, 1), leader_event(leader, 0, 2)];
+        let mut runtime = Runtime::new().unwrap();
+        let mut schedules = schedules(&mut runtime, &events);
+
+        assert!(schedules.retain_epoch(0, &events).is_empty());
+        assert!(events.iter().all(|event| schedules.contains(event)));
+    }
+}
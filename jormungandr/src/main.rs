@@ -2,6 +2,8 @@ extern crate actix_net;
 extern crate actix_web;
 extern crate bincode;
 extern crate bytes;
+#[cfg(test)]
+extern crate chain_addr;
 extern crate chain_core;
 extern crate chain_crypto;
 extern crate chain_impl_mockchain;
@@ -25,12 +27,14 @@ extern crate network_core;
 extern crate network_grpc;
 extern crate poldercast;
 extern crate rand;
+extern crate rpassword;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 #[macro_use]
 extern crate serde_json;
 extern crate serde_yaml;
+extern crate sled;
 #[macro_use]
 extern crate slog;
 extern crate slog_async;
@@ -44,17 +48,21 @@ extern crate slog_syslog;
 extern crate slog_term;
 extern crate structopt;
 extern crate tokio;
+extern crate tokio_signal;
 
 use crate::{
-    blockcfg::{HeaderHash, Leader},
+    blockcfg::HeaderHash,
     blockchain::Blockchain,
-    secure::enclave::Enclave,
-    settings::start::Settings,
+    settings::{
+        logging::{LogFormat, LogOutput, LogSettings},
+        start::Settings,
+    },
     utils::{async_msg, task::Services},
 };
 use futures::Future;
 use settings::{start::RawSettings, CommandLine};
-use slog::Logger;
+use slog::{FilterLevel, Logger};
+use std::path::Path;
 use std::thread;
 use std::time::Duration;
 use tokio::sync::lock::Lock;
@@ -78,7 +86,25 @@ pub mod utils;
 use stats_counter::StatsCounter;
 
 fn start() -> Result<(), start_up::Error> {
-    let initialized_node = initialize_node()?;
+    let command_line = CommandLine::load();
+
+    if let Some(socket) = command_line.start_arguments.enclave_listen.clone() {
+        return run_enclave_daemon(command_line, &socket);
+    }
+
+    let initialized_node = initialize_node(command_line)?;
+
+    if let Some(export) = initialized_node.settings.export_blocks.clone() {
+        return export_blocks(initialized_node, &export);
+    }
+
+    if let Some(check) = initialized_node.settings.storage_check {
+        return check_storage(initialized_node, check);
+    }
+
+    if let Some(target) = initialized_node.settings.storage_migration {
+        return migrate_storage(initialized_node, target);
+    }
 
     let bootstrapped_node = bootstrap(initialized_node)?;
 
@@ -97,6 +123,7 @@ pub struct BootstrappedNode {
 
 const FRAGMENT_TASK_QUEUE_LEN: usize = 1024;
 const NETWORK_TASK_QUEUE_LEN: usize = 32;
+const LEADERS_CHANGED_QUEUE_LEN: usize = 4;
 
 fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::Error> {
     let mut services = Services::new(bootstrapped_node.logger.clone());
@@ -115,31 +142,59 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
 
     let stats_counter = StatsCounter::default();
 
-    let (fragment_pool, pool_logs) = {
+    let pool_persistence =
+        start_up::prepare_pool_persistence(&bootstrapped_node.settings, &bootstrapped_node.logger);
+
+    let (fragment_pool, pool_logs, fragment_senders) = {
         let stats_counter = stats_counter.clone();
+        let mempool = &bootstrapped_node.settings.mempool;
+        let pool_limits = fragment::PoolLimits {
+            max_entries: mempool.pool_max_entries,
+            max_size: mempool.pool_max_size,
+            eviction_policy: mempool.eviction_policy,
+        };
+        let sender_limits = fragment::SenderLimits {
+            max_fragments: mempool.sender_quota,
+            interval: mempool.sender_quota_interval.into(),
+            max_rejections: mempool.sender_ban_threshold,
+            ban_duration: mempool.sender_ban_duration.into(),
+        };
         let process = fragment::Process::new(
-            bootstrapped_node.settings.mempool.fragment_ttl.into(),
-            bootstrapped_node.settings.mempool.log_ttl.into(),
-            bootstrapped_node
-                .settings
-                .mempool
-                .garbage_collection_interval
-                .into(),
+            mempool.fragment_ttl.into(),
+            pool_limits,
+            mempool.log_ttl.into(),
+            sender_limits,
+            pool_persistence.clone(),
+            mempool.garbage_collection_interval.into(),
         );
 
         let pool = process.pool().clone();
         let logs = process.logs().clone();
+        let senders = process.senders().clone();
 
+        let blockchain_tip = blockchain_tip.clone();
+        let network_msgbox = network_msgbox.clone();
+        let chain_events = blockchain.events().subscribe();
+        let storage = blockchain.storage().clone();
         services.spawn_future("fragment", move |info| {
-            process.start(info, stats_counter, fragment_queue)
+            process.start(
+                info,
+                stats_counter,
+                blockchain_tip,
+                network_msgbox,
+                chain_events,
+                storage,
+                fragment_queue,
+            )
         });
-        (pool, logs)
+        (pool, logs, senders)
     };
 
     let block_task = {
         let mut blockchain = blockchain.clone();
         let mut blockchain_tip = blockchain_tip.clone();
         let stats_counter = stats_counter.clone();
+        let mut fragment_pool = fragment_pool.clone();
         services.spawn_future_with_inputs("block", move |info, input| {
             blockchain::handle_input(
                 info,
@@ -148,11 +203,30 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
                 &stats_counter,
                 &mut new_epoch_announcements,
                 &mut network_msgbox,
+                &mut fragment_pool,
                 input,
             )
         })
     };
 
+    {
+        let blockchain = blockchain.clone();
+        let stats_counter = stats_counter.clone();
+        let garbage_collection_interval = bootstrapped_node
+            .settings
+            .blockchain
+            .garbage_collection_interval
+            .into();
+        services.spawn_future("blockchain-gc", move |info| {
+            blockchain::start_garbage_collector(
+                info,
+                blockchain,
+                garbage_collection_interval,
+                stats_counter,
+            )
+        });
+    }
+
     let client_task = {
         let storage = blockchain.storage().clone();
         let blockchain_tip = blockchain_tip.clone();
@@ -186,40 +260,71 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         });
     }
 
-    let leader_secrets: Result<Vec<Leader>, start_up::Error> = bootstrapped_node
-        .settings
-        .secrets
-        .iter()
-        .map(|secret_path| {
-            let secret = secure::NodeSecret::load_from_file(secret_path.as_path())?;
-            Ok(Leader {
-                bft_leader: secret.bft(),
-                genesis_leader: secret.genesis(),
-            })
-        })
-        .collect();
-    let leader_secrets = leader_secrets?;
-    let enclave = Enclave::from_vec(leader_secrets);
+    let (local_enclave, leadership_enclave) =
+        start_up::prepare_enclave(&bootstrapped_node.settings, &bootstrapped_node.logger)?;
+    let (leaders_changed_msgbox, leaders_changed_queue) =
+        async_msg::channel(LEADERS_CHANGED_QUEUE_LEN);
+
+    let enclave = match local_enclave {
+        Some((enclave, secret_files)) => {
+            if !secret_files.files().is_empty() {
+                let enclave = enclave.clone();
+                let check_interval = bootstrapped_node
+                    .settings
+                    .leadership
+                    .secret_files_check_interval
+                    .into();
+                services.spawn_future("secrets", move |info| {
+                    secret_files.watch(
+                        enclave,
+                        check_interval,
+                        leaders_changed_msgbox,
+                        info.into_logger(),
+                    )
+                });
+            }
+            Some(enclave)
+        }
+        None => None,
+    };
 
     {
         let leadership_logs = leadership_logs.clone();
         let fragment_pool = fragment_pool.clone();
+        let mempool = &bootstrapped_node.settings.mempool;
+        let fragment_selection = mempool.fragment_selection;
+        let block_limits = fragment::selection::BlockLimits {
+            max_fragments: mempool.max_fragments_per_block,
+            max_size: mempool.max_block_content_size,
+        };
         let block_task = block_task.clone();
         let blockchain_tip = blockchain_tip.clone();
-        let enclave = leadership::Enclave::new(enclave.clone());
+        let stats_counter = stats_counter.clone();
+        let chain_events = blockchain.events().subscribe();
+        let storage = blockchain.storage().clone();
 
         services.spawn_future("leadership", move |info| {
+            let logger = info.logger().clone();
             leadership::LeadershipModule::start(
                 info,
                 leadership_logs,
                 leadership_garbage_collection_interval,
-                enclave,
+                leadership_enclave,
                 fragment_pool,
+                fragment_selection,
+                block_limits,
                 blockchain_tip,
                 new_epoch_notifier,
                 block_task,
+                stats_counter,
+                chain_events,
+                storage,
+                leaders_changed_queue,
             )
-            .map_err(|e| unimplemented!("error in leadership {}", e))
+            .map_err(move |error| {
+                crit!(logger, "leadership stopped, the node no longer creates blocks";
+                    "reason" => error.to_string())
+            })
         });
     }
 
@@ -231,6 +336,9 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
                 blockchain_tip,
                 transaction_task: fragment_msgbox,
                 logs: pool_logs,
+                fragment_pool: fragment_pool.clone(),
+                fragment_senders,
+                logger: bootstrapped_node.logger.new(o!(log::KEY_TASK => "rest")),
                 leadership_logs,
                 server: Lock::new(None),
                 enclave,
@@ -240,15 +348,77 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         None => None,
     };
 
+    // the node stops on the REST shutdown request or on a termination
+    // signal, the fragment pool is saved in both cases
+    let signal_logger = bootstrapped_node.logger.clone();
     match rest_server {
-        Some(server) => server.wait_for_stop(),
-        None => thread::sleep(Duration::from_secs(u64::max_value())),
+        Some(server) => {
+            let signal_server = server.clone();
+            thread::spawn(move || {
+                if wait_for_termination_signal(&signal_logger) {
+                    signal_server.stop();
+                }
+            });
+            server.wait_for_stop();
+        }
+        None => {
+            if !wait_for_termination_signal(&signal_logger) {
+                thread::sleep(Duration::from_secs(u64::max_value()));
+            }
+        }
     }
     info!(bootstrapped_node.logger, "Shutting down node");
 
+    if let Some(persistence) = pool_persistence {
+        persistence
+            .save_pool(&fragment_pool, bootstrapped_node.logger.clone())
+            .wait()
+            .unwrap_or(());
+    }
+
     Ok(())
 }
 
+/// block until the node receives SIGINT or SIGTERM (Ctrl-C on the other
+/// platforms), returns `false` if the signals cannot be listened to.
+fn wait_for_termination_signal(logger: &Logger) -> bool {
+    match termination_signal().wait() {
+        Ok(()) => {
+            info!(logger, "termination signal received");
+            true
+        }
+        Err(error) => {
+            warn!(logger, "cannot listen to the termination signals"; "reason" => error.to_string());
+            false
+        }
+    }
+}
+
+#[cfg(unix)]
+fn termination_signal() -> impl Future<Item = (), Error = std::io::Error> {
+    use futures::Stream;
+    use tokio_signal::unix::{Signal, SIGINT, SIGTERM};
+
+    let sigint = Signal::new(SIGINT).flatten_stream();
+    let sigterm = Signal::new(SIGTERM).flatten_stream();
+    sigint
+        .select(sigterm)
+        .into_future()
+        .map(|_| ())
+        .map_err(|(error, _)| error)
+}
+
+#[cfg(not(unix))]
+fn termination_signal() -> impl Future<Item = (), Error = std::io::Error> {
+    use futures::Stream;
+
+    tokio_signal::ctrl_c()
+        .flatten_stream()
+        .into_future()
+        .map(|_| ())
+        .map_err(|(error, _)| error)
+}
+
 /// # Bootstrap phase
 ///
 /// done at every startup: we need to bootstrap from whatever local state (including nothing)
@@ -277,13 +447,26 @@ fn bootstrap(initialized_node: InitializedNode) -> Result<BootstrappedNode, star
     // TODO: we should get this value from the configuration
     let block_cache_ttl: Duration = Duration::from_secs(5 * 24 * 3600);
 
+    let snapshots = start_up::prepare_snapshots(&settings, &bootstrap_logger)?;
+
     let (blockchain, blockchain_tip) = start_up::load_blockchain(
         block0,
         storage,
+        snapshots,
         new_epoch_announcements.clone(),
         block_cache_ttl,
+        &bootstrap_logger,
     )?;
 
+    if let Some(path) = &settings.import_blocks {
+        start_up::import_blocks(
+            path,
+            blockchain.clone(),
+            blockchain_tip.clone(),
+            &bootstrap_logger,
+        )?;
+    }
+
     network::bootstrap(
         &settings.network,
         blockchain.clone(),
@@ -302,6 +485,92 @@ fn bootstrap(initialized_node: InitializedNode) -> Result<BootstrappedNode, star
     })
 }
 
+/// run the enclave daemon holding the leader keys instead of the node
+fn run_enclave_daemon(command_line: CommandLine, socket: &Path) -> Result<(), start_up::Error> {
+    let logger = LogSettings {
+        level: command_line.log_level.unwrap_or(FilterLevel::Info),
+        format: command_line.log_format.unwrap_or(LogFormat::Plain),
+        output: command_line.log_output.unwrap_or(LogOutput::Stderr),
+    }
+    .to_logger()?;
+    let enclave_logger = logger.new(o!(log::KEY_TASK => "enclave"));
+    info!(
+        enclave_logger,
+        "Starting the enclave of {} {}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    );
+
+    let arguments = command_line.start_arguments;
+    let key_file = arguments
+        .enclave_key
+        .expect("`--enclave-key' is required by `--enclave-listen'");
+    start_up::run_enclave_daemon(
+        socket,
+        &key_file,
+        &arguments.secret,
+        arguments.secret_passphrase(),
+        enclave_logger,
+    )
+}
+
+/// write the blocks of the storage in a block archive and stop there,
+/// the node is not started.
+fn export_blocks(
+    initialized_node: InitializedNode,
+    export: &settings::start::BlockExport,
+) -> Result<(), start_up::Error> {
+    let InitializedNode {
+        block0,
+        storage,
+        logger,
+        ..
+    } = initialized_node;
+    let export_logger = logger.new(o!(log::KEY_TASK => "export"));
+
+    start_up::export_blocks(export, storage, block0.header.hash(), &export_logger)
+}
+
+/// verify (and repair if requested) the storage and stop there,
+/// the node is not started.
+fn check_storage(
+    initialized_node: InitializedNode,
+    check: settings::start::StorageCheck,
+) -> Result<(), start_up::Error> {
+    let InitializedNode {
+        block0,
+        storage,
+        logger,
+        ..
+    } = initialized_node;
+    let check_logger = logger.new(o!(log::KEY_TASK => "storage-check"));
+
+    start_up::check_storage(check, storage, block0, &check_logger)
+}
+
+/// copy the blocks of the storage in another storage backend and stop
+/// there, the node is not started.
+fn migrate_storage(
+    initialized_node: InitializedNode,
+    target: settings::start::StorageBackend,
+) -> Result<(), start_up::Error> {
+    let InitializedNode {
+        settings,
+        block0,
+        storage,
+        logger,
+    } = initialized_node;
+    let migration_logger = logger.new(o!(log::KEY_TASK => "storage-migration"));
+
+    start_up::migrate_storage(
+        &settings,
+        storage,
+        block0.header.hash(),
+        target,
+        &migration_logger,
+    )
+}
+
 pub struct InitializedNode {
     pub settings: Settings,
     pub block0: blockcfg::Block,
@@ -309,8 +578,7 @@ pub struct InitializedNode {
     pub logger: Logger,
 }
 
-fn initialize_node() -> Result<InitializedNode, start_up::Error> {
-    let command_line = CommandLine::load();
+fn initialize_node(command_line: CommandLine) -> Result<InitializedNode, start_up::Error> {
     let raw_settings = RawSettings::load(command_line)?;
     let logger = raw_settings.to_logger()?;
 
@@ -6,11 +6,13 @@ use super::{
     subscription, Channels, ConnectionState, GlobalStateR,
 };
 use crate::{
-    blockcfg::{Block, Header, HeaderHash},
+    blockcfg::{Block, Fragment, Header, HeaderHash},
     intercom::{self, BlockMsg, ClientMsg},
 };
 use futures::prelude::*;
+use jormungandr_lib::interfaces::FragmentOrigin;
 use network_core::client::block::BlockService;
+use network_core::client::content::ContentService;
 use network_core::client::gossip::GossipService;
 use network_core::client::p2p::P2pService;
 use network_core::client::{self as core_client, Client as _};
@@ -48,8 +50,10 @@ where
     S: core_client::Client,
     S: P2pService<NodeId = topology::NodeId>,
     S: BlockService<Block = Block>,
+    S: ContentService<Fragment = Fragment>,
     S: GossipService<Node = topology::Node>,
     S::UploadBlocksFuture: Send + 'static,
+    S::ContentSubscription: Send + 'static,
     S::GossipSubscription: Send + 'static,
 {
     fn subscribe(
@@ -69,13 +73,21 @@ where
                     .map(move |service| (service, peer_comms, block_req))
             })
             .and_then(move |(mut service, mut peer_comms, block_req)| {
-                let gossip_req = service.gossip_subscription(peer_comms.subscribe_to_gossip());
-                block_req
-                    .join(gossip_req)
-                    .map(move |(block_res, gossip_res)| {
-                        (service, peer_comms, block_res, gossip_res)
-                    })
+                let content_req = service.content_subscription(peer_comms.subscribe_to_messages());
+                service
+                    .ready()
+                    .map(move |service| (service, peer_comms, block_req, content_req))
             })
+            .and_then(
+                move |(mut service, mut peer_comms, block_req, content_req)| {
+                    let gossip_req = service.gossip_subscription(peer_comms.subscribe_to_gossip());
+                    block_req.join3(content_req, gossip_req).map(
+                        move |(block_res, content_res, gossip_res)| {
+                            (service, peer_comms, block_res, content_res, gossip_res)
+                        },
+                    )
+                },
+            )
             .map_err(move |err| {
                 warn!(err_logger, "subscription request failed: {:?}", err);
             })
@@ -84,12 +96,16 @@ where
                     service,
                     mut peer_comms,
                     (block_events, node_id),
-                    (gossip_sub, node_id_1),
+                    (content_sub, node_id_1),
+                    (gossip_sub, node_id_2),
                 )| {
-                    if node_id != node_id_1 {
+                    if node_id != node_id_1 || node_id != node_id_2 {
                         warn!(
                             state.logger(),
-                            "peer subscription IDs do not match: {} != {}", node_id, node_id_1
+                            "peer subscription IDs do not match: {}, {}, {}",
+                            node_id,
+                            node_id_1,
+                            node_id_2
                         );
                         return Err(());
                     }
@@ -97,6 +113,17 @@ where
 
                     // Spin off processing tasks for subscriptions that can be
                     // managed with just the global state.
+                    let origin = FragmentOrigin::Network {
+                        node_id: node_id.to_string(),
+                        addr: Some(state.connection),
+                    };
+                    subscription::process_fragments(
+                        content_sub,
+                        origin,
+                        state.global.clone(),
+                        channels.transaction_box.clone(),
+                        logger.clone(),
+                    );
                     subscription::process_gossip(gossip_sub, state.global.clone(), logger.clone());
 
                     // Plug the block solicitations and header pulls to be handled
@@ -163,14 +190,11 @@ where
             }
             BlockEvent::Missing(req) => {
                 debug!(self.logger, "received block event Missing");
-                self.push_missing_blocks(req);
+                self.push_missing_headers(req);
             }
         }
     }
 
-    // FIXME: use this to handle BlockEvent::Missing events when two-stage
-    // chain pull processing is implemented in the blockchain task.
-    #[allow(dead_code)]
     fn push_missing_headers(&mut self, req: ChainPullRequest<HeaderHash>) {
         let (reply_handle, stream) =
             intercom::stream_reply::<Header, network_core::error::Error>(self.logger.clone());
@@ -193,29 +217,6 @@ where
                 }),
         );
     }
-
-    // Temporary support for pushing chain blocks without two-stage
-    // retrieval.
-    fn push_missing_blocks(&mut self, req: ChainPullRequest<HeaderHash>) {
-        let (reply_handle, stream) =
-            intercom::stream_reply::<Block, network_core::error::Error>(self.logger.clone());
-        self.channels
-            .client_box
-            .send_to(ClientMsg::PullBlocksToTip(req.from, reply_handle));
-        let node_id = self.remote_node_id;
-        let done_logger = self.logger.clone();
-        let err_logger = self.logger.clone();
-        tokio::spawn(
-            self.service
-                .upload_blocks(stream)
-                .map(move |_| {
-                    debug!(done_logger, "finished pushing blocks to {}", node_id);
-                })
-                .map_err(move |err| {
-                    warn!(err_logger, "UploadBlocks request failed: {:?}", err);
-                }),
-        );
-    }
 }
 
 impl<S> Client<S>
@@ -224,9 +225,6 @@ where
     S::PullHeadersFuture: Send + 'static,
     S::PullHeadersStream: Send + 'static,
 {
-    // FIXME: use this to handle chain pull requests when two-stage
-    // chain pull processing is implemented in the blockchain task.
-    #[allow(dead_code)]
     fn pull_headers(&mut self, req: ChainPullRequest<HeaderHash>) {
         let block_box = self.channels.block_box.clone();
         let logger = self.logger.clone();
@@ -260,49 +258,6 @@ where
     }
 }
 
-impl<S> Client<S>
-where
-    S: BlockService<Block = Block>,
-    S::PullBlocksToTipFuture: Send + 'static,
-    S::PullBlocksStream: Send + 'static,
-{
-    // Temporary support for pulling chain blocks without two-stage
-    // retrieval.
-    fn pull_blocks_to_tip(&mut self, req: ChainPullRequest<HeaderHash>) {
-        let block_box = self.channels.block_box.clone();
-        let logger = self.logger.clone();
-        let err_logger = logger.clone();
-        tokio::spawn(
-            self.service
-                .pull_blocks_to_tip(&req.from)
-                .map_err(move |e| {
-                    warn!(err_logger, "PullBlocksToTip request failed: {:?}", e);
-                })
-                .and_then(move |stream| {
-                    let err_logger = logger.clone();
-                    stream
-                        .map_err(move |e| {
-                            warn!(
-                                err_logger,
-                                "PullBlocksToTip response stream failed: {:?}", e
-                            );
-                        })
-                        .for_each(move |block| {
-                            let err_logger = logger.clone();
-                            InboundProcessing::with_unary(
-                                block_box.clone(),
-                                logger.clone(),
-                                |reply| BlockMsg::NetworkBlock(block, reply),
-                            )
-                            .map_err(move |e| {
-                                warn!(err_logger, "pulled block validation failed: {:?}", e)
-                            })
-                        })
-                }),
-        );
-    }
-}
-
 impl<S> Client<S>
 where
     S: BlockService<Block = Block>,
@@ -350,8 +305,6 @@ where
     S: BlockService<Block = Block>,
     S::GetBlocksFuture: Send + 'static,
     S::GetBlocksStream: Send + 'static,
-    S::PullBlocksToTipFuture: Send + 'static,
-    S::PullBlocksStream: Send + 'static,
     S::PullHeadersFuture: Send + 'static,
     S::PullHeadersStream: Send + 'static,
     S::PushHeadersFuture: Send + 'static,
@@ -403,9 +356,7 @@ where
                 }
                 Async::Ready(Some(req)) => {
                     streams_ready = true;
-                    // FIXME: implement two-stage chain pull processing
-                    // in the blockchain task and use pull_headers here.
-                    self.pull_blocks_to_tip(req);
+                    self.pull_headers(req);
                 }
             }
             if !streams_ready {
@@ -10,6 +10,7 @@ mod client;
 mod grpc;
 mod inbound;
 pub mod p2p;
+mod seen_fragments;
 mod service;
 mod subscription;
 
@@ -24,10 +25,15 @@ mod chain_pull {
     pub const CHUNK_SIZE: usize = 32;
 }
 
+// Number of propagated fragments remembered to drop the copies
+// received from the other peers.
+const SEEN_FRAGMENTS_CAPACITY: usize = 16 * 1024;
+
 use self::p2p::{
     comm::{PeerComms, Peers},
     topology::{self, P2pTopology},
 };
+use self::seen_fragments::SeenFragments;
 use crate::blockcfg::{Block, HeaderHash};
 use crate::blockchain::{Blockchain as NewBlockchain, Branch};
 use crate::intercom::{BlockMsg, ClientMsg, NetworkMsg, PropagateMsg, TransactionMsg};
@@ -76,6 +82,7 @@ pub struct GlobalState {
     pub topology: P2pTopology,
     pub node: topology::Node,
     pub peers: Peers,
+    pub seen_fragments: SeenFragments,
     pub logger: Logger,
 }
 
@@ -112,6 +119,7 @@ impl GlobalState {
             topology,
             node,
             peers: Peers::new(logger.clone()),
+            seen_fragments: SeenFragments::new(SEEN_FRAGMENTS_CAPACITY),
             logger,
         }
     }
@@ -260,6 +268,14 @@ fn handle_network_input(
 
 fn handle_propagation_msg(msg: PropagateMsg, state: GlobalStateR, channels: Channels) {
     trace!(state.logger(), "to propagate: {:?}", &msg);
+    if let PropagateMsg::Message(ref message) = msg {
+        use chain_core::property::Fragment as _;
+
+        if !state.seen_fragments.insert(message.id()) {
+            debug!(state.logger(), "fragment already propagated"; "id" => %message.id());
+            return;
+        }
+    }
     let nodes = state.topology.view().collect::<Vec<_>>();
     debug!(
         state.logger(),
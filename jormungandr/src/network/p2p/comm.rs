@@ -380,10 +380,19 @@ impl Peers {
         }
     }
 
-    pub fn pull_headers(&self, node_id: topology::NodeId, from: Vec<HeaderHash>, to: HeaderHash) {
+    pub fn pull_headers(
+        &self,
+        node_id: Option<topology::NodeId>,
+        from: Vec<HeaderHash>,
+        to: HeaderHash,
+    ) {
         let mut map = self.mutex.lock().unwrap();
-        match map.peer_comms(node_id) {
-            Some(comms) => {
+        let peer = match node_id {
+            Some(node_id) => map.peer_comms(node_id).map(|comms| (node_id, comms)),
+            None => map.next_peer_for_block_fetch(),
+        };
+        match peer {
+            Some((node_id, comms)) => {
                 debug!(self.logger, "pulling headers from {}", node_id;
                        "from" => ?from, "to" => ?to);
                 comms
@@ -398,10 +407,8 @@ impl Peers {
             }
             None => {
                 // TODO: connect and request on demand, or select another peer?
-                warn!(
-                    self.logger,
-                    "peer {} not available to pull headers from", node_id
-                );
+                warn!(self.logger, "no peer available to pull headers from";
+                      "node_id" => ?node_id);
             }
         }
     }
@@ -0,0 +1,107 @@
+//! cache of the fragments already propagated to the network
+//!
+//! The same fragment reaches a node from several of its peers: once a
+//! fragment has been propagated, the copies received afterwards are
+//! dropped instead of being processed and propagated again.
+
+use crate::blockcfg::FragmentId;
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::Mutex,
+};
+
+pub struct SeenFragments {
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    ids: HashSet<FragmentId>,
+    /// the ids in the order they were inserted, the oldest id is
+    /// forgotten once the capacity is reached
+    by_time: VecDeque<FragmentId>,
+    capacity: usize,
+}
+
+impl SeenFragments {
+    pub fn new(capacity: usize) -> Self {
+        SeenFragments {
+            inner: Mutex::new(Inner {
+                ids: HashSet::with_capacity(capacity),
+                by_time: VecDeque::with_capacity(capacity),
+                capacity,
+            }),
+        }
+    }
+
+    pub fn contains(&self, id: &FragmentId) -> bool {
+        self.inner.lock().unwrap().ids.contains(id)
+    }
+
+    /// mark the fragment as seen, returns `false` if the fragment
+    /// had already been seen.
+    pub fn insert(&self, id: FragmentId) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.ids.insert(id) {
+            return false;
+        }
+        inner.by_time.push_back(id);
+        if inner.by_time.len() > inner.capacity {
+            if let Some(oldest) = inner.by_time.pop_front() {
+                inner.ids.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chain_impl_mockchain::key::Hash;
+
+    fn id(n: u8) -> FragmentId {
+        Hash::hash_bytes(&[n])
+    }
+
+    #[test]
+    fn insert_once() {
+        let seen = SeenFragments::new(4);
+        assert!(seen.insert(id(0)));
+        assert!(!seen.insert(id(0)));
+        assert!(seen.contains(&id(0)));
+        assert!(!seen.contains(&id(1)));
+    }
+
+    #[test]
+    fn forget_the_oldest_beyond_the_capacity() {
+        let seen = SeenFragments::new(3);
+        for n in 0..3 {
+            assert!(seen.insert(id(n)));
+        }
+        assert!(seen.insert(id(3)));
+
+        assert!(!seen.contains(&id(0)));
+        for n in 1..4 {
+            assert!(seen.contains(&id(n)));
+        }
+        assert_eq!(seen.inner.lock().unwrap().ids.len(), 3);
+        assert_eq!(seen.inner.lock().unwrap().by_time.len(), 3);
+
+        // the forgotten fragment is seen again as new
+        assert!(seen.insert(id(0)));
+        assert!(!seen.contains(&id(1)));
+    }
+
+    #[test]
+    fn seen_again_does_not_refresh() {
+        let seen = SeenFragments::new(2);
+        assert!(seen.insert(id(0)));
+        assert!(seen.insert(id(1)));
+        assert!(!seen.insert(id(0)));
+        assert!(seen.insert(id(2)));
+
+        assert!(!seen.contains(&id(0)));
+        assert!(seen.contains(&id(1)));
+        assert!(seen.contains(&id(2)));
+    }
+}
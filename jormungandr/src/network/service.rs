@@ -6,9 +6,10 @@ use super::{
     subscription, Channels, GlobalStateR,
 };
 use crate::blockcfg::{Block, BlockDate, Fragment, FragmentId, Header, HeaderHash};
-use crate::intercom::{self, BlockMsg, ClientMsg, ReplyFuture, ReplyStream};
+use crate::intercom::{self, BlockMsg, ClientMsg, ReplyFuture, ReplyStream, TransactionMsg};
 use futures::future::{self, FutureResult};
 use futures::prelude::*;
+use jormungandr_lib::interfaces::FragmentOrigin;
 use network_core::{
     error as core_error,
     gossip::{Gossip, Node as _},
@@ -49,8 +50,7 @@ impl Node for NodeService {
     }
 
     fn content_service(&mut self) -> Option<&mut Self::ContentService> {
-        // Not implemented yet
-        None
+        Some(self)
     }
 
     fn gossip_service(&mut self) -> Option<&mut Self::GossipService> {
@@ -216,23 +216,56 @@ impl ContentService for NodeService {
     type Fragment = Fragment;
     type FragmentId = FragmentId;
     type GetFragmentsStream = ReplyStream<Self::Fragment, core_error::Error>;
-    type GetFragmentsFuture = ReplyFuture<Self::GetFragmentsStream, core_error::Error>;
+    type GetFragmentsFuture = FutureResult<Self::GetFragmentsStream, core_error::Error>;
     type ContentSubscription = Subscription<Fragment>;
     type ContentSubscriptionFuture = FutureResult<Self::ContentSubscription, core_error::Error>;
 
-    fn get_fragments(&mut self, _ids: &[Self::FragmentId]) -> Self::GetFragmentsFuture {
-        unimplemented!()
+    fn get_fragments(&mut self, ids: &[Self::FragmentId]) -> Self::GetFragmentsFuture {
+        let (handle, stream) = intercom::stream_reply(self.logger().clone());
+        match self
+            .channels
+            .transaction_box
+            .try_send(TransactionMsg::GetTransactions(ids.into(), handle))
+        {
+            Ok(()) => future::ok(stream),
+            Err(e) => {
+                warn!(self.logger(), "cannot request the fragments: {}", e);
+                future::err(core_error::Error::new(
+                    core_error::Code::Unavailable,
+                    "fragment task unavailable",
+                ))
+            }
+        }
     }
 
-    fn content_subscription<S>(
+    fn content_subscription<In>(
         &mut self,
         subscriber: Self::NodeId,
-        _inbound: S,
+        inbound: In,
     ) -> Self::ContentSubscriptionFuture
     where
-        S: Stream<Item = Self::Fragment, Error = core_error::Error>,
+        In: Stream<Item = Self::Fragment, Error = core_error::Error> + Send + 'static,
     {
-        unimplemented!()
+        let addr = self
+            .global_state
+            .topology
+            .view()
+            .find(|node| node.id() == subscriber)
+            .and_then(|node| node.address());
+        let origin = FragmentOrigin::Network {
+            node_id: subscriber.to_string(),
+            addr,
+        };
+        subscription::process_fragments(
+            inbound,
+            origin,
+            self.global_state.clone(),
+            self.channels.transaction_box.clone(),
+            self.logger().clone(),
+        );
+
+        let subscription = self.global_state.peers.subscribe_to_messages(subscriber);
+        future::ok(subscription)
     }
 }
 
@@ -2,8 +2,13 @@ use super::{
     p2p::topology::{Node, NodeId},
     GlobalState, GlobalStateR,
 };
-use crate::{blockcfg::Header, intercom::BlockMsg, utils::async_msg::MessageBox};
+use crate::{
+    blockcfg::{Fragment, Header},
+    intercom::{BlockMsg, TransactionMsg},
+    utils::async_msg::MessageBox,
+};
 use futures::prelude::*;
+use jormungandr_lib::interfaces::FragmentOrigin;
 use network_core::{error as core_error, gossip::Gossip};
 use slog::Logger;
 
@@ -41,6 +46,61 @@ pub fn process_block_announcement(
         .unwrap();
 }
 
+pub fn process_fragments<S>(
+    inbound: S,
+    origin: FragmentOrigin,
+    state: GlobalStateR,
+    mut transaction_box: MessageBox<TransactionMsg>,
+    logger: Logger,
+) -> tokio::executor::Spawn
+where
+    S: Stream<Item = Fragment, Error = core_error::Error> + Send + 'static,
+{
+    let err_logger = logger.clone();
+    tokio::spawn(
+        inbound
+            .for_each(move |fragment| {
+                process_fragment(
+                    fragment,
+                    origin.clone(),
+                    &state,
+                    &mut transaction_box,
+                    &logger,
+                );
+                Ok(())
+            })
+            .map_err(move |err| {
+                info!(
+                    err_logger,
+                    "fragment subscription stream failure: {:?}", err
+                );
+            }),
+    )
+}
+
+fn process_fragment(
+    fragment: Fragment,
+    origin: FragmentOrigin,
+    global_state: &GlobalState,
+    transaction_box: &mut MessageBox<TransactionMsg>,
+    logger: &Logger,
+) {
+    use chain_core::property::Fragment as _;
+
+    // the fragment has already been received and propagated
+    if global_state.seen_fragments.contains(&fragment.id()) {
+        trace!(logger, "dropping fragment already propagated"; "id" => %fragment.id());
+        return;
+    }
+    transaction_box
+        .try_send(TransactionMsg::SendTransaction(
+            origin,
+            vec![fragment],
+            None,
+        ))
+        .unwrap_or_else(|err| warn!(logger, "cannot process fragment from the network: {}", err));
+}
+
 pub fn process_gossip<S>(inbound: S, state: GlobalStateR, logger: Logger) -> tokio::executor::Spawn
 where
     S: Stream<Item = Gossip<Node>, Error = core_error::Error> + Send + 'static,
@@ -10,11 +10,12 @@ use actix_web::dev::Resource;
 use actix_web::middleware::cors::Cors;
 use actix_web::App;
 use futures::{future, Future};
+use slog::Logger;
 use std::convert::Infallible;
 use tokio::sync::lock::Lock;
 
 use crate::blockchain::{Blockchain, Branch};
-use crate::fragment::Logs;
+use crate::fragment::{Logs, Pool, Senders};
 use crate::leadership::Logs as LeadershipLogs;
 use crate::secure::enclave::Enclave;
 use crate::settings::start::{Cors as CorsConfig, Error as ConfigError, Rest};
@@ -30,9 +31,14 @@ pub struct Context {
     pub blockchain_tip: Branch,
     pub transaction_task: MessageBox<TransactionMsg>,
     pub logs: Logs,
+    pub fragment_pool: Pool,
+    pub fragment_senders: Senders,
+    pub logger: Logger,
     pub leadership_logs: LeadershipLogs,
     pub server: Lock<Option<Server>>,
-    pub enclave: Enclave,
+    /// the enclave holding the leader keys in the node, `None` if they are
+    /// held by an external enclave
+    pub enclave: Option<Enclave>,
 }
 
 pub fn start_rest_server(config: &Rest, mut context: Context) -> Result<Server, ConfigError> {
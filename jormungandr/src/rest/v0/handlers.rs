@@ -1,8 +1,10 @@
 use jormungandr_lib::interfaces::*;
 use jormungandr_lib::time::SystemTime;
 
-use actix_web::error::{ErrorBadRequest, ErrorInternalServerError, ErrorNotFound};
-use actix_web::{Error, HttpResponse};
+use actix_web::error::{
+    ErrorBadRequest, ErrorForbidden, ErrorInternalServerError, ErrorNotFound, ErrorTooManyRequests,
+};
+use actix_web::{Error, HttpRequest, HttpResponse};
 use actix_web::{Json, Path, Query, Responder, State};
 use chain_core::property::{Block, Deserialize, Serialize as _};
 use chain_crypto::{Blake2b256, PublicKey};
@@ -12,11 +14,15 @@ use chain_impl_mockchain::key::Hash;
 use chain_impl_mockchain::leadership::{Leader, LeadershipConsensus};
 use chain_impl_mockchain::value::{Value, ValueError};
 
-use crate::blockchain::Ref;
-use crate::intercom::TransactionMsg;
-use crate::secure::NodeSecret;
+use crate::blockchain::{Blockchain, ErrorKind as BlockchainErrorKind, Ref};
+use crate::fragment::FragmentId;
+use crate::intercom::{self, TransactionMsg};
+use crate::secure::{enclave::Enclave, NodeSecret};
 use bytes::{Bytes, IntoBuf};
-use futures::{future, Future, IntoFuture, Stream};
+use futures::{
+    future::{self, Either},
+    Future, IntoFuture, Stream,
+};
 use std::convert::Infallible;
 use std::str::FromStr;
 
@@ -33,22 +39,83 @@ fn chain_tip_fut<'a>(context: &State<Context>) -> impl Future<Item = Ref, Error
         .map_err(|infallible| match infallible {})
 }
 
-pub fn get_utxos(context: State<Context>) -> ActixFuture!() {
-    chain_tip_fut(&context).map(|tip_reference| {
-        let utxos = tip_reference.ledger().utxos();
+/// the block whose state is queried: either a block hash or a chain length
+/// on the main branch. The tip of the blockchain is queried by default.
+#[derive(Deserialize)]
+pub struct StateQueryParams {
+    block: Option<String>,
+    chain_length: Option<u32>,
+}
+
+fn state_ref_fut(
+    context: &State<Context>,
+    query_params: &StateQueryParams,
+) -> impl Future<Item = Ref, Error = Error> {
+    let blockchain = context.blockchain.clone();
+    let storage = context.blockchain.storage().clone();
+    let tip_fut = chain_tip_fut(context);
+
+    match (query_params.block.as_ref(), query_params.chain_length) {
+        (None, None) => Either::A(tip_fut),
+        (Some(_), Some(_)) => Either::B(Either::A(future::err(ErrorBadRequest(
+            "only one of `block` and `chain_length` can be set",
+        )))),
+        (Some(block_id_hex), None) => Either::B(Either::B(Either::A(
+            parse_block_hash(block_id_hex)
+                .into_future()
+                .and_then(move |block_id| block_ref_fut(blockchain, block_id)),
+        ))),
+        (None, Some(chain_length)) => Either::B(Either::B(Either::B(
+            tip_fut
+                .and_then(move |tip| {
+                    storage
+                        .get_ancestor_at_chain_length(tip.hash(), chain_length)
+                        .map_err(|e| ErrorInternalServerError(e))
+                })
+                .and_then(move |block_id| match block_id {
+                    Some(block_id) => Either::A(block_ref_fut(blockchain, block_id)),
+                    None => Either::B(future::err(ErrorNotFound(
+                        "the chain has not reached this chain length yet",
+                    ))),
+                }),
+        ))),
+    }
+}
+
+fn block_ref_fut(
+    mut blockchain: Blockchain,
+    block_id: Hash,
+) -> impl Future<Item = Ref, Error = Error> {
+    blockchain
+        .get_ref(block_id)
+        .map_err(|e| match e.kind() {
+            BlockchainErrorKind::StateTooFar(_) => ErrorNotFound(e.to_string()),
+            _ => ErrorInternalServerError(e.to_string()),
+        })
+        .and_then(|block_ref| block_ref.ok_or_else(|| ErrorNotFound("Block not found")))
+}
+
+pub fn get_utxos(context: State<Context>, query_params: Query<StateQueryParams>) -> ActixFuture!() {
+    state_ref_fut(&context, &query_params).map(|state_reference| {
+        let utxos = state_reference.ledger().utxos();
         let utxos = utxos.map(UTxOInfo::from).collect::<Vec<_>>();
         Json(utxos)
     })
 }
 
-pub fn get_account_state(context: State<Context>, account_id_hex: Path<String>) -> ActixFuture!() {
+pub fn get_account_state(
+    context: State<Context>,
+    account_id_hex: Path<String>,
+    query_params: Query<StateQueryParams>,
+) -> ActixFuture!() {
     parse_account_id(&account_id_hex)
         .into_future()
         .and_then(move |account_id| {
-            chain_tip_fut(&context).map(|tip_reference| (tip_reference, account_id))
+            state_ref_fut(&context, &query_params)
+                .map(|state_reference| (state_reference, account_id))
         })
-        .and_then(|(tip_reference, account_id)| {
-            let state = tip_reference
+        .and_then(|(state_reference, account_id)| {
+            let state = state_reference
                 .ledger()
                 .accounts()
                 .get_state(&account_id)
@@ -72,15 +139,173 @@ pub fn get_message_logs(context: State<Context>) -> ActixFuture!() {
         .map(Json)
 }
 
-pub fn post_message(context: State<Context>, message: Bytes) -> Result<HttpResponse, Error> {
-    let fragment = Fragment::deserialize(message.into_buf()).map_err(ErrorBadRequest)?;
-    let msg = TransactionMsg::SendTransaction(FragmentOrigin::Rest, vec![fragment]);
+/// the range of the pending fragments to return: `limit` fragments at
+/// most, from the fragment at `offset` in the order they were received
+#[derive(Deserialize)]
+pub struct PendingFragmentsQueryParams {
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+}
+
+pub fn get_pending_fragments(
+    context: State<Context>,
+    query_params: Query<PendingFragmentsQueryParams>,
+) -> ActixFuture!() {
     context
-        .transaction_task
-        .clone()
-        .try_send(msg)
-        .map_err(|e| ErrorInternalServerError(e))?;
-    Ok(HttpResponse::Ok().finish())
+        .fragment_pool
+        .pending(query_params.offset, query_params.limit)
+        .map_err(|_| ErrorInternalServerError("Failed to get pending fragments"))
+        .map(Json)
+}
+
+#[derive(Deserialize)]
+pub struct PendingFragmentQueryParams {
+    /// return the decoded fragment in JSON instead of the raw fragment
+    #[serde(default)]
+    decoded: bool,
+}
+
+pub fn get_pending_fragment(
+    context: State<Context>,
+    fragment_id_hex: Path<String>,
+    query_params: Query<PendingFragmentQueryParams>,
+) -> ActixFuture!() {
+    let decoded = query_params.decoded;
+    parse_fragment_id(&fragment_id_hex)
+        .into_future()
+        .and_then(move |fragment_id| {
+            context
+                .fragment_pool
+                .get_pending(fragment_id)
+                .map_err(|_| ErrorInternalServerError("Failed to get pending fragment"))
+        })
+        .and_then(|pending| pending.ok_or_else(|| ErrorNotFound("Fragment not found in the pool")))
+        .and_then(move |(pending, fragment)| {
+            if decoded {
+                Ok(HttpResponse::Ok().json(DecodedPendingFragment::new(pending, &fragment)))
+            } else {
+                fragment
+                    .serialize_as_vec()
+                    .map_err(|e| ErrorInternalServerError(e))
+                    .map(|bytes| {
+                        HttpResponse::Ok()
+                            .content_type("application/octet-stream")
+                            .body(bytes)
+                    })
+            }
+        })
+}
+
+pub fn get_fragment_status(
+    context: State<Context>,
+    fragment_id_hex: Path<String>,
+) -> ActixFuture!() {
+    parse_fragment_id(&fragment_id_hex)
+        .into_future()
+        .and_then(move |fragment_id| {
+            context
+                .logs
+                .get(fragment_id)
+                .map_err(|_| ErrorInternalServerError("Failed to get fragment log"))
+        })
+        .and_then(|log| log.ok_or_else(|| ErrorNotFound("Fragment not found")))
+        .map(Json)
+}
+
+fn parse_fragment_id(hex: &str) -> Result<FragmentId, Error> {
+    Blake2b256::from_str(hex)
+        .map_err(|e| ErrorBadRequest(e))
+        .map(Into::into)
+}
+
+pub fn get_fragment_bans(context: State<Context>) -> impl Responder {
+    Json(context.fragment_senders.bans())
+}
+
+pub fn post_message(
+    context: State<Context>,
+    request: HttpRequest<Context>,
+    message: Bytes,
+) -> ActixFuture!() {
+    let (reply_handle, reply_future) = intercom::unary_reply(context.logger.clone());
+    let origin = FragmentOrigin::Rest {
+        addr: request.peer_addr(),
+    };
+    let senders = context.fragment_senders.clone();
+    let refused_origin = origin.clone();
+    Fragment::deserialize(message.into_buf())
+        .map_err(ErrorBadRequest)
+        .and_then(|fragment| {
+            let msg = TransactionMsg::SendTransaction(origin, vec![fragment], Some(reply_handle));
+            context
+                .transaction_task
+                .clone()
+                .try_send(msg)
+                .map_err(|e| ErrorInternalServerError(e))
+        })
+        .into_future()
+        .and_then(move |()| {
+            reply_future.map_err(|e: intercom::Error| ErrorInternalServerError(e.to_string()))
+        })
+        .and_then(move |submissions| match submissions.into_iter().next() {
+            None => Err(ErrorInternalServerError(
+                "the fragment has not been submitted",
+            )),
+            Some(submission) => match submission.status() {
+                FragmentSubmissionStatus::Accepted => Ok(HttpResponse::Ok().finish()),
+                FragmentSubmissionStatus::Rejected { reason } => {
+                    Err(ErrorBadRequest(reason.clone()))
+                }
+                FragmentSubmissionStatus::Refused { reason } => {
+                    if senders.is_banned(&refused_origin) {
+                        Err(ErrorForbidden(reason.clone()))
+                    } else {
+                        Err(ErrorTooManyRequests(reason.clone()))
+                    }
+                }
+            },
+        })
+}
+
+/// submit the fragments of the request body to the pool, the response
+/// gives the outcome of the submission of each fragment, in the order of
+/// the fragments in the body.
+pub fn post_fragments(
+    context: State<Context>,
+    request: HttpRequest<Context>,
+    body: Bytes,
+) -> ActixFuture!() {
+    let (reply_handle, reply_future) = intercom::unary_reply(context.logger.clone());
+    let origin = FragmentOrigin::Rest {
+        addr: request.peer_addr(),
+    };
+    deserialize_fragments(body)
+        .and_then(|fragments| {
+            let msg = TransactionMsg::SendTransaction(origin, fragments, Some(reply_handle));
+            context
+                .transaction_task
+                .clone()
+                .try_send(msg)
+                .map_err(|e| ErrorInternalServerError(e))
+        })
+        .into_future()
+        .and_then(move |()| {
+            reply_future.map_err(|e: intercom::Error| ErrorInternalServerError(e.to_string()))
+        })
+        .map(Json)
+}
+
+/// the fragments are serialized one after the other, like in the
+/// content of a block
+fn deserialize_fragments(body: Bytes) -> Result<Vec<Fragment>, Error> {
+    let len = body.len() as u64;
+    let mut reader = body.into_buf();
+    let mut fragments = Vec::new();
+    while reader.position() < len {
+        fragments.push(Fragment::deserialize(&mut reader).map_err(ErrorBadRequest)?);
+    }
+    Ok(fragments)
 }
 
 pub fn get_tip(context: State<Context>) -> ActixFuture!() {
@@ -128,6 +353,10 @@ pub fn get_stats_counter(context: State<Context>) -> ActixFuture!() {
             Ok(Json(json!({
                 "txRecvCnt": stats.tx_recv_cnt(),
                 "blockRecvCnt": stats.block_recv_cnt(),
+                "collectedStatesCnt": stats.gc_collected_cnt(),
+                "leaderBlockCnt": stats.leader_block_cnt(),
+                "leaderSkippedCnt": stats.leader_skipped_cnt(),
+                "leaderFailedCnt": stats.leader_failed_cnt(),
                 "uptime": stats.uptime_sec(),
                 "lastBlockTime": stats.slot_start_time().map(SystemTime::from),
                 "lastBlockTx": block_tx_count,
@@ -197,10 +426,13 @@ impl QueryParams {
     }
 }
 
-pub fn get_stake_distribution(context: State<Context>) -> ActixFuture!() {
-    chain_tip_fut(&context).map(|blockchain_tip| {
-        let leadership = blockchain_tip.epoch_leadership_schedule();
-        let last_epoch = blockchain_tip.block_date().epoch;
+pub fn get_stake_distribution(
+    context: State<Context>,
+    query_params: Query<StateQueryParams>,
+) -> ActixFuture!() {
+    state_ref_fut(&context, &query_params).map(|state_reference| {
+        let leadership = state_reference.epoch_leadership_schedule();
+        let last_epoch = state_reference.block_date().epoch;
         if let LeadershipConsensus::GenesisPraos(gp) = leadership.consensus() {
             let stake = gp.distribution();
             let pools: Vec<_> = stake
@@ -257,26 +489,38 @@ pub fn get_shutdown(context: State<Context>) -> ActixFuture!() {
     })
 }
 
-pub fn get_leaders(context: State<Context>) -> impl Responder {
-    Json(json! {
-        context.enclave.get_leaderids()
-    })
+fn node_enclave(context: &Context) -> Result<&Enclave, Error> {
+    context
+        .enclave
+        .as_ref()
+        .ok_or_else(|| ErrorBadRequest("The leaders are held by an external enclave"))
+}
+
+pub fn get_leaders(context: State<Context>) -> Result<impl Responder, Error> {
+    let enclave = node_enclave(&context)?;
+    Ok(Json(json! {
+        enclave.get_leaderids()
+    }))
 }
 
-pub fn post_leaders(secret: Json<NodeSecret>, context: State<Context>) -> impl Responder {
+pub fn post_leaders(
+    secret: Json<NodeSecret>,
+    context: State<Context>,
+) -> Result<impl Responder, Error> {
+    let enclave = node_enclave(&context)?;
     let leader = Leader {
         bft_leader: secret.bft(),
         genesis_leader: secret.genesis(),
     };
-    let leader_id = context.enclave.add_leader(leader);
-    Json(leader_id)
+    let leader_id = enclave.add_leader(leader);
+    Ok(Json(leader_id))
 }
 
 pub fn delete_leaders(
     context: State<Context>,
     leader_id: Path<EnclaveLeaderId>,
 ) -> Result<impl Responder, Error> {
-    match context.enclave.remove_leader(*leader_id) {
+    match node_enclave(&context)?.remove_leader(*leader_id) {
         true => Ok(HttpResponse::Ok().finish()),
         false => Err(ErrorNotFound("Leader with given ID not found")),
     }
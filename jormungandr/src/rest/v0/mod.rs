@@ -2,6 +2,9 @@ mod handlers;
 
 use actix_web::dev::Resource;
 
+/// maximum size of the body of the batch fragment submission
+const MAX_FRAGMENTS_PAYLOAD_SIZE: usize = 16 * 1024 * 1024;
+
 pub fn resources() -> Vec<(
     &'static str,
     &'static dyn Fn(&mut Resource<handlers::Context>),
@@ -16,9 +19,28 @@ pub fn resources() -> Vec<(
         ("/block/{block_id}/next_id", &|r| {
             r.get().with_async(handlers::get_block_next_id)
         }),
+        ("/fragment/bans", &|r| {
+            r.get().with(handlers::get_fragment_bans)
+        }),
         ("/fragment/logs", &|r| {
             r.get().with_async(handlers::get_message_logs)
         }),
+        ("/fragment/pending", &|r| {
+            r.get().with_async(handlers::get_pending_fragments)
+        }),
+        ("/fragment/pending/{fragment_id}", &|r| {
+            r.get().with_async(handlers::get_pending_fragment)
+        }),
+        ("/fragment/status/{fragment_id}", &|r| {
+            r.get().with_async(handlers::get_fragment_status)
+        }),
+        ("/fragments", &|r| {
+            // a batch of fragments exceeds the default payload limit
+            r.post()
+                .with_async_config(handlers::post_fragments, |(_, _, payload)| {
+                    payload.limit(MAX_FRAGMENTS_PAYLOAD_SIZE);
+                });
+        }),
         ("/leaders", &|r| {
             r.get().with(handlers::get_leaders);
             r.post().with(handlers::post_leaders);
@@ -37,7 +59,7 @@ pub fn resources() -> Vec<(
             r.get().with_async(handlers::get_stake_pools)
         }),
         ("/shutdown", &|r| r.get().with_async(handlers::get_shutdown)),
-        ("/message", &|r| r.post().with(handlers::post_message)),
+        ("/message", &|r| r.post().with_async(handlers::post_message)),
         ("/node/stats", &|r| {
             r.get().with_async(handlers::get_stats_counter)
         }),
@@ -0,0 +1,216 @@
+//! the enclave daemon
+//!
+//! It holds the leader keys and answers the requests of the node on a
+//! Unix-domain socket, see the [`protocol`] module. The leaderships sent
+//! by the node with the schedule requests are kept to evaluate again the
+//! election of the leaders when signing their blocks. The date of the
+//! last block signed by each leader is kept as well: a leader never signs
+//! two blocks at the same date, nor a block older than its last one.
+//!
+//! [`protocol`]: ../protocol/index.html
+
+use crate::{
+    blockcfg::{BlockDate, Epoch, Leadership, Ledger},
+    secure::{
+        enclave::{BlockTemplate, Enclave, LeaderEvent},
+        protocol::{self, EnclaveKey, Request, Response},
+    },
+};
+use jormungandr_lib::interfaces::EnclaveLeaderId as LeaderId;
+use slog::Logger;
+use std::{
+    collections::BTreeMap,
+    io,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// number of epochs the leaderships are kept for
+const LEADERSHIPS_KEPT: usize = 2;
+
+#[derive(Clone)]
+pub struct EnclaveDaemon {
+    enclave: Enclave,
+    key: EnclaveKey,
+    leaderships: Arc<Mutex<BTreeMap<Epoch, Arc<Leadership>>>>,
+    last_signed: Arc<Mutex<BTreeMap<LeaderId, BlockDate>>>,
+    logger: Logger,
+}
+
+impl EnclaveDaemon {
+    pub fn new(enclave: Enclave, key: EnclaveKey, logger: Logger) -> Self {
+        EnclaveDaemon {
+            enclave,
+            key,
+            leaderships: Arc::new(Mutex::new(BTreeMap::new())),
+            last_signed: Arc::new(Mutex::new(BTreeMap::new())),
+            logger,
+        }
+    }
+
+    /// listen on the Unix-domain socket at the given path and answer the
+    /// requests until the process is stopped
+    #[cfg(unix)]
+    pub fn run(self, socket: &Path) -> Result<(), io::Error> {
+        use std::{
+            fs,
+            os::unix::fs::{FileTypeExt as _, PermissionsExt as _},
+        };
+        use tokio::{net::UnixListener, prelude::*};
+
+        // a socket left behind by a previous run prevents from binding
+        if let Ok(metadata) = fs::symlink_metadata(socket) {
+            if metadata.file_type().is_socket() {
+                fs::remove_file(socket)?;
+            }
+        }
+        let listener = UnixListener::bind(socket)?;
+        fs::set_permissions(socket, fs::Permissions::from_mode(0o600))?;
+
+        info!(self.logger, "enclave listening";
+            "socket" => socket.display().to_string(),
+            "leaders" => self.enclave.get_leaderids().len());
+
+        let error_logger = self.logger.clone();
+        let server = listener
+            .incoming()
+            .map_err(move |error| {
+                crit!(error_logger, "cannot accept the connections"; "reason" => error.to_string())
+            })
+            .for_each(move |stream| {
+                let daemon = self.clone();
+                let logger = self.logger.clone();
+                let key = self.key.clone();
+                tokio::spawn(
+                    protocol::read_request(stream, key)
+                        .and_then(move |(stream, request)| {
+                            let response = daemon.process(request);
+                            protocol::write_response(stream, &response)
+                        })
+                        .map(|_| ())
+                        .map_err(move |error| {
+                            warn!(logger, "cannot answer the request"; "reason" => error.to_string())
+                        }),
+                );
+                Ok(())
+            });
+        tokio::run(server);
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn run(self, _socket: &Path) -> Result<(), io::Error> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            protocol::Error::Unsupported.to_string(),
+        ))
+    }
+
+    fn process(&self, request: Request) -> Response {
+        let result = match request {
+            Request::Schedules {
+                epoch,
+                ledger,
+                slot_start,
+                nb_slots,
+            } => self.schedules(epoch, &ledger, slot_start, nb_slots),
+            Request::BlockFinalize {
+                leader,
+                date,
+                parent,
+                chain_length,
+                fragments,
+            } => {
+                let event = LeaderEvent {
+                    id: leader,
+                    date: date.into(),
+                };
+                fragments
+                    .iter()
+                    .map(|bytes| protocol::deserialize(bytes))
+                    .collect::<Result<_, _>>()
+                    .map_err(|error| error.to_string())
+                    .and_then(|fragments| {
+                        let template = BlockTemplate {
+                            date: event.date,
+                            parent: parent.into_hash(),
+                            chain_length: chain_length.into(),
+                            fragments,
+                        };
+                        self.block_finalize(template, event)
+                    })
+            }
+        };
+        result.unwrap_or_else(Response::Error)
+    }
+
+    fn schedules(
+        &self,
+        epoch: Epoch,
+        ledger: &[u8],
+        slot_start: u32,
+        nb_slots: u32,
+    ) -> Result<Response, String> {
+        let ledger: Ledger = protocol::deserialize(ledger).map_err(|error| error.to_string())?;
+        let leadership = Arc::new(Leadership::new(epoch, &ledger));
+
+        let events = self
+            .enclave
+            .leadership_evaluate(&leadership, slot_start, nb_slots);
+        debug!(self.logger, "leadership schedule evaluated";
+            "epoch" => epoch,
+            "events" => events.len());
+
+        let mut leaderships = self.leaderships.lock().unwrap();
+        leaderships.insert(epoch, leadership);
+        while leaderships.len() > LEADERSHIPS_KEPT {
+            let oldest = *leaderships.keys().next().unwrap();
+            leaderships.remove(&oldest);
+        }
+
+        Ok(Response::Schedules(
+            events
+                .into_iter()
+                .map(|event| (event.id, event.date.into()))
+                .collect(),
+        ))
+    }
+
+    fn block_finalize(
+        &self,
+        template: BlockTemplate,
+        event: LeaderEvent,
+    ) -> Result<Response, String> {
+        let leadership = self
+            .leaderships
+            .lock()
+            .unwrap()
+            .get(&event.date.epoch)
+            .cloned()
+            .ok_or_else(|| format!("no leadership schedule for epoch {}", event.date.epoch))?;
+
+        // the lock is held until the block is signed so the same date
+        // cannot be signed twice by concurrent requests
+        let mut last_signed = self.last_signed.lock().unwrap();
+        if let Some(last_date) = last_signed.get(&event.id) {
+            if event.date <= *last_date {
+                return Err(format!(
+                    "leader {} has already signed a block at {}, refusing to sign at {}",
+                    event.id, last_date, event.date
+                ));
+            }
+        }
+        let block = self
+            .enclave
+            .create_block(&leadership, template, event)
+            .ok_or_else(|| format!("leader {} is not elected at {}", event.id, event.date))?;
+        last_signed.insert(event.id, event.date);
+        debug!(self.logger, "block signed";
+            "leader" => event.id.to_string(),
+            "date" => event.date.to_string());
+
+        protocol::serialize(&block)
+            .map(Response::Block)
+            .map_err(|error| error.to_string())
+    }
+}
@@ -1,4 +1,4 @@
-use crate::blockcfg::{BlockBuilder, BlockDate};
+use crate::blockcfg::{BlockBuilder, BlockDate, ChainLength, Fragment, HeaderHash};
 use chain_impl_mockchain::block::Block;
 use chain_impl_mockchain::leadership::{Leader, LeaderOutput, Leadership};
 use jormungandr_lib::interfaces::EnclaveLeaderId as LeaderId;
@@ -10,10 +10,23 @@ pub struct Enclave {
     leaders: Arc<RwLock<BTreeMap<LeaderId, Leader>>>,
 }
 
+/// a leader of the enclave elected to create the block at the given date
+///
+/// The proof of the election is not kept: it is computed again by the
+/// enclave when signing the block so it never leaves the enclave.
+#[derive(Clone, Copy)]
 pub struct LeaderEvent {
     pub id: LeaderId,
     pub date: BlockDate,
-    pub output: LeaderOutput,
+}
+
+/// the content of a block to sign
+#[derive(Clone)]
+pub struct BlockTemplate {
+    pub date: BlockDate,
+    pub parent: HeaderHash,
+    pub chain_length: ChainLength,
+    pub fragments: Vec<Fragment>,
 }
 
 fn get_maximum_id<A>(leaders: &BTreeMap<LeaderId, A>) -> LeaderId {
@@ -73,10 +86,9 @@ impl Enclave {
             let date = leadership.date_at_slot(slot);
             match leadership.is_leader_for_date(&leader, date) {
                 Ok(LeaderOutput::None) => None,
-                Ok(leader_output) => Some(LeaderEvent {
+                Ok(_) => Some(LeaderEvent {
                     id: *leader_id,
                     date: date,
-                    output: leader_output,
                 }),
                 Err(_) => {
                     // For now silently ignore error
@@ -103,10 +115,9 @@ impl Enclave {
             for (id, leader) in leaders.iter() {
                 match leadership.is_leader_for_date(&leader, date) {
                     Ok(LeaderOutput::None) => (),
-                    Ok(leader_output) => output.push(LeaderEvent {
+                    Ok(_) => output.push(LeaderEvent {
                         id: *id,
                         date: date,
-                        output: leader_output,
                     }),
                     Err(_) => {
                         // For now silently ignore error
@@ -117,11 +128,21 @@ impl Enclave {
         output
     }
 
-    pub fn create_block(&self, block: BlockBuilder, event: LeaderEvent) -> Option<Block> {
+    /// sign the block of the given leader event, returns `None` if the
+    /// leader is not in the enclave or is not elected at the date of the
+    /// event with the given leadership.
+    pub fn create_block(
+        &self,
+        leadership: &Leadership,
+        template: BlockTemplate,
+        event: LeaderEvent,
+    ) -> Option<Block> {
         let leaders = self.leaders.read().unwrap();
         let leader = leaders.get(&event.id)?;
-        let block = match event.output {
-            LeaderOutput::None => unreachable!("Output::None are supposed to be filtered out"),
+        let output = leadership.is_leader_for_date(leader, event.date).ok()?;
+        let block = template.into_builder();
+        let block = match output {
+            LeaderOutput::None => return None,
             LeaderOutput::Bft(_) => {
                 if let Some(ref leader) = &leader.bft_leader {
                     block.make_bft_block(&leader.sig_key)
@@ -144,3 +165,17 @@ impl Enclave {
         Some(block)
     }
 }
+
+impl BlockTemplate {
+    pub fn into_builder(self) -> BlockBuilder {
+        let mut builder = BlockBuilder::new();
+        for fragment in self.fragments {
+            builder.message(fragment);
+        }
+        builder
+            .date(self.date)
+            .parent(self.parent)
+            .chain_length(self.chain_length);
+        builder
+    }
+}
@@ -1,13 +1,19 @@
+use self::passphrase::Passphrase;
 use chain_crypto::{Blake2b256, Curve25519_2HashDH, Ed25519, PublicKey, SumEd25519_12};
 use chain_impl_mockchain::leadership::{BftLeader, GenesisLeader};
 use jormungandr_lib::crypto::{
+    encryption,
     hash::Hash,
     key::{Identifier, SigningKey},
 };
 use serde::Deserialize;
-use std::path::Path;
+use std::{fs, path::Path};
 
+pub mod daemon;
 pub mod enclave;
+pub mod passphrase;
+pub mod protocol;
+pub mod reload;
 
 /// hold the node's bft secret setting
 #[derive(Clone, Deserialize)]
@@ -58,12 +64,31 @@ pub struct NodePublic {
 custom_error! {pub NodeSecretFromFileError
     Io { source: std::io::Error } = "Cannot read node's secrets: {source}",
     Format { source: serde_yaml::Error } = "Invalid Node secret file: {source}",
+    Passphrase { source: passphrase::Error } = "Cannot get the passphrase of the node's secrets: {source}",
+    Decryption { source: encryption::Error } = "Cannot decrypt the node's secrets: {source}",
 }
 
 impl NodeSecret {
-    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<NodeSecret, NodeSecretFromFileError> {
-        let file = std::fs::File::open(path)?;
-        Ok(serde_yaml::from_reader(file)?)
+    pub fn load_from_file<P: AsRef<Path>>(
+        path: P,
+        passphrase: &mut Passphrase,
+    ) -> Result<NodeSecret, NodeSecretFromFileError> {
+        let content = fs::read(path)?;
+        NodeSecret::from_slice(&content, passphrase)
+    }
+
+    /// parse the content of a secret file, the passphrase is only read
+    /// if the content is encrypted
+    pub fn from_slice(
+        content: &[u8],
+        passphrase: &mut Passphrase,
+    ) -> Result<NodeSecret, NodeSecretFromFileError> {
+        if encryption::is_encrypted(content) {
+            let content = encryption::decrypt(passphrase.get()?, content)?;
+            Ok(serde_yaml::from_slice(&content)?)
+        } else {
+            Ok(serde_yaml::from_slice(content)?)
+        }
     }
 
     pub fn bft(&self) -> Option<BftLeader> {
@@ -0,0 +1,114 @@
+//! passphrase of the encrypted secret files
+//!
+//! The same passphrase unlocks all the encrypted secret files of the
+//! node. It is read from its source the first time an encrypted secret
+//! file is loaded and kept for the next ones, the environment variable
+//! holding it is then removed.
+
+use std::{env, io};
+
+custom_error! {pub Error
+    Env { name: String } = "the environment variable `{name}' holding the passphrase is not set",
+    Io { source: io::Error } = "cannot read the passphrase: {source}",
+    PromptDisabled = "the passphrase has not been given when the node started",
+    Unsupported = "reading the passphrase from a file descriptor is only supported on Unix platforms",
+}
+
+/// where to read the passphrase from
+#[derive(Clone, Debug)]
+pub enum PassphraseSource {
+    /// the environment variable of the given name
+    Env(String),
+    /// the given file descriptor, read until the end of file
+    Fd(i32),
+    /// prompt the user on the terminal
+    Prompt,
+}
+
+pub struct Passphrase {
+    source: PassphraseSource,
+    prompt_enabled: bool,
+    passphrase: Option<Vec<u8>>,
+}
+
+impl Passphrase {
+    pub fn new(source: PassphraseSource) -> Self {
+        Passphrase {
+            source,
+            prompt_enabled: true,
+            passphrase: None,
+        }
+    }
+
+    /// do not prompt the user for the passphrase anymore, once the node
+    /// runs in the background
+    pub fn disable_prompt(&mut self) {
+        self.prompt_enabled = false;
+    }
+
+    /// get the passphrase, it is read from its source the first time only
+    pub fn get(&mut self) -> Result<&[u8], Error> {
+        if self.passphrase.is_none() {
+            self.passphrase = Some(self.read()?);
+        }
+        Ok(self.passphrase.as_ref().unwrap())
+    }
+
+    fn read(&self) -> Result<Vec<u8>, Error> {
+        match &self.source {
+            PassphraseSource::Env(name) => {
+                let passphrase = env::var_os(name)
+                    .map(into_bytes)
+                    .ok_or_else(|| Error::Env { name: name.clone() })?;
+                // the processes spawned by the node must not inherit it
+                env::remove_var(name);
+                Ok(passphrase)
+            }
+            PassphraseSource::Fd(fd) => read_fd(*fd).map(trim_end_of_line),
+            PassphraseSource::Prompt if self.prompt_enabled => {
+                let passphrase =
+                    rpassword::read_password_from_tty(Some("passphrase of the secret files: "))?;
+                Ok(passphrase.into_bytes())
+            }
+            PassphraseSource::Prompt => Err(Error::PromptDisabled),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn read_fd(fd: i32) -> Result<Vec<u8>, Error> {
+    use std::{fs::File, io::Read as _, os::unix::io::FromRawFd as _};
+
+    // the file descriptor is closed once the passphrase has been read
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    let mut passphrase = Vec::new();
+    file.read_to_end(&mut passphrase)?;
+    Ok(passphrase)
+}
+
+#[cfg(not(unix))]
+fn read_fd(_fd: i32) -> Result<Vec<u8>, Error> {
+    Err(Error::Unsupported)
+}
+
+#[cfg(unix)]
+fn into_bytes(value: std::ffi::OsString) -> Vec<u8> {
+    use std::os::unix::ffi::OsStringExt as _;
+    value.into_vec()
+}
+
+#[cfg(not(unix))]
+fn into_bytes(value: std::ffi::OsString) -> Vec<u8> {
+    value.to_string_lossy().into_owned().into_bytes()
+}
+
+/// remove the end of line following the passphrase, if any
+fn trim_end_of_line(mut passphrase: Vec<u8>) -> Vec<u8> {
+    if passphrase.ends_with(b"\n") {
+        passphrase.pop();
+        if passphrase.ends_with(b"\r") {
+            passphrase.pop();
+        }
+    }
+    passphrase
+}
@@ -0,0 +1,418 @@
+//! protocol between the node and an external enclave
+//!
+//! The enclave daemon holds the leader keys and listens on a Unix-domain
+//! socket. The node connects to it to query the leadership schedules and
+//! to have its blocks signed, the signing keys never leave the daemon.
+//!
+//! The node and the enclave share a secret key. Upon connection they prove
+//! to each other that they know the key:
+//!
+//! 1. the node sends a random nonce;
+//! 2. the enclave replies with its own random nonce and the hash of the
+//!    key, of its role and of both nonces;
+//! 3. the node checks the hash and sends the hash of the key, of its own
+//!    role and of both nonces, followed by its request;
+//! 4. the enclave checks the hash of the node before reading the request,
+//!    then replies with the response and closes the connection.
+//!
+//! Every message is encoded with bincode and prefixed by its length in
+//! bytes (u32, big endian).
+
+use crate::{
+    blockcfg::{Block, Epoch, Ledger},
+    secure::enclave::{BlockTemplate, LeaderEvent},
+};
+use chain_crypto::Blake2b256;
+use jormungandr_lib::{
+    crypto::hash::Hash,
+    interfaces::{BlockDate, EnclaveLeaderId as LeaderId},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{fs, io, path::Path, sync::Arc};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    prelude::*,
+};
+
+/// minimum size of the key shared by the node and the enclave, in bytes
+pub const MIN_KEY_SIZE: usize = 32;
+
+/// maximum size of a request or a response, it has to fit the ledger
+const MAX_MESSAGE_SIZE: usize = 1024 * 1024 * 1024;
+/// maximum size of the messages exchanged before the authentication
+const MAX_HANDSHAKE_SIZE: usize = 128;
+
+const NONCE_SIZE: usize = 32;
+const NODE_ROLE: &[u8] = b"node";
+const ENCLAVE_ROLE: &[u8] = b"enclave";
+
+type Nonce = [u8; NONCE_SIZE];
+type Proof = [u8; 32];
+
+custom_error! {pub Error
+    Io { source: io::Error } = "I/O error while communicating with the enclave",
+    Encoding { reason: String } = "cannot encode or decode the enclave message: {reason}",
+    MessageTooLarge { size: usize } = "the enclave message of {size} bytes is too large",
+    InvalidKey = "the enclave key must be at least 32 bytes long",
+    AuthenticationFailed = "the enclave does not know the enclave key",
+    UnexpectedResponse = "unexpected response from the enclave",
+    Enclave { reason: String } = "the enclave failed to process the request: {reason}",
+    Unsupported = "external enclaves are only supported on Unix platforms",
+}
+
+/// the secret shared by the node and the enclave daemon
+#[derive(Clone)]
+pub struct EnclaveKey(Arc<Vec<u8>>);
+
+#[derive(Serialize, Deserialize)]
+struct NodeHello {
+    nonce: Nonce,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EnclaveHello {
+    nonce: Nonce,
+    proof: Proof,
+}
+
+#[derive(Serialize, Deserialize)]
+struct NodeProof {
+    proof: Proof,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum Request {
+    /// evaluate the leadership schedule of the leaders of the enclave,
+    /// the leadership of the epoch is computed from the serialized ledger
+    Schedules {
+        epoch: Epoch,
+        ledger: Vec<u8>,
+        slot_start: u32,
+        nb_slots: u32,
+    },
+    /// sign the block of the leader event, the leadership of the epoch
+    /// of the block must have been sent with a `Schedules` request
+    BlockFinalize {
+        leader: LeaderId,
+        date: BlockDate,
+        parent: Hash,
+        chain_length: u32,
+        fragments: Vec<Vec<u8>>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum Response {
+    Schedules(Vec<(LeaderId, BlockDate)>),
+    Block(Vec<u8>),
+    Error(String),
+}
+
+impl EnclaveKey {
+    /// load the key from the given file, the whitespaces around the key
+    /// are ignored
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let content = fs::read(path)?;
+        let start = content.iter().position(|byte| !byte.is_ascii_whitespace());
+        let end = content.iter().rposition(|byte| !byte.is_ascii_whitespace());
+        let key = match (start, end) {
+            (Some(start), Some(end)) => content[start..=end].to_vec(),
+            _ => Vec::new(),
+        };
+        if key.len() < MIN_KEY_SIZE {
+            return Err(Error::InvalidKey);
+        }
+        Ok(EnclaveKey(Arc::new(key)))
+    }
+
+    fn proof(&self, role: &[u8], node_nonce: &Nonce, enclave_nonce: &Nonce) -> Proof {
+        let mut data = Vec::with_capacity(self.0.len() + role.len() + 2 * NONCE_SIZE);
+        data.extend_from_slice(&self.0);
+        data.extend_from_slice(role);
+        data.extend_from_slice(node_nonce);
+        data.extend_from_slice(enclave_nonce);
+        Blake2b256::new(&data).into()
+    }
+}
+
+impl Request {
+    pub fn schedules(
+        epoch: Epoch,
+        ledger: &Ledger,
+        slot_start: u32,
+        nb_slots: u32,
+    ) -> Result<Self, Error> {
+        Ok(Request::Schedules {
+            epoch,
+            ledger: serialize(ledger)?,
+            slot_start,
+            nb_slots,
+        })
+    }
+
+    pub fn block_finalize(template: &BlockTemplate, event: LeaderEvent) -> Result<Self, Error> {
+        let fragments = template
+            .fragments
+            .iter()
+            .map(serialize)
+            .collect::<Result<_, _>>()?;
+        Ok(Request::BlockFinalize {
+            leader: event.id,
+            date: event.date.into(),
+            parent: template.parent.clone().into(),
+            chain_length: template.chain_length.into(),
+            fragments,
+        })
+    }
+}
+
+impl Response {
+    pub fn into_schedules(self) -> Result<Vec<LeaderEvent>, Error> {
+        match self {
+            Response::Schedules(events) => Ok(events
+                .into_iter()
+                .map(|(id, date)| LeaderEvent {
+                    id,
+                    date: date.into(),
+                })
+                .collect()),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    pub fn into_block(self) -> Result<Block, Error> {
+        match self {
+            Response::Block(bytes) => deserialize(&bytes),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+}
+
+/// send the request to the enclave listening at the given socket
+#[cfg(unix)]
+pub fn query<P: AsRef<Path>>(
+    socket: P,
+    key: EnclaveKey,
+    request: Request,
+) -> impl Future<Item = Response, Error = Error> {
+    tokio::net::UnixStream::connect(socket)
+        .map_err(Error::from)
+        .and_then(move |stream| node_handshake(stream, key))
+        .and_then(move |stream| write_message(stream, &request))
+        .and_then(|stream| read_message(stream, MAX_MESSAGE_SIZE))
+        .and_then(|(_, response)| match response {
+            Response::Error(reason) => Err(Error::Enclave { reason }),
+            response => Ok(response),
+        })
+}
+
+#[cfg(not(unix))]
+pub fn query<P: AsRef<Path>>(
+    _socket: P,
+    _key: EnclaveKey,
+    _request: Request,
+) -> impl Future<Item = Response, Error = Error> {
+    future::err(Error::Unsupported)
+}
+
+/// authenticate the enclave, and the node to the enclave
+#[cfg(unix)]
+fn node_handshake<S>(stream: S, key: EnclaveKey) -> impl Future<Item = S, Error = Error>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    let node_nonce: Nonce = rand::random();
+    write_message(stream, &NodeHello { nonce: node_nonce })
+        .and_then(|stream| read_message(stream, MAX_HANDSHAKE_SIZE))
+        .and_then(move |(stream, hello): (S, EnclaveHello)| {
+            let expected = key.proof(ENCLAVE_ROLE, &node_nonce, &hello.nonce);
+            if !constant_time_eq(&expected, &hello.proof) {
+                return future::Either::A(future::err(Error::AuthenticationFailed));
+            }
+            let proof = key.proof(NODE_ROLE, &node_nonce, &hello.nonce);
+            future::Either::B(write_message(stream, &NodeProof { proof }))
+        })
+}
+
+/// authenticate the node, and the enclave to the node, then read the
+/// request of the node
+pub fn read_request<S>(
+    stream: S,
+    key: EnclaveKey,
+) -> impl Future<Item = (S, Request), Error = Error>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    let enclave_nonce: Nonce = rand::random();
+    read_message(stream, MAX_HANDSHAKE_SIZE)
+        .and_then(move |(stream, hello): (S, NodeHello)| {
+            let node_nonce = hello.nonce;
+            let proof = key.proof(ENCLAVE_ROLE, &node_nonce, &enclave_nonce);
+            write_message(
+                stream,
+                &EnclaveHello {
+                    nonce: enclave_nonce,
+                    proof,
+                },
+            )
+            .and_then(|stream| read_message(stream, MAX_HANDSHAKE_SIZE))
+            .and_then(move |(stream, node_proof): (S, NodeProof)| {
+                let expected = key.proof(NODE_ROLE, &node_nonce, &enclave_nonce);
+                if constant_time_eq(&expected, &node_proof.proof) {
+                    Ok(stream)
+                } else {
+                    Err(Error::AuthenticationFailed)
+                }
+            })
+        })
+        .and_then(|stream| read_message(stream, MAX_MESSAGE_SIZE))
+}
+
+pub fn write_response<S>(stream: S, response: &Response) -> impl Future<Item = S, Error = Error>
+where
+    S: AsyncWrite,
+{
+    write_message(stream, response)
+}
+
+fn write_message<W, T>(writer: W, message: &T) -> impl Future<Item = W, Error = Error>
+where
+    W: AsyncWrite,
+    T: Serialize,
+{
+    future::result(encode_message(message)).and_then(move |frame| {
+        tokio::io::write_all(writer, frame)
+            .map(|(writer, _)| writer)
+            .map_err(Error::from)
+    })
+}
+
+fn read_message<R, T>(reader: R, max_size: usize) -> impl Future<Item = (R, T), Error = Error>
+where
+    R: AsyncRead,
+    T: DeserializeOwned,
+{
+    tokio::io::read_exact(reader, [0; 4])
+        .map_err(Error::from)
+        .and_then(move |(reader, size)| {
+            let size = u32::from_be_bytes(size) as usize;
+            if size > max_size {
+                future::Either::A(future::err(Error::MessageTooLarge { size }))
+            } else {
+                future::Either::B(tokio::io::read_exact(reader, vec![0; size]).map_err(Error::from))
+            }
+        })
+        .and_then(|(reader, bytes)| {
+            bincode::deserialize(&bytes)
+                .map(|message| (reader, message))
+                .map_err(|err| Error::Encoding {
+                    reason: err.to_string(),
+                })
+        })
+}
+
+fn encode_message<T: Serialize>(message: &T) -> Result<Vec<u8>, Error> {
+    let bytes = bincode::serialize(message).map_err(|err| Error::Encoding {
+        reason: err.to_string(),
+    })?;
+    if bytes.len() > MAX_MESSAGE_SIZE {
+        return Err(Error::MessageTooLarge { size: bytes.len() });
+    }
+    let mut frame = Vec::with_capacity(4 + bytes.len());
+    frame.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&bytes);
+    Ok(frame)
+}
+
+pub fn serialize<T: chain_core::property::Serialize>(value: &T) -> Result<Vec<u8>, Error> {
+    value.serialize_as_vec().map_err(|err| Error::Encoding {
+        reason: err.to_string(),
+    })
+}
+
+pub fn deserialize<T: chain_core::property::Deserialize>(bytes: &[u8]) -> Result<T, Error> {
+    T::deserialize(bytes).map_err(|err| Error::Encoding {
+        reason: err.to_string(),
+    })
+}
+
+/// compare the proofs in a time independent of their content
+fn constant_time_eq(a: &Proof, b: &Proof) -> bool {
+    a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tokio::{net::UnixStream, runtime::current_thread::Runtime};
+
+    fn key(byte: u8) -> EnclaveKey {
+        EnclaveKey(Arc::new(vec![byte; MIN_KEY_SIZE]))
+    }
+
+    fn request() -> Request {
+        Request::Schedules {
+            epoch: 1,
+            ledger: vec![0, 1, 2, 3],
+            slot_start: 0,
+            nb_slots: 10,
+        }
+    }
+
+    /// run the enclave side and the node side of the given connection
+    /// to completion, whatever their results
+    fn run<E, N>(enclave: E, node: N) -> (Result<E::Item, Error>, Result<N::Item, Error>)
+    where
+        E: Future<Error = Error>,
+        N: Future<Error = Error>,
+    {
+        let mut runtime = Runtime::new().unwrap();
+        runtime
+            .block_on(enclave.then(Ok::<_, ()>).join(node.then(Ok::<_, ()>)))
+            .unwrap()
+    }
+
+    #[test]
+    fn read_the_request_of_an_authenticated_node() {
+        let (enclave_stream, node_stream) = UnixStream::pair().unwrap();
+        let enclave = read_request(enclave_stream, key(1));
+        let node = node_handshake(node_stream, key(1))
+            .and_then(|stream| write_message(stream, &request()));
+
+        match run(enclave, node) {
+            (Ok((_, Request::Schedules { epoch, ledger, .. })), Ok(_)) => {
+                assert_eq!(epoch, 1);
+                assert_eq!(ledger, vec![0, 1, 2, 3]);
+            }
+            _ => panic!("the request has not been read"),
+        }
+    }
+
+    #[test]
+    fn refuse_a_node_with_another_key() {
+        let (enclave_stream, node_stream) = UnixStream::pair().unwrap();
+        let enclave = read_request(enclave_stream, key(1));
+        let node = node_handshake(node_stream, key(2))
+            .and_then(|stream| write_message(stream, &request()));
+
+        match run(enclave, node) {
+            (Err(_), Err(Error::AuthenticationFailed)) => {}
+            _ => panic!("authenticated with another key"),
+        }
+    }
+
+    #[test]
+    fn refuse_an_oversize_handshake() {
+        let (enclave_stream, node_stream) = UnixStream::pair().unwrap();
+        let enclave = read_request(enclave_stream, key(1));
+        let size = MAX_HANDSHAKE_SIZE as u32 + 1;
+        let node = tokio::io::write_all(node_stream, size.to_be_bytes()).map_err(Error::from);
+
+        match run(enclave, node) {
+            (Err(Error::MessageTooLarge { size: read }), Ok(_)) => {
+                assert_eq!(read, size as usize)
+            }
+            _ => panic!("read an oversize handshake"),
+        }
+    }
+}
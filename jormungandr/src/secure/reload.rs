@@ -0,0 +1,169 @@
+//! reload of the leader secret files
+//!
+//! The node keeps track of the leader it added to the enclave for every
+//! secret file. The files are checked periodically:
+//!
+//! * a new secret file adds its leader to the enclave;
+//! * a modified secret file replaces the leader of its previous content;
+//! * the leader of a deleted secret file is removed from the enclave.
+//!
+//! The leaders added with the REST API are left untouched. The passphrase
+//! of the encrypted secret files is only prompted for when the node starts.
+
+use crate::{
+    leadership::LeadersChanged,
+    secure::{enclave::Enclave, passphrase::Passphrase, NodeSecret, NodeSecretFromFileError},
+    utils::async_msg::MessageBox,
+};
+use chain_crypto::Blake2b256;
+use chain_impl_mockchain::leadership::Leader;
+use jormungandr_lib::interfaces::EnclaveLeaderId as LeaderId;
+use slog::Logger;
+use std::{collections::BTreeMap, fs, io, path::PathBuf, time::Duration};
+use tokio::{prelude::*, timer::Interval};
+
+/// the secret files and the leaders loaded from them
+pub struct SecretFiles {
+    files: Vec<PathBuf>,
+    passphrase: Passphrase,
+    loaded: BTreeMap<PathBuf, LoadedSecret>,
+}
+
+struct LoadedSecret {
+    digest: Blake2b256,
+    leader_id: LeaderId,
+}
+
+impl SecretFiles {
+    pub fn new(files: Vec<PathBuf>, passphrase: Passphrase) -> Self {
+        SecretFiles {
+            files,
+            passphrase,
+            loaded: BTreeMap::new(),
+        }
+    }
+
+    /// load the leaders of all the secret files in the enclave
+    pub fn load(&mut self, enclave: &Enclave) -> Result<(), NodeSecretFromFileError> {
+        for path in &self.files {
+            let content = fs::read(path)?;
+            let leader = parse_leader(&content, &mut self.passphrase)?;
+            let leader_id = enclave.add_leader(leader);
+            self.loaded.insert(
+                path.clone(),
+                LoadedSecret {
+                    digest: Blake2b256::new(&content),
+                    leader_id,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// load again the secret files which changed since they were last
+    /// loaded, a file which cannot be loaded keeps its previous leader.
+    /// Returns `true` if the leaders of the enclave changed.
+    pub fn reload(&mut self, enclave: &Enclave, logger: &Logger) -> bool {
+        let mut changed = false;
+        for path in &self.files {
+            let content = match fs::read(path) {
+                Err(ref error) if error.kind() == io::ErrorKind::NotFound => {
+                    if let Some(loaded) = self.loaded.remove(path) {
+                        enclave.remove_leader(loaded.leader_id);
+                        info!(logger, "secret file deleted, leader removed";
+                            "path" => path.display().to_string(),
+                            "leader" => loaded.leader_id.to_string());
+                        changed = true;
+                    }
+                    continue;
+                }
+                Err(error) => {
+                    warn!(logger, "cannot read the secret file";
+                        "path" => path.display().to_string(),
+                        "reason" => error.to_string());
+                    continue;
+                }
+                Ok(content) => content,
+            };
+
+            let digest = Blake2b256::new(&content);
+            if let Some(loaded) = self.loaded.get(path) {
+                if loaded.digest == digest {
+                    continue;
+                }
+            }
+            let leader = match parse_leader(&content, &mut self.passphrase) {
+                Err(error) => {
+                    warn!(logger, "cannot load the secret file, the leader is not updated";
+                        "path" => path.display().to_string(),
+                        "reason" => error.to_string());
+                    continue;
+                }
+                Ok(leader) => leader,
+            };
+
+            // the new leader is added before the previous one is removed so
+            // it does not take over the identifier of the previous leader
+            let leader_id = enclave.add_leader(leader);
+            let previous = self
+                .loaded
+                .insert(path.clone(), LoadedSecret { digest, leader_id });
+            match previous {
+                Some(previous) => {
+                    enclave.remove_leader(previous.leader_id);
+                    info!(logger, "secret file modified, leader replaced";
+                        "path" => path.display().to_string(),
+                        "previous leader" => previous.leader_id.to_string(),
+                        "leader" => leader_id.to_string());
+                }
+                None => {
+                    info!(logger, "secret file created, leader added";
+                        "path" => path.display().to_string(),
+                        "leader" => leader_id.to_string());
+                }
+            }
+            changed = true;
+        }
+        changed
+    }
+
+    /// check the secret files every `interval` and notify the leadership
+    /// module when the leaders of the enclave changed
+    pub fn watch(
+        mut self,
+        enclave: Enclave,
+        interval: Duration,
+        leaders_changed: MessageBox<LeadersChanged>,
+        logger: Logger,
+    ) -> impl Future<Item = (), Error = ()> {
+        self.passphrase.disable_prompt();
+        let error_logger = logger.clone();
+        Interval::new_interval(interval)
+            .map_err(move |error| {
+                error!(error_logger, "cannot check the secret files"; "reason" => error.to_string())
+            })
+            .fold(leaders_changed, move |leaders_changed, _instant| {
+                if self.reload(&enclave, &logger) {
+                    future::Either::A(leaders_changed.send(LeadersChanged).map_err(|_| ()))
+                } else {
+                    future::Either::B(future::ok(leaders_changed))
+                }
+            })
+            .map(|_| ())
+    }
+
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+}
+
+fn parse_leader(
+    content: &[u8],
+    passphrase: &mut Passphrase,
+) -> Result<Leader, NodeSecretFromFileError> {
+    let secret = NodeSecret::from_slice(content, passphrase)?;
+    Ok(Leader {
+        bft_leader: secret.bft(),
+        genesis_leader: secret.genesis(),
+    })
+}
@@ -6,7 +6,11 @@ use structopt::StructOpt;
 
 use crate::{
     blockcfg::HeaderHash,
-    settings::logging::{LogFormat, LogOutput},
+    secure::passphrase::PassphraseSource,
+    settings::{
+        logging::{LogFormat, LogOutput},
+        start::StorageBackend,
+    },
 };
 
 #[derive(StructOpt, Debug)]
@@ -44,14 +48,32 @@ pub struct StartArguments {
     pub storage: Option<PathBuf>,
 
     /// Set the node config (in YAML format) to use as general configuration
-    #[structopt(long = "config", parse(from_os_str))]
-    pub node_config: PathBuf,
+    #[structopt(
+        long = "config",
+        parse(from_os_str),
+        raw(required_unless = "\"enclave_listen\"")
+    )]
+    pub node_config: Option<PathBuf>,
 
     /// Set the secret node config (in YAML format). Can be given
     /// multiple times.
     #[structopt(long = "secret", parse(from_os_str))]
     pub secret: Vec<PathBuf>,
 
+    /// Name of the environment variable holding the passphrase of the
+    /// encrypted secret files. The passphrase is prompted for on the
+    /// terminal if neither this option nor `--secret-passphrase-fd` is given.
+    #[structopt(long = "secret-passphrase-env")]
+    pub secret_passphrase_env: Option<String>,
+
+    /// File descriptor to read the passphrase of the encrypted secret
+    /// files from, until the end of file.
+    #[structopt(
+        long = "secret-passphrase-fd",
+        raw(conflicts_with = "\"secret_passphrase_env\"")
+    )]
+    pub secret_passphrase_fd: Option<i32>,
+
     /// Path to the genesis block (the block0) of the blockchain
     #[structopt(long = "genesis-block", parse(try_from_str))]
     pub block_0_path: Option<PathBuf>,
@@ -61,6 +83,80 @@ pub struct StartArguments {
     /// or from the network.
     #[structopt(long = "genesis-block-hash", parse(try_from_str))]
     pub block_0_hash: Option<HeaderHash>,
+
+    /// Import the blocks of the given block archive file before starting
+    /// the node. Every block is validated before being applied.
+    #[structopt(long = "import-blocks", parse(from_os_str))]
+    pub import_blocks: Option<PathBuf>,
+
+    /// Export the blocks of the storage in the given block archive file
+    /// and exit. By default all the blocks from the genesis block to the
+    /// `HEAD` are exported.
+    #[structopt(
+        long = "export-blocks",
+        parse(from_os_str),
+        raw(conflicts_with = "\"import_blocks\"")
+    )]
+    pub export_blocks: Option<PathBuf>,
+
+    /// Hash of the first block to export (included), defaults to the
+    /// genesis block.
+    #[structopt(
+        long = "export-from",
+        parse(try_from_str),
+        raw(requires = "\"export_blocks\"")
+    )]
+    pub export_from: Option<HeaderHash>,
+
+    /// Hash of the last block to export (included), defaults to the
+    /// `HEAD` of the storage.
+    #[structopt(
+        long = "export-to",
+        parse(try_from_str),
+        raw(requires = "\"export_blocks\"")
+    )]
+    pub export_to: Option<HeaderHash>,
+
+    /// Verify the blocks of the storage, from the genesis block to the
+    /// `HEAD`, report the first inconsistency found and exit.
+    #[structopt(long = "verify-storage")]
+    pub verify_storage: bool,
+
+    /// Verify the blocks of the storage and rewind the `HEAD` to the last
+    /// good block if an inconsistency is found, then exit.
+    #[structopt(long = "repair-storage", raw(conflicts_with = "\"verify_storage\""))]
+    pub repair_storage: bool,
+
+    /// Copy the blocks of the storage, from the genesis block to the `HEAD`,
+    /// in the given storage backend ("sqlite" or "kv") and exit.
+    #[structopt(long = "migrate-storage", parse(try_from_str))]
+    pub migrate_storage: Option<StorageBackend>,
+
+    /// Run the enclave daemon instead of the node: hold the leader keys of
+    /// the `--secret` files and answer the requests of the node on the
+    /// Unix-domain socket at the given path.
+    #[structopt(
+        long = "enclave-listen",
+        parse(from_os_str),
+        raw(requires = "\"enclave_key\"")
+    )]
+    pub enclave_listen: Option<PathBuf>,
+
+    /// Path to the file holding the key shared by the enclave daemon and
+    /// the node, required by `--enclave-listen`.
+    #[structopt(long = "enclave-key", parse(from_os_str))]
+    pub enclave_key: Option<PathBuf>,
+}
+
+impl StartArguments {
+    /// where to read the passphrase of the encrypted secret files from
+    pub fn secret_passphrase(&self) -> PassphraseSource {
+        match (&self.secret_passphrase_env, self.secret_passphrase_fd) {
+            (Some(name), _) => PassphraseSource::Env(name.clone()),
+            (None, Some(fd)) => PassphraseSource::Fd(fd),
+            (None, None) => PassphraseSource::Prompt,
+        }
+    }
 }
 
 #[derive(StructOpt, Debug)]
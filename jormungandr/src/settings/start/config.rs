@@ -7,14 +7,14 @@ use jormungandr_lib::time::Duration;
 use poldercast;
 use serde::{de::Error as _, de::Visitor, Deserialize, Deserializer, Serialize, Serializer};
 use slog::FilterLevel;
-use std::{collections::BTreeMap, fmt, net::SocketAddr, path::PathBuf};
+use std::{collections::BTreeMap, fmt, net::SocketAddr, path::PathBuf, str::FromStr};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
     pub secret_files: Option<Vec<PathBuf>>,
     pub legacy_peers: Option<Vec<SocketAddr>>,
     pub grpc_peers: Option<Vec<SocketAddr>>,
-    pub storage: Option<PathBuf>,
+    pub storage: Option<Storage>,
     pub log: Option<ConfigLogSettings>,
 
     /// setting of the mempool, fragment logs and related data
@@ -24,10 +24,37 @@ pub struct Config {
     #[serde(default)]
     pub leadership: Leadership,
 
+    /// setting of the blockchain state persistence
+    #[serde(default)]
+    pub blockchain: Blockchain,
+
     pub rest: Option<Rest>,
     pub p2p: P2pConfig,
 }
 
+/// the storage of the blocks: either the path to the storage directory
+/// only, or the path and the storage backend to use.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Storage {
+    Path(PathBuf),
+    Settings {
+        path: PathBuf,
+        #[serde(default)]
+        backend: StorageBackend,
+    },
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+/// implementation of the block storage
+pub enum StorageBackend {
+    /// the blocks are stored in a SQLite database
+    Sqlite,
+    /// the blocks are stored in an embedded log-structured key-value store
+    Kv,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct ConfigLogSettings {
     #[serde(with = "filter_level_opt_serde")]
@@ -85,6 +112,24 @@ pub struct Leadership {
     pub log_ttl: Duration,
     /// interval between 2 garbage collection check logs
     pub garbage_collection_interval: Duration,
+    /// the external enclave holding the leader keys. If not set the leader
+    /// keys of the secret files are loaded in the node.
+    #[serde(default)]
+    pub enclave: Option<ExternalEnclave>,
+    /// interval between 2 checks of the secret files, the leaders of the
+    /// modified secret files are reloaded
+    #[serde(default = "default_secret_files_check_interval")]
+    pub secret_files_check_interval: Duration,
+}
+
+/// an enclave daemon holding the leader keys, reachable over a
+/// Unix-domain socket
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ExternalEnclave {
+    /// path to the socket the enclave daemon listens on
+    pub socket: PathBuf,
+    /// path to the file holding the key shared with the enclave daemon
+    pub key_file: PathBuf,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
@@ -97,6 +142,81 @@ pub struct Mempool {
     pub log_ttl: Duration,
     /// interval between 2 garbage collection check of the mempool and the log cache.
     pub garbage_collection_interval: Duration,
+    /// algorithm used to select the fragments of the blocks created by the node
+    #[serde(default)]
+    pub fragment_selection: FragmentSelection,
+    /// maximum number of fragments in a block created by the node
+    #[serde(default = "default_max_fragments_per_block")]
+    pub max_fragments_per_block: usize,
+    /// maximum cumulated size, in bytes, of the fragments of a block created by the node
+    #[serde(default = "default_max_block_content_size")]
+    pub max_block_content_size: usize,
+    /// maximum number of fragments in the mempool
+    #[serde(default = "default_pool_max_entries")]
+    pub pool_max_entries: usize,
+    /// maximum cumulated size, in bytes, of the fragments in the mempool
+    #[serde(default = "default_pool_max_size")]
+    pub pool_max_size: usize,
+    /// fragments to evict when the mempool is full
+    #[serde(default)]
+    pub eviction_policy: EvictionPolicy,
+    /// maximum number of fragments a peer or a REST client may send to the
+    /// node within `sender_quota_interval`
+    #[serde(default = "default_sender_quota")]
+    pub sender_quota: usize,
+    /// interval over which the number of fragments sent by a peer or a REST
+    /// client is accounted for
+    #[serde(default = "default_sender_quota_interval")]
+    pub sender_quota_interval: Duration,
+    /// number of invalid fragments, within `sender_quota_interval`, after
+    /// which the sender is temporarily banned
+    #[serde(default = "default_sender_ban_threshold")]
+    pub sender_ban_threshold: usize,
+    /// duration of the ban of a sender
+    #[serde(default = "default_sender_ban_duration")]
+    pub sender_ban_duration: Duration,
+    /// keep the fragments of the mempool and their logs across restarts, they
+    /// are written in the storage directory
+    #[serde(default)]
+    pub persistent: bool,
+    /// interval between 2 writes of the mempool in the storage directory, the
+    /// mempool is also written when the node shuts down
+    #[serde(default = "default_persistence_interval")]
+    pub persistence_interval: Duration,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+/// algorithm to select the fragments of the blocks created by the node
+pub enum FragmentSelection {
+    /// the fragments are selected in the order they were received
+    OldestFirst,
+    /// the fragments paying the highest fee per byte are selected first
+    FeePerByte,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+/// selection of the fragments to evict when the mempool is full
+pub enum EvictionPolicy {
+    /// the fragments paying the lowest fee per byte are evicted first
+    LowestFee,
+    /// the fragments are evicted in the order they were received
+    OldestFirst,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Blockchain {
+    /// number of blocks between 2 snapshots of the ledger state. The snapshots
+    /// are stored in the storage directory and allow the node to restart without
+    /// applying all the blocks since the block0. Set to 0 to disable the snapshots.
+    pub snapshot_interval: u32,
+    /// number of snapshots to keep in the storage directory.
+    pub snapshots_to_keep: usize,
+    /// interval between 2 garbage collections of the in memory states
+    /// of the blockchain.
+    #[serde(default = "default_blockchain_garbage_collection_interval")]
+    pub garbage_collection_interval: Duration,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -114,25 +234,149 @@ pub struct TrustedPeer {
     pub id: NodeId,
 }
 
+impl Storage {
+    pub fn path(&self) -> &PathBuf {
+        match self {
+            Storage::Path(path) => path,
+            Storage::Settings { path, .. } => path,
+        }
+    }
+
+    pub fn backend(&self) -> StorageBackend {
+        match self {
+            Storage::Path(_) => StorageBackend::default(),
+            Storage::Settings { backend, .. } => *backend,
+        }
+    }
+}
+
+impl Default for StorageBackend {
+    fn default() -> Self {
+        StorageBackend::Sqlite
+    }
+}
+
+impl fmt::Display for StorageBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            StorageBackend::Sqlite => "sqlite",
+            StorageBackend::Kv => "kv",
+        };
+        f.write_str(s)
+    }
+}
+
+impl FromStr for StorageBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &*s.trim().to_lowercase() {
+            "sqlite" => Ok(StorageBackend::Sqlite),
+            "kv" => Ok(StorageBackend::Kv),
+            other => Err(format!("unknown storage backend '{}'", other)),
+        }
+    }
+}
+
 impl Default for Mempool {
     fn default() -> Self {
         Mempool {
             fragment_ttl: Duration::new(30 * 60, 0),
             log_ttl: Duration::new(3600, 0),
             garbage_collection_interval: Duration::new(3600 / 4, 0),
+            fragment_selection: FragmentSelection::default(),
+            max_fragments_per_block: default_max_fragments_per_block(),
+            max_block_content_size: default_max_block_content_size(),
+            pool_max_entries: default_pool_max_entries(),
+            pool_max_size: default_pool_max_size(),
+            eviction_policy: EvictionPolicy::default(),
+            sender_quota: default_sender_quota(),
+            sender_quota_interval: default_sender_quota_interval(),
+            sender_ban_threshold: default_sender_ban_threshold(),
+            sender_ban_duration: default_sender_ban_duration(),
+            persistent: false,
+            persistence_interval: default_persistence_interval(),
         }
     }
 }
 
+fn default_secret_files_check_interval() -> Duration {
+    Duration::new(10, 0)
+}
+
+fn default_max_fragments_per_block() -> usize {
+    250
+}
+
+fn default_max_block_content_size() -> usize {
+    1024 * 1024
+}
+
+fn default_pool_max_entries() -> usize {
+    10_000
+}
+
+fn default_pool_max_size() -> usize {
+    64 * 1024 * 1024
+}
+
+fn default_sender_quota() -> usize {
+    1000
+}
+
+fn default_sender_quota_interval() -> Duration {
+    Duration::new(60, 0)
+}
+
+fn default_sender_ban_threshold() -> usize {
+    100
+}
+
+fn default_sender_ban_duration() -> Duration {
+    Duration::new(10 * 60, 0)
+}
+
+fn default_persistence_interval() -> Duration {
+    Duration::new(5 * 60, 0)
+}
+
+impl Default for FragmentSelection {
+    fn default() -> Self {
+        FragmentSelection::OldestFirst
+    }
+}
+
+impl Default for EvictionPolicy {
+    fn default() -> Self {
+        EvictionPolicy::LowestFee
+    }
+}
+
 impl Default for Leadership {
     fn default() -> Self {
         Leadership {
             log_ttl: Duration::new(3600, 0),
             garbage_collection_interval: Duration::new(3600 / 4, 0),
+            enclave: None,
+            secret_files_check_interval: default_secret_files_check_interval(),
         }
     }
 }
 
+impl Default for Blockchain {
+    fn default() -> Self {
+        Blockchain {
+            snapshot_interval: 1000,
+            snapshots_to_keep: 2,
+            garbage_collection_interval: default_blockchain_garbage_collection_interval(),
+        }
+    }
+}
+
+fn default_blockchain_garbage_collection_interval() -> Duration {
+    Duration::new(3600 / 4, 0)
+}
+
 impl Address {
     pub fn to_socketaddr(&self) -> Option<SocketAddr> {
         self.0.to_socketaddr()
@@ -1,10 +1,14 @@
 mod config;
 pub mod network;
 
-use self::config::{Config, Leadership, Mempool};
-pub use self::config::{Cors, Rest};
+use self::config::{Blockchain, Config, Leadership, Mempool};
+pub use self::config::{
+    Cors, EvictionPolicy, ExternalEnclave, FragmentSelection, Rest, StorageBackend,
+};
 use self::network::Protocol;
+use crate::blockcfg::HeaderHash;
 use crate::rest::Error as RestError;
+use crate::secure::passphrase::PassphraseSource;
 use crate::settings::logging::{self, LogFormat, LogOutput, LogSettings};
 use crate::settings::{command_arguments::*, Block0Info};
 use slog::{FilterLevel, Logger};
@@ -12,22 +16,56 @@ use slog::{FilterLevel, Logger};
 use std::{collections::BTreeMap, fs::File, path::PathBuf};
 
 custom_error! {pub Error
+   ExpectedConfig = "Cannot start the node without the `--config' node configuration file",
    ConfigIo { source: std::io::Error } = "Cannot read the node configuration file: {source}",
    Config { source: serde_yaml::Error } = "Error while parsing the node configuration file: {source}",
    Rest { source: RestError } = "The Rest configuration is invalid: {source}",
    ExpectedBlock0Info = "Cannot start the node without the information to retrieve the genesis block",
    TooMuchBlock0Info = "Use only `--genesis-block-hash' or `--genesis-block'",
+   StorageCheckWithoutStorage = "Cannot verify or repair the storage, no storage directory is set",
+   StorageMigrationWithoutStorage = "Cannot migrate the storage, no storage directory is set",
+   StorageMigrationToSameBackend = "Cannot migrate the storage to the backend it already uses",
+   SecretsWithExternalEnclave = "Cannot load the secret files in the node when the leader keys are held by an external enclave",
 }
 
 /// Overall Settings for node
 pub struct Settings {
     pub network: network::Configuration,
     pub storage: Option<PathBuf>,
+    pub storage_backend: StorageBackend,
     pub block_0: Block0Info,
     pub secrets: Vec<PathBuf>,
+    pub secret_passphrase: PassphraseSource,
     pub rest: Option<Rest>,
     pub mempool: Mempool,
     pub leadership: Leadership,
+    pub blockchain: Blockchain,
+    pub import_blocks: Option<PathBuf>,
+    pub export_blocks: Option<BlockExport>,
+    pub storage_check: Option<StorageCheck>,
+    /// copy the blocks of the storage in the given backend instead
+    /// of starting the node
+    pub storage_migration: Option<StorageBackend>,
+}
+
+/// offline check of the storage to run instead of starting the node
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StorageCheck {
+    /// report the first inconsistency
+    Verify,
+    /// report the first inconsistency and rewind the `HEAD` to the
+    /// last good block
+    Repair,
+}
+
+/// the blocks to export in a block archive
+#[derive(Clone)]
+pub struct BlockExport {
+    pub path: PathBuf,
+    /// first block to export, the genesis block if not set
+    pub from: Option<HeaderHash>,
+    /// last block to export, the `HEAD` if not set
+    pub to: Option<HeaderHash>,
 }
 
 pub struct RawSettings {
@@ -37,7 +75,12 @@ pub struct RawSettings {
 
 impl RawSettings {
     pub fn load(command_line: CommandLine) -> Result<Self, Error> {
-        let config_file = File::open(&command_line.start_arguments.node_config)?;
+        let config_path = command_line
+            .start_arguments
+            .node_config
+            .as_ref()
+            .ok_or(Error::ExpectedConfig)?;
+        let config_file = File::open(config_path)?;
         let config = serde_yaml::from_reader(config_file)?;
         Ok(Self {
             command_line,
@@ -88,9 +131,14 @@ impl RawSettings {
         let command_arguments = &command_line.start_arguments;
         let network = generate_network(&command_arguments, &config);
 
+        let storage_backend = config
+            .storage
+            .as_ref()
+            .map(|storage| storage.backend())
+            .unwrap_or_default();
         let storage = match (command_arguments.storage.as_ref(), config.storage) {
             (Some(path), _) => Some(path.clone()),
-            (None, Some(path)) => Some(path.clone()),
+            (None, Some(storage)) => Some(storage.path().clone()),
             (None, None) => None,
         };
 
@@ -99,11 +147,13 @@ impl RawSettings {
             secrets.extend(secret_files);
         }
 
-        if secrets.is_empty() {
-            warn!(
+        match (secrets.is_empty(), &config.leadership.enclave) {
+            (false, Some(_)) => return Err(Error::SecretsWithExternalEnclave),
+            (true, None) => warn!(
                 logger,
                 "Node started without path to the stored secret keys"
-            );
+            ),
+            (_, _) => (),
         };
 
         let block0_info = match (
@@ -116,14 +166,51 @@ impl RawSettings {
             (None, Some(hash)) => Block0Info::Hash(hash.clone()),
         };
 
+        let export_blocks = command_arguments
+            .export_blocks
+            .as_ref()
+            .map(|path| BlockExport {
+                path: path.clone(),
+                from: command_arguments.export_from.clone(),
+                to: command_arguments.export_to.clone(),
+            });
+
+        let storage_check = if command_arguments.repair_storage {
+            Some(StorageCheck::Repair)
+        } else if command_arguments.verify_storage {
+            Some(StorageCheck::Verify)
+        } else {
+            None
+        };
+        if storage_check.is_some() && storage.is_none() {
+            return Err(Error::StorageCheckWithoutStorage);
+        }
+
+        let storage_migration = command_arguments.migrate_storage;
+        if let Some(target) = storage_migration {
+            if storage.is_none() {
+                return Err(Error::StorageMigrationWithoutStorage);
+            }
+            if target == storage_backend {
+                return Err(Error::StorageMigrationToSameBackend);
+            }
+        }
+
         Ok(Settings {
             storage: storage,
+            storage_backend,
             block_0: block0_info,
             network: network,
             secrets,
+            secret_passphrase: command_arguments.secret_passphrase(),
             rest: config.rest,
             mempool: config.mempool,
             leadership: config.leadership,
+            blockchain: config.blockchain,
+            import_blocks: command_arguments.import_blocks.clone(),
+            export_blocks,
+            storage_check,
+            storage_migration,
         })
     }
 }
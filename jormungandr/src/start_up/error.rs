@@ -1,13 +1,17 @@
 use crate::{
     blockcfg, blockchain, network, secure,
-    settings::{self, logging},
+    settings::{self, logging, start::StorageBackend},
 };
 use chain_storage::error::Error as StorageError;
 use std::io;
 
 custom_error! {pub ErrorKind
    SQLite = "SQLite file",
-   Block0 = "Block0"
+   Block0 = "Block0",
+   Snapshots = "ledger snapshots directory",
+   BlockArchive = "block archive file",
+   KvStore = "key-value block store directory",
+   EnclaveSocket = "enclave socket"
 }
 
 custom_error! {pub Error
@@ -21,7 +25,13 @@ custom_error! {pub Error
     FetchBlock0 { source: network::FetchBlockError } = "Error fetching the genesis block from the network",
     NetworkBootstrapError { source: network::BootstrapError } = "Error while loading the blockchain from the network",
     NodeSecrets { source: secure::NodeSecretFromFileError} = "Error while loading the node's secrets.",
+    EnclaveKey { source: secure::protocol::Error } = "Error while loading the enclave key",
     Block0InFuture = "Block 0 is set to start in the future",
+    BlockArchive { source: blockchain::ArchiveError } = "Error while importing or exporting the block archive",
+    KvStorage { source: blockchain::KvStoreError } = "Error while opening the key-value block store",
+    StorageMigration { source: blockchain::ArchiveError, target: StorageBackend } = "Error while copying the blocks to the {target} storage",
+    StorageCheck { source: blockchain::VerifyError } = "Error while verifying the storage",
+    StorageInconsistent { source: blockchain::StorageInconsistency } = "The storage is inconsistent",
 }
 
 impl Error {
@@ -33,12 +43,18 @@ impl Error {
             Error::IO { .. } => 3,
             Error::ParseError { .. } => 4,
             Error::StorageError { .. } => 5,
+            Error::KvStorage { .. } => 5,
             Error::Blockchain { .. } => 6,
             Error::Block0 { .. } => 7,
             Error::Block0InFuture => 7,
             Error::NodeSecrets { .. } => 8,
+            Error::EnclaveKey { .. } => 8,
             Error::FetchBlock0 { .. } => 9,
             Error::NetworkBootstrapError { .. } => 10,
+            Error::BlockArchive { .. } => 11,
+            Error::StorageCheck { .. } => 12,
+            Error::StorageInconsistent { .. } => 12,
+            Error::StorageMigration { .. } => 13,
         }
     }
 }
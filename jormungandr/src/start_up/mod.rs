@@ -2,16 +2,33 @@ mod error;
 
 pub use self::error::{Error, ErrorKind};
 use crate::{
-    blockcfg::{Block, Leadership},
-    blockchain::{Blockchain, Branch, ErrorKind as BlockchainError},
-    leadership::NewEpochToSchedule,
+    blockcfg::{Block, HeaderHash, Leader, Leadership},
+    blockchain::{
+        self, Blockchain, Branch, ErrorKind as BlockchainError, KvBlockStore, Snapshots, Storage,
+    },
+    fragment::PoolPersistence,
+    leadership::{self, NewEpochToSchedule},
     network,
-    settings::start::Settings,
+    secure::{
+        self,
+        daemon::EnclaveDaemon,
+        enclave::Enclave,
+        passphrase::{Passphrase, PassphraseSource},
+        protocol::EnclaveKey,
+        reload::SecretFiles,
+    },
+    settings::start::{BlockExport, Settings, StorageBackend, StorageCheck},
 };
 use chain_storage::{memory::MemoryBlockStore, store::BlockStore};
 use chain_storage_sqlite::SQLiteBlockStore;
 use slog::Logger;
-use std::{sync::Arc, time::Duration};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 use tokio::sync::mpsc;
 
 pub type NodeStorage = Box<dyn BlockStore<Block = Block> + Send + Sync>;
@@ -24,19 +41,198 @@ pub fn prepare_storage(setting: &Settings, logger: &Logger) -> Result<NodeStorag
             info!(logger, "storing blockchain in memory");
             Ok(Box::new(MemoryBlockStore::new()))
         }
-        Some(dir) => {
+        Some(dir) => open_storage(dir, setting.storage_backend, logger),
+    }
+}
+
+/// open the block storage of the given backend in the storage directory
+fn open_storage(
+    dir: &Path,
+    backend: StorageBackend,
+    logger: &Logger,
+) -> Result<NodeStorage, Error> {
+    match backend {
+        StorageBackend::Sqlite => {
             std::fs::create_dir_all(dir).map_err(|err| Error::IO {
                 source: err,
                 reason: ErrorKind::SQLite,
             })?;
-            let mut sqlite = dir.clone();
-            sqlite.push("blocks.sqlite");
+            let sqlite = dir.join("blocks.sqlite");
             info!(logger, "storing blockchain in '{:?}'", sqlite);
             Ok(Box::new(SQLiteBlockStore::new(sqlite)))
         }
+        StorageBackend::Kv => {
+            std::fs::create_dir_all(dir).map_err(|err| Error::IO {
+                source: err,
+                reason: ErrorKind::KvStore,
+            })?;
+            let kv = dir.join("blocks.kv");
+            info!(logger, "storing blockchain in '{:?}'", kv);
+            Ok(Box::new(KvBlockStore::open(kv)?))
+        }
+    }
+}
+
+/// copy the blocks of the storage, from the block0 to the `HEAD`, in the
+/// storage of the given backend.
+///
+pub fn migrate_storage(
+    setting: &Settings,
+    storage: NodeStorage,
+    block0_hash: HeaderHash,
+    target: StorageBackend,
+    logger: &Logger,
+) -> Result<(), Error> {
+    let dir = setting
+        .storage
+        .as_ref()
+        .expect("the storage migration requires a storage directory");
+    let mut target_storage = open_storage(dir, target, logger)?;
+
+    info!(
+        logger,
+        "copying blocks from the {} storage to the {} storage", setting.storage_backend, target
+    );
+    let copied = blockchain::copy_blocks(&Storage::new(storage), block0_hash, &mut target_storage)
+        .map_err(|source| Error::StorageMigration { source, target })?;
+    info!(
+        logger,
+        "{} blocks copied, set `storage.backend` to `{}` to use the new storage", copied, target
+    );
+    Ok(())
+}
+
+/// prepare the ledger snapshots from the given settings
+///
+/// The snapshots are stored in the `snapshots` directory of the storage.
+/// There are no snapshots if the blockchain is stored in memory or if the
+/// snapshot interval is set to 0.
+pub fn prepare_snapshots(setting: &Settings, logger: &Logger) -> Result<Option<Snapshots>, Error> {
+    let interval = setting.blockchain.snapshot_interval;
+    match &setting.storage {
+        Some(dir) if interval != 0 => {
+            let mut snapshots_dir = dir.clone();
+            snapshots_dir.push("snapshots");
+            info!(
+                logger,
+                "storing ledger snapshots every {} blocks in '{:?}'", interval, snapshots_dir
+            );
+            let snapshots = Snapshots::new(
+                snapshots_dir,
+                interval,
+                setting.blockchain.snapshots_to_keep,
+            )
+            .map_err(|err| Error::IO {
+                source: err,
+                reason: ErrorKind::Snapshots,
+            })?;
+            Ok(Some(snapshots))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// prepare the persistence of the fragment pool from the given settings
+///
+/// The fragment pool is stored in the `mempool` file of the storage. The
+/// fragment pool is not persisted if the blockchain is stored in memory.
+pub fn prepare_pool_persistence(setting: &Settings, logger: &Logger) -> Option<PoolPersistence> {
+    if !setting.mempool.persistent {
+        return None;
+    }
+    match &setting.storage {
+        Some(dir) => {
+            let path = dir.join("mempool");
+            info!(logger, "persisting the fragment pool in '{:?}'", path);
+            Some(PoolPersistence::new(
+                path,
+                setting.mempool.persistence_interval.into(),
+            ))
+        }
+        None => {
+            warn!(
+                logger,
+                "the fragment pool is not persisted, no storage directory is set"
+            );
+            None
+        }
+    }
+}
+
+/// load the leader keys of the given secret files, the passphrase of the
+/// encrypted ones is read from the given source
+pub fn load_leaders(
+    secrets: &[PathBuf],
+    passphrase_source: PassphraseSource,
+) -> Result<Vec<Leader>, Error> {
+    let mut passphrase = Passphrase::new(passphrase_source);
+    secrets
+        .iter()
+        .map(|secret_path| {
+            let secret =
+                secure::NodeSecret::load_from_file(secret_path.as_path(), &mut passphrase)?;
+            Ok(Leader {
+                bft_leader: secret.bft(),
+                genesis_leader: secret.genesis(),
+            })
+        })
+        .collect()
+}
+
+/// prepare the enclave from the given settings
+///
+/// The leader keys of the secret files are loaded in the node unless an
+/// external enclave holds them. Returns the enclave holding the keys in
+/// the node along with the secret files loaded in it, if any, and the
+/// client side of the enclave.
+pub fn prepare_enclave(
+    setting: &Settings,
+    logger: &Logger,
+) -> Result<(Option<(Enclave, SecretFiles)>, leadership::Enclave), Error> {
+    match &setting.leadership.enclave {
+        None => {
+            let enclave = Enclave::new();
+            let mut secret_files = SecretFiles::new(
+                setting.secrets.clone(),
+                Passphrase::new(setting.secret_passphrase.clone()),
+            );
+            secret_files.load(&enclave)?;
+            Ok((
+                Some((enclave.clone(), secret_files)),
+                leadership::Enclave::new(enclave),
+            ))
+        }
+        Some(external) => {
+            let key = EnclaveKey::load_from_file(&external.key_file)?;
+            info!(logger, "leader keys held by the external enclave";
+                "socket" => external.socket.display().to_string());
+            Ok((
+                None,
+                leadership::Enclave::remote(external.socket.clone(), key),
+            ))
+        }
     }
 }
 
+/// run the enclave daemon holding the leader keys of the given secret
+/// files, the node is not started.
+pub fn run_enclave_daemon(
+    socket: &Path,
+    key_file: &Path,
+    secrets: &[PathBuf],
+    passphrase_source: PassphraseSource,
+    logger: Logger,
+) -> Result<(), Error> {
+    let key = EnclaveKey::load_from_file(key_file)?;
+    let enclave = Enclave::from_vec(load_leaders(secrets, passphrase_source)?);
+    EnclaveDaemon::new(enclave, key, logger)
+        .run(socket)
+        .map_err(|source| Error::IO {
+            source,
+            reason: ErrorKind::EnclaveSocket,
+        })
+}
+
 /// loading the block 0 is not as trivial as it seems,
 /// there are different cases that we may encounter:
 ///
@@ -86,16 +282,20 @@ pub fn prepare_block_0(
 pub fn load_blockchain(
     block0: Block,
     storage: NodeStorage,
+    snapshots: Option<Snapshots>,
     epoch_event: mpsc::Sender<NewEpochToSchedule>,
     block_cache_ttl: Duration,
+    logger: &Logger,
 ) -> Result<(Blockchain, Branch), Error> {
     use tokio::prelude::*;
 
-    let mut blockchain = Blockchain::new(storage, block_cache_ttl);
+    let mut blockchain = Blockchain::new(storage, block_cache_ttl, snapshots);
 
     let main_branch: Branch = match blockchain.load_from_block0(block0.clone()).wait() {
         Err(error) => match error.kind() {
-            BlockchainError::Block0AlreadyInStorage => blockchain.load_from_storage(block0).wait(),
+            BlockchainError::Block0AlreadyInStorage => {
+                blockchain.load_from_storage(block0, logger.clone()).wait()
+            }
             _ => Err(error),
         },
         Ok(branch) => Ok(branch),
@@ -123,6 +323,7 @@ pub fn load_blockchain(
                 .send(NewEpochToSchedule {
                     new_schedule: Arc::new(new_schedule),
                     new_parameters: reference.epoch_ledger_parameters().clone(),
+                    epoch_ledger: current_known_state.clone(),
                     time_frame: reference.time_frame().as_ref().clone(),
                 })
                 .into_future()
@@ -132,3 +333,88 @@ pub fn load_blockchain(
 
     Ok((blockchain, main_branch))
 }
+
+/// validate and apply the blocks of the given block archive file
+///
+pub fn import_blocks(
+    path: &Path,
+    blockchain: Blockchain,
+    blockchain_tip: Branch,
+    logger: &Logger,
+) -> Result<(), Error> {
+    info!(logger, "importing blocks from '{:?}'", path);
+    let file = File::open(path).map_err(|err| Error::IO {
+        source: err,
+        reason: ErrorKind::BlockArchive,
+    })?;
+    let imported =
+        blockchain::import_blocks(blockchain, blockchain_tip, BufReader::new(file), logger)?;
+    info!(logger, "{} blocks imported", imported);
+    Ok(())
+}
+
+/// write the blocks of the storage in a block archive file
+///
+/// The range of blocks defaults to the block0 up to the `HEAD` of the storage.
+pub fn export_blocks(
+    export: &BlockExport,
+    storage: NodeStorage,
+    block0_hash: HeaderHash,
+    logger: &Logger,
+) -> Result<(), Error> {
+    info!(logger, "exporting blocks to '{:?}'", export.path);
+    let file = File::create(&export.path).map_err(|err| Error::IO {
+        source: err,
+        reason: ErrorKind::BlockArchive,
+    })?;
+    let exported = blockchain::export_blocks(
+        &Storage::new(storage),
+        export.from.unwrap_or(block0_hash),
+        export.to,
+        BufWriter::new(file),
+    )?;
+    info!(logger, "{} blocks exported", exported);
+    Ok(())
+}
+
+/// verify the blocks of the storage from the block0 to the `HEAD` and, if
+/// requested, rewind the `HEAD` to the last good block.
+///
+pub fn check_storage(
+    check: StorageCheck,
+    storage: NodeStorage,
+    block0: Block,
+    logger: &Logger,
+) -> Result<(), Error> {
+    let mut storage = Storage::new(storage);
+
+    info!(logger, "verifying the blocks of the storage");
+    let verification = blockchain::verify(&storage, block0, logger)?;
+    let inconsistency = match verification.inconsistency {
+        None => {
+            info!(
+                logger,
+                "storage is consistent, {} blocks verified", verification.verified;
+                "head" => verification.head.to_string()
+            );
+            return Ok(());
+        }
+        Some(ref inconsistency) => inconsistency.to_string(),
+    };
+    warn!(
+        logger,
+        "storage is inconsistent after {} blocks", verification.verified;
+        "head" => verification.head.to_string(),
+        "inconsistency" => inconsistency,
+        "last_good" => verification.last_good.map(|hash| hash.to_string()),
+    );
+
+    match check {
+        StorageCheck::Verify => Err(verification.inconsistency.unwrap().into()),
+        StorageCheck::Repair => {
+            let last_good = blockchain::rewind_head(&mut storage, verification)?;
+            info!(logger, "HEAD rewound to the last good block"; "head" => last_good.to_string());
+            Ok(())
+        }
+    }
+}
@@ -14,6 +14,10 @@ pub struct StatsCounter {
 struct StatsCounterImpl {
     tx_recv_cnt: AtomicUsize,
     block_recv_cnt: AtomicUsize,
+    gc_collected_cnt: AtomicUsize,
+    leader_block_cnt: AtomicUsize,
+    leader_skipped_cnt: AtomicUsize,
+    leader_failed_cnt: AtomicUsize,
     start_time: Instant,
     slot_start_time: AtomicU64,
 }
@@ -23,6 +27,10 @@ impl Default for StatsCounterImpl {
         Self {
             tx_recv_cnt: AtomicUsize::default(),
             block_recv_cnt: AtomicUsize::default(),
+            gc_collected_cnt: AtomicUsize::default(),
+            leader_block_cnt: AtomicUsize::default(),
+            leader_skipped_cnt: AtomicUsize::default(),
+            leader_failed_cnt: AtomicUsize::default(),
             start_time: Instant::now(),
             slot_start_time: AtomicU64::new(SLOT_START_TIME_UNDEFINED),
         }
@@ -48,6 +56,46 @@ impl StatsCounter {
         self.stats.block_recv_cnt.load(Ordering::Relaxed) as u64
     }
 
+    pub fn add_gc_collected_cnt(&self, count: usize) {
+        self.stats
+            .gc_collected_cnt
+            .fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn gc_collected_cnt(&self) -> u64 {
+        self.stats.gc_collected_cnt.load(Ordering::Relaxed) as u64
+    }
+
+    pub fn add_leader_block_cnt(&self, count: usize) {
+        self.stats
+            .leader_block_cnt
+            .fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn leader_block_cnt(&self) -> u64 {
+        self.stats.leader_block_cnt.load(Ordering::Relaxed) as u64
+    }
+
+    pub fn add_leader_skipped_cnt(&self, count: usize) {
+        self.stats
+            .leader_skipped_cnt
+            .fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn leader_skipped_cnt(&self) -> u64 {
+        self.stats.leader_skipped_cnt.load(Ordering::Relaxed) as u64
+    }
+
+    pub fn add_leader_failed_cnt(&self, count: usize) {
+        self.stats
+            .leader_failed_cnt
+            .fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn leader_failed_cnt(&self) -> u64 {
+        self.stats.leader_failed_cnt.load(Ordering::Relaxed) as u64
+    }
+
     pub fn uptime_sec(&self) -> u64 {
         self.stats.start_time.elapsed().as_secs()
     }